@@ -3,34 +3,50 @@ mod icons;
 mod login_page;
 mod main_page;
 
-pub enum App {
+pub struct App {
+    settings: main_page::Settings,
+    page: Page,
+}
+enum Page {
     Login(login_page::State),
     MainPage(main_page::State),
 }
 impl App {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self::Login(login_page::State::new())
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        // Для просмотра прикреплённых чеков
+        egui_extras::install_image_loaders(&cc.egui_ctx);
+        let settings = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+        Self {
+            settings,
+            page: Page::Login(login_page::State::new()),
+        }
     }
 }
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        match self {
-            Self::Login(page) => {
+        match &mut self.page {
+            Page::Login(page) => {
                 page.view(ctx);
                 if let login_page::Response::SuccessfulLogin(db) = page.drive() {
-                    *self = Self::MainPage(main_page::State::new(db));
+                    self.page = Page::MainPage(main_page::State::new(db));
                 }
             }
-            Self::MainPage(page) => {
-                if let main_page::Response::Exit = page.view(ctx) {
-                    *self = Self::Login(login_page::State::new())
+            Page::MainPage(page) => {
+                if let main_page::Response::Exit = page.view(ctx, &mut self.settings) {
+                    self.page = Page::Login(login_page::State::new());
                 } else {
                     page.drive();
                 }
             }
         }
     }
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
 }
 
 macro_rules! drive_promise {
@@ -2,3 +2,8 @@ pub const EDIT: &str = "\u{270F}";
 pub const REMOVE: &str = "\u{274C}";
 pub const CONFIRM: &str = "\u{2705}";
 pub const CANCEL: &str = "\u{27F3}";
+pub const LOCKED: &str = "\u{1F512}";
+pub const ATTACHMENT: &str = "\u{1F4CE}";
+pub const SPLIT: &str = "\u{2702}";
+pub const MERGE: &str = "\u{2935}";
+pub const ARCHIVE: &str = "\u{1F5C4}";
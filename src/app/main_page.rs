@@ -1,15 +1,26 @@
 mod articles;
+mod backup;
 mod balance;
+mod budgets;
 mod dynamics;
+mod export;
+mod history;
+mod layout;
 mod operations;
+mod palette;
 mod percents;
 mod profit;
+mod recurring;
+mod report;
+mod sort;
+mod totals;
 
 use std::borrow::Cow;
 
+use serde::{Deserialize, Serialize};
 use strum::IntoStaticStr;
 
-use crate::db::Db;
+use crate::db::{Db, validation::Rules};
 
 pub struct State {
     db: Db,
@@ -20,12 +31,30 @@ pub struct State {
     profit_state: profit::State,
     percents_state: percents::State,
     dynamics_state: dynamics::State,
+    backup_state: backup::State,
+    report_state: report::State,
+    recurring_state: recurring::State,
+    budgets_state: budgets::State,
+    history: history::History,
 }
 
 pub enum Response {
     Exit,
     None,
 }
+/// Настройки, которые сохраняются между запусками
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    operations_sort: sort::Order<operations::Column>,
+    articles_sort: sort::Order<articles::Column>,
+    balance_sort: sort::Order<balance::Column>,
+    operations_layout: layout::Layout<operations::Column>,
+    articles_layout: layout::Layout<articles::Column>,
+    balance_layout: layout::Layout<balance::Column>,
+    operations_rules: Rules,
+    budget_threshold: budgets::Threshold,
+}
 #[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq)]
 pub enum SelectedView {
     #[strum(serialize = "Динамика")]
@@ -40,6 +69,16 @@ pub enum SelectedView {
     Articles,
     #[strum(serialize = "Баланс")]
     Balance,
+    #[strum(serialize = "Повторяющиеся")]
+    Recurring,
+    #[strum(serialize = "Бюджеты")]
+    Budgets,
+    #[strum(serialize = "Отчёт")]
+    Report,
+    #[strum(serialize = "Резервная копия")]
+    Backup,
+    #[strum(serialize = "История")]
+    History,
 }
 impl State {
     pub fn new(db: Db) -> Self {
@@ -51,11 +90,30 @@ impl State {
             profit_state: profit::State::new(&db),
             percents_state: percents::State::new(&db),
             dynamics_state: dynamics::State::new(),
+            backup_state: backup::State::new(),
+            report_state: report::State::new(),
+            recurring_state: recurring::State::new(),
+            budgets_state: budgets::State::new(&db),
+            history: history::History::default(),
             db,
         }
     }
-    pub fn view(&mut self, ctx: &egui::Context) -> Response {
+    pub fn view(&mut self, ctx: &egui::Context, settings: &mut Settings) -> Response {
         let mut response = Response::None;
+        // Пока редактируется текст, Ctrl+Z относится к нему
+        let mut history_action = if ctx.wants_keyboard_input() {
+            None
+        } else {
+            ctx.input_mut(|i| {
+                if i.consume_shortcut(&history::REDO) {
+                    Some(history::Action::Redo)
+                } else if i.consume_shortcut(&history::UNDO) {
+                    Some(history::Action::Undo)
+                } else {
+                    None
+                }
+            })
+        };
         egui::TopBottomPanel::top("Main page menu").show(ctx, |ui| {
             egui::MenuBar::new().ui(ui, |ui| {
                 egui::widgets::global_theme_preference_buttons(ui);
@@ -71,37 +129,201 @@ impl State {
             self.tables_selectors(ui);
             ui.add_space(20.0);
             self.indicator_selectors(ui);
+            ui.add_space(20.0);
+            self.service_selectors(ui);
         });
-        egui::CentralPanel::default().show(ctx, |ui| match &self.selected {
+        self.budget_alerts(ctx, settings.budget_threshold);
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(action) = self.selected_view(ui, settings) {
+                history_action = Some(action);
+            }
+        });
+        self.recurring_state.confirm_window(
+            ctx,
+            &self.db,
+            self.articles_state.table(),
+            &settings.operations_rules,
+        );
+        if let Some(action) = history_action {
+            self.history_action(action);
+        }
+        return response;
+    }
+    /// Содержимое выбранного раздела. Возвращает действие с историей,
+    /// если его выбрали в разделе истории.
+    fn selected_view(
+        &mut self,
+        ui: &mut egui::Ui,
+        settings: &mut Settings,
+    ) -> Option<history::Action> {
+        match &self.selected {
             SelectedView::Dynamics => {
-                self.dynamics_state
-                    .view(ui, &self.db, self.articles_state.table());
+                self.dynamics_state.view(
+                    ui,
+                    &self.db,
+                    self.articles_state.table(),
+                    self.operations_state.tags(),
+                );
             }
             SelectedView::Percentages => {
-                self.percents_state.view(ui, &self.db);
+                self.percents_state
+                    .view(ui, &self.db, self.articles_state.table());
             }
             SelectedView::Profit => {
                 self.profit_state.view(ui, &self.db);
             }
-            SelectedView::Operations => {
-                self.operations_state
-                    .view(ui, &self.db, self.articles_state.table())
+            SelectedView::Operations => self.operations_state.view(
+                ui,
+                &self.db,
+                self.articles_state.table(),
+                self.balance_state.table(),
+                (
+                    &mut settings.operations_sort,
+                    &mut settings.operations_layout,
+                ),
+                &mut settings.operations_rules,
+            ),
+            SelectedView::Articles => {
+                self.articles_state.view(
+                    ui,
+                    &self.db,
+                    (&mut settings.articles_sort, &mut settings.articles_layout),
+                );
             }
-            SelectedView::Articles => self.articles_state.view(ui, &self.db),
 
             SelectedView::Balance => {
-                self.balance_state.view(ui, &self.db);
+                self.balance_state.view(
+                    ui,
+                    &self.db,
+                    self.articles_state.table(),
+                    (&mut settings.balance_sort, &mut settings.balance_layout),
+                );
             }
-        });
-        return response;
+            SelectedView::Recurring => self.recurring_state.view(
+                ui,
+                &self.db,
+                self.articles_state.table(),
+                &settings.operations_rules,
+            ),
+            SelectedView::Budgets => self.budgets_state.view(
+                ui,
+                &self.db,
+                self.articles_state.table(),
+                &mut settings.budget_threshold,
+            ),
+            SelectedView::Report => {
+                self.report_state
+                    .view(ui, &self.db, self.balance_state.table());
+            }
+            SelectedView::Backup => {
+                self.backup_state.view(ui, &self.db);
+            }
+            SelectedView::History => {
+                let enabled = self.tables_idle();
+                return self.history.view(ui, enabled);
+            }
+        }
+        None
     }
     pub fn drive(&mut self) {
-        self.operations_state.drive();
-        self.articles_state.drive();
+        if let Some((change, splits)) = self.operations_state.drive() {
+            self.history
+                .record(history::Entry::Operation(change, splits));
+            self.budgets_state.refresh_alerts(&self.db);
+        }
+        if let Some(change) = self.articles_state.drive() {
+            self.history.record(history::Entry::Article(change));
+        }
+        if let Some(applied) = self.operations_state.take_applied() {
+            self.history.finish(applied);
+        }
+        if let Some(applied) = self.articles_state.take_applied() {
+            self.history.finish(applied);
+        }
+        if self.articles_state.take_merged() {
+            // Записи истории могут ссылаться на удалённую статью
+            self.history.clear();
+            self.operations_state = operations::State::new(&self.db);
+            self.recurring_state.reload(&self.db);
+            self.percents_state = percents::State::new(&self.db);
+            self.dynamics_state = dynamics::State::new();
+            self.budgets_state = budgets::State::new(&self.db);
+        }
         self.balance_state.drive();
+        // Сигнал ждёт, пока перечитываемая таблица не освободится
+        if self.operations_state.is_idle() && self.balance_state.take_changed() {
+            self.operations_state.reload(&self.db);
+        }
+        if self.balance_state.is_idle() && self.operations_state.take_balance_changed() {
+            self.balance_state.reload(&self.db);
+        }
         self.profit_state.drive();
         self.percents_state.drive();
         self.dynamics_state.drive();
+        self.report_state.drive();
+        self.budgets_state.drive();
+        if self.recurring_state.drive() {
+            // Появились новые операции
+            self.operations_state = operations::State::new(&self.db);
+            self.profit_state = profit::State::new(&self.db);
+            self.percents_state = percents::State::new(&self.db);
+            self.budgets_state.refresh_alerts(&self.db);
+        }
+        if matches!(self.backup_state.drive(), backup::Response::Restored) {
+            self.reload();
+        }
+    }
+    /// Перечитывает все таблицы и индикаторы, например после восстановления копии
+    fn reload(&mut self) {
+        self.history.clear();
+        self.operations_state = operations::State::new(&self.db);
+        self.articles_state = articles::State::new(&self.db);
+        self.balance_state = balance::State::new(&self.db);
+        self.profit_state = profit::State::new(&self.db);
+        self.percents_state = percents::State::new(&self.db);
+        self.recurring_state = recurring::State::new();
+        self.budgets_state = budgets::State::new(&self.db);
+    }
+    /// Полоса внизу со статьями, которые подходят к бюджету на этот месяц или превысили его
+    fn budget_alerts(&mut self, ctx: &egui::Context, threshold: budgets::Threshold) {
+        let alerts = self.budgets_state.alerts(threshold);
+        let Some(articles) = self.articles_state.table() else {
+            return;
+        };
+        if alerts.is_empty() {
+            return;
+        }
+        egui::TopBottomPanel::bottom("Budget alerts").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                for alert in &alerts {
+                    if alert.show(ui, articles) {
+                        self.selected = SelectedView::Budgets;
+                    }
+                }
+            });
+        });
+    }
+    /// Изменения из истории применяются, только когда таблицы не ждут ответа базы
+    fn tables_idle(&self) -> bool {
+        self.operations_state.is_idle() && self.articles_state.is_idle()
+    }
+    fn history_action(&mut self, action: history::Action) {
+        if !self.tables_idle() {
+            return;
+        }
+        let entry = match action {
+            history::Action::Undo => self.history.undo(),
+            history::Action::Redo => self.history.redo(),
+        };
+        match entry {
+            Some(history::Entry::Operation(change, splits)) => {
+                self.operations_state.apply(&self.db, change, splits);
+            }
+            Some(history::Entry::Article(change)) => {
+                self.articles_state.apply(&self.db, change);
+            }
+            None => {}
+        }
     }
     fn tables_selectors(&mut self, ui: &mut egui::Ui) {
         self.side_buttons(
@@ -110,6 +332,8 @@ impl State {
                 SelectedView::Operations,
                 SelectedView::Articles,
                 SelectedView::Balance,
+                SelectedView::Recurring,
+                SelectedView::Budgets,
             ],
             ui,
         );
@@ -125,6 +349,17 @@ impl State {
             ui,
         );
     }
+    fn service_selectors(&mut self, ui: &mut egui::Ui) {
+        self.side_buttons(
+            "Сервис",
+            &[
+                SelectedView::Report,
+                SelectedView::Backup,
+                SelectedView::History,
+            ],
+            ui,
+        );
+    }
     fn side_buttons(&mut self, heading: &str, variants: &[SelectedView], ui: &mut egui::Ui) {
         ui.heading(heading);
         let mut chosen_one = None;
@@ -1,41 +1,137 @@
+mod delete;
+mod merge;
 mod table;
+mod tree;
 use std::collections::BTreeMap;
+pub use table::Column;
 
 use tokio_postgres::Error;
 
 use crate::{
-    app::drive_result_promise,
-    db::{Db, scheme::ArticlesRow},
+    app::{
+        drive_result_promise,
+        main_page::{history::Change, layout::Layout, sort},
+    },
+    db::{
+        Db, Orphans,
+        scheme::{ArticleUsage, ArticlesRow, Inserted},
+        validation,
+    },
     promise_lite::PromiseLite,
 };
 pub struct State {
     table: Option<table::State>,
+    /// Показывать статьи деревом вместо таблицы
+    tree_view: bool,
+    /// Показывать архивные статьи
+    show_archived: bool,
     error_message: Option<String>,
-    result: Option<PromiseLite<Result<BTreeMap<i32, ArticlesRow>, Error>>>,
+    result: Option<PromiseLite<Result<BTreeMap<i32, ArticlesRow>, validation::Error>>>,
+    /// Добавление новой статьи: база возвращает её id вместе со строками
+    inserted: Option<PromiseLite<Result<Inserted<ArticlesRow>, validation::Error>>>,
+    /// Изменение, которое попадёт в историю, когда база его подтвердит
+    pending: Option<Change<ArticlesRow>>,
+    /// Ждём ответа базы на изменение из истории
+    applying: bool,
+    /// Чем закончилось изменение из истории: `true` — применено
+    applied: Option<bool>,
+    /// Открытое окно слияния статей
+    merge: Option<merge::Merge>,
+    /// Открытое окно удаления статьи с операциями
+    delete: Option<delete::Delete>,
+    /// Сколько операций ссылается на каждую статью; `None`, пока считается
+    usage: Option<BTreeMap<i32, ArticleUsage>>,
+    usage_result: Option<PromiseLite<Result<BTreeMap<i32, ArticleUsage>, Error>>>,
+    /// Слияние или удаление затронуло операции, их нужно перечитать
+    merged: bool,
 }
 impl State {
     pub fn new(db: &Db) -> Self {
         Self {
             table: None,
+            tree_view: false,
+            show_archived: false,
             error_message: None,
             result: Some(db.select_from_articles()),
+            inserted: None,
+            pending: None,
+            applying: false,
+            applied: None,
+            merge: None,
+            delete: None,
+            usage: None,
+            usage_result: Some(db.select_article_usage()),
+            merged: false,
         }
     }
-    pub fn view(&mut self, ui: &mut egui::Ui, db: &Db) {
+    pub fn view(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        (sort, layout): (&mut sort::Order<Column>, &mut Layout<Column>),
+    ) {
         ui.heading("Статьи");
-        let enabled = self.result.is_none();
+        let enabled = self.is_idle();
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.tree_view, false, "Таблица");
+            ui.radio_value(&mut self.tree_view, true, "Дерево");
+            ui.separator();
+            ui.checkbox(&mut self.show_archived, "Показывать архивные");
+        });
         if let Some(table) = &mut self.table {
-            if let Some(response) = table.show(ui, enabled) {
+            table.set_show_archived(self.show_archived);
+            let response = if self.tree_view {
+                let enabled = enabled && !table.is_changing();
+                tree::show(ui, table.inner(), (enabled, self.show_archived))
+            } else {
+                table.show(ui, enabled, (sort, &mut *layout))
+            };
+            if let Some(response) = response {
+                let before = |id| table.inner().get(id).cloned();
+                self.pending = match &response {
+                    table::Response::Update(id, row) => Some(Change {
+                        id: *id,
+                        before: before(id),
+                        after: Some(row.clone()),
+                    }),
+                    table::Response::Delete(id) if !is_used(self.usage.as_ref(), *id) => {
+                        Some(Change {
+                            id: *id,
+                            before: before(id),
+                            after: None,
+                        })
+                    }
+                    // id новой строки вернёт база
+                    table::Response::Insert(row) => Some(Change {
+                        id: 0,
+                        before: None,
+                        after: Some(row.clone()),
+                    }),
+                    table::Response::Delete(_) | table::Response::Merge(_) => None,
+                };
                 match response {
                     table::Response::Update(id, articles_row) => {
                         self.result = Some(db.update_in_articles(id, articles_row))
                     }
-                    table::Response::Delete(id) => self.result = Some(db.delete_from_articles(id)),
+                    table::Response::Delete(id) if self.pending.is_some() => {
+                        self.result = Some(db.delete_from_articles(id))
+                    }
+                    // На статью ссылаются операции: спрашиваем, что с ними делать
+                    table::Response::Delete(id) => {
+                        self.delete = Some(delete::Delete::new(id));
+                        self.refresh_usage(db);
+                    }
                     table::Response::Insert(articles_row) => {
-                        self.result = Some(db.insert_to_articles(articles_row))
+                        self.inserted = Some(db.insert_to_articles(articles_row));
+                    }
+                    table::Response::Merge(source) => {
+                        self.merge = Some(merge::Merge::new(source));
+                        self.refresh_usage(db);
                     }
                 }
             }
+            self.merge_window(ui.ctx(), db, enabled);
+            self.delete_window(ui.ctx(), db, enabled);
         }
         ui.horizontal(|ui| {
             let insert = egui::Button::new("Добавить!");
@@ -47,34 +143,161 @@ impl State {
                 .clicked()
             {
                 if let Some(t) = &mut self.table {
+                    // Новая статья вводится в таблице
+                    self.tree_view = false;
                     t.insert_new_row();
                 }
             }
             let reload = egui::Button::new("Перезагрузить!");
             if ui.add_enabled(enabled, reload).clicked() {
-                self.result = Some(db.select_from_articles())
+                self.result = Some(db.select_from_articles());
+                self.refresh_usage(db);
             }
+            layout.editor(ui);
         });
         if let Some(error) = &self.error_message {
             ui.colored_label(egui::Color32::RED, error);
         }
     }
-    pub fn drive(&mut self) {
+    fn merge_window(&mut self, ctx: &egui::Context, db: &Db, enabled: bool) {
+        let (Some(merge), Some(table)) = (&mut self.merge, &self.table) else {
+            return;
+        };
+        match merge.show(ctx, table.inner(), self.usage.as_ref(), enabled) {
+            Some(merge::Action::Confirm { source, target }) => {
+                log::info!("Сливаем статью {source} с {target}");
+                self.merged = true;
+                self.result = Some(db.delete_article(source, Orphans::Reassign(target)));
+            }
+            Some(merge::Action::Cancel) => self.merge = None,
+            None => {}
+        }
+    }
+    fn delete_window(&mut self, ctx: &egui::Context, db: &Db, enabled: bool) {
+        let (Some(delete), Some(table)) = (&mut self.delete, &self.table) else {
+            return;
+        };
+        // Окно откроется, когда операции будут посчитаны
+        let Some(usage) = &self.usage else {
+            return;
+        };
+        let usage = usage.get(&delete.id()).cloned().unwrap_or_default();
+        match delete.show(ctx, table.inner(), &usage, enabled) {
+            Some(delete::Action::Confirm(id, orphans)) => {
+                log::info!("Удаляем статью {id}, операции: {orphans:?}");
+                self.merged = true;
+                self.result = Some(db.delete_article(id, orphans));
+            }
+            Some(delete::Action::Cancel) => self.delete = None,
+            None => {}
+        }
+    }
+    fn refresh_usage(&mut self, db: &Db) {
+        self.usage = None;
+        self.usage_result = Some(db.select_article_usage());
+    }
+    /// Слияние или удаление затронуло операции, их пора перечитать
+    pub fn take_merged(&mut self) -> bool {
+        self.result.is_none() && std::mem::take(&mut self.merged)
+    }
+    /// Возвращает подтверждённое базой изменение для истории
+    pub fn drive(&mut self) -> Option<Change<ArticlesRow>> {
+        let mut change = None;
         drive_result_promise!(
             self.result,
-            Ok(values) => {
-                self.table = Some(table::State::new(values));
-                self.error_message = None;
+            Ok(values) => change = self.loaded(values, None),
+            Err(err) => self.failed(err),
+        );
+        drive_result_promise!(
+            self.inserted,
+            Ok(inserted) => {
+                let (id, values) = inserted;
+                change = self.loaded(values, Some(id));
+            },
+            Err(err) => self.failed(err),
+        );
+        drive_result_promise!(
+            self.usage_result,
+            Ok(usage) => {
+                if let Some(table) = &mut self.table {
+                    table.set_usage(usage.clone());
+                }
+                self.usage = Some(usage);
             },
             Err(err) => self.set_err(err),
         );
+        change
+    }
+    /// `inserted` — id добавленной строки, его получает изменение для истории
+    fn loaded(
+        &mut self,
+        values: BTreeMap<i32, ArticlesRow>,
+        inserted: Option<i32>,
+    ) -> Option<Change<ArticlesRow>> {
+        if std::mem::take(&mut self.applying) {
+            self.applied = Some(true);
+        }
+        let change = self.pending.take().map(|mut pending| {
+            if let Some(id) = inserted {
+                pending.id = id;
+            }
+            pending
+        });
+        let mut table = table::State::new(values);
+        if let Some(usage) = &self.usage {
+            table.set_usage(usage.clone());
+        }
+        self.table = Some(table);
+        self.merge = None;
+        self.delete = None;
+        self.error_message = None;
+        change
+    }
+    fn failed(&mut self, err: impl std::error::Error) {
+        if std::mem::take(&mut self.applying) {
+            self.applied = Some(false);
+        }
+        self.pending = None;
+        self.merged = false;
+        self.set_err(err);
+    }
+    /// Применяет изменение из истории: приводит строку к состоянию `after`
+    pub fn apply(&mut self, db: &Db, change: Change<ArticlesRow>) {
+        self.applying = true;
+        self.result = Some(match change {
+            Change {
+                id, after: None, ..
+            } => db.delete_from_articles(id),
+            Change {
+                id,
+                before: None,
+                after: Some(row),
+            } => db.reinsert_to_articles(id, row),
+            Change {
+                id,
+                after: Some(row),
+                ..
+            } => db.update_in_articles(id, row),
+        });
+    }
+    /// Результат последнего [`Self::apply`], когда база ответила
+    pub fn take_applied(&mut self) -> Option<bool> {
+        self.applied.take()
+    }
+    pub fn is_idle(&self) -> bool {
+        self.result.is_none() && self.inserted.is_none()
     }
     pub fn table(&self) -> Option<&BTreeMap<i32, ArticlesRow>> {
         self.table.as_ref().map(|t| t.inner())
     }
     fn set_err(&mut self, err: impl std::error::Error) {
-        let message = format!("{err:?}");
+        let message = err.to_string();
         log::error!("{}", message);
         self.error_message = Some(message);
     }
 }
+
+/// Ссылаются ли на статью операции. Пока это неизвестно, считаем, что да.
+fn is_used(usage: Option<&BTreeMap<i32, ArticleUsage>>, id: i32) -> bool {
+    usage.is_none_or(|usage| usage.get(&id).is_some_and(|u| u.operations > 0))
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+
+use super::merge::target_input;
+use crate::db::{
+    Orphans,
+    hierarchy::{self, Tree},
+    scheme::{ArticleUsage, ArticlesRow},
+};
+
+/// Удаление статьи, на которую ещё ссылаются операции
+pub struct Delete {
+    id: i32,
+    choice: Choice,
+    target: Option<i32>,
+}
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Reassign,
+    Unlink,
+    Delete,
+}
+pub enum Action {
+    Confirm(i32, Orphans),
+    Cancel,
+}
+impl Delete {
+    pub fn new(id: i32) -> Self {
+        Self {
+            id,
+            choice: Choice::Reassign,
+            target: None,
+        }
+    }
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        articles: &BTreeMap<i32, ArticlesRow>,
+        usage: &ArticleUsage,
+        enabled: bool,
+    ) -> Option<Action> {
+        let tree = Tree::new(articles);
+        let name = hierarchy::path(self.id, articles);
+        let mut action = None;
+        egui::Window::new("Удаление статьи")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "На статью «{name}» ссылается операций: {}, оборот {}",
+                    usage.operations,
+                    usage.turnover()
+                ));
+                ui.label("Что сделать с операциями?");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.choice, Choice::Reassign, "Перенести в");
+                    ui.add_enabled_ui(self.choice == Choice::Reassign, |ui| {
+                        target_input(ui, "delete target", &tree, self.id, &mut self.target);
+                    });
+                });
+                ui.radio_value(&mut self.choice, Choice::Unlink, "Оставить без статьи");
+                ui.add_enabled_ui(usage.locked == 0, |ui| {
+                    ui.radio_value(
+                        &mut self.choice,
+                        Choice::Delete,
+                        "Удалить вместе со статьёй",
+                    );
+                })
+                .response
+                .on_disabled_hover_text(format!(
+                    "Операций в сформированных балансах: {}. Их удалить нельзя",
+                    usage.locked
+                ));
+                if usage.locked > 0 && self.choice != Choice::Delete {
+                    ui.colored_label(
+                        ui.visuals().warn_fg_color,
+                        format!(
+                            "Операций в сформированных балансах: {}. \
+                            Их изменение попадёт в журнал изменений балансов",
+                            usage.locked
+                        ),
+                    );
+                }
+                let orphans = match self.choice {
+                    Choice::Reassign => self.target.map(Orphans::Reassign),
+                    Choice::Unlink => Some(Orphans::Unlink),
+                    Choice::Delete => (usage.locked == 0).then_some(Orphans::Delete),
+                };
+                ui.horizontal(|ui| {
+                    let confirm = egui::Button::new("Удалить!");
+                    if ui
+                        .add_enabled(enabled && orphans.is_some(), confirm)
+                        .clicked()
+                        && let Some(orphans) = orphans
+                    {
+                        action = Some(Action::Confirm(self.id, orphans));
+                    }
+                    if ui.button("Отмена").clicked() {
+                        action = Some(Action::Cancel);
+                    }
+                });
+            });
+        action
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use crate::db::{
+    hierarchy::{self, Tree},
+    scheme::{ArticleUsage, ArticlesRow},
+};
+
+/// Слияние дубликатов: операции статьи переносятся в другую, сама статья удаляется
+pub struct Merge {
+    source: i32,
+    target: Option<i32>,
+}
+pub enum Action {
+    Confirm { source: i32, target: i32 },
+    Cancel,
+}
+impl Merge {
+    pub fn new(source: i32) -> Self {
+        Self {
+            source,
+            target: None,
+        }
+    }
+    /// `usage` — `None`, пока число операций считается
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        articles: &BTreeMap<i32, ArticlesRow>,
+        usage: Option<&BTreeMap<i32, ArticleUsage>>,
+        enabled: bool,
+    ) -> Option<Action> {
+        let tree = Tree::new(articles);
+        let source_name = hierarchy::path(self.source, articles);
+        let mut action = None;
+        egui::Window::new("Слияние статей")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!("Слить «{source_name}» с:"));
+                target_input(ui, "merge target", &tree, self.source, &mut self.target);
+                match usage {
+                    Some(usage) => {
+                        let usage = usage.get(&self.source);
+                        let operations = usage.map_or(0, |u| u.operations);
+                        ui.label(format!("Будет перенесено операций: {operations}"));
+                        let locked = usage.map_or(0, |u| u.locked);
+                        if locked > 0 {
+                            ui.colored_label(
+                                ui.visuals().warn_fg_color,
+                                format!(
+                                    "Из них в сформированных балансах: {locked}. \
+                                    Перенос попадёт в журнал изменений балансов"
+                                ),
+                            );
+                        }
+                    }
+                    None => {
+                        ui.label("Считаем операции…");
+                    }
+                }
+                let children = tree.children(Some(self.source)).len();
+                if children > 0 {
+                    ui.label(format!("Вложенных статей перейдёт в выбранную: {children}"));
+                }
+                ui.label(format!("Статья «{source_name}» будет удалена"));
+                ui.horizontal(|ui| {
+                    let confirm = egui::Button::new("Слить!");
+                    if ui
+                        .add_enabled(enabled && self.target.is_some(), confirm)
+                        .clicked()
+                        && let Some(target) = self.target
+                    {
+                        action = Some(Action::Confirm {
+                            source: self.source,
+                            target,
+                        });
+                    }
+                    if ui.button("Отмена").clicked() {
+                        action = Some(Action::Cancel);
+                    }
+                });
+            });
+        action
+    }
+}
+
+/// Статья, в которую переносятся операции `source`. Саму `source`
+/// и вложенные в неё выбрать нельзя.
+pub fn target_input(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    tree: &Tree<'_>,
+    source: i32,
+    target: &mut Option<i32>,
+) {
+    let excluded = tree.descendants(source);
+    let selected = target.map(|id| tree.indented(id, 0)).unwrap_or_default();
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(selected)
+        .width(240.0)
+        .show_ui(ui, |ui| {
+            for (id, depth) in tree.depth_first() {
+                if !excluded.contains(&id) {
+                    ui.selectable_value(target, Some(id), tree.indented(id, depth));
+                }
+            }
+        });
+}
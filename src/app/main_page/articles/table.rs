@@ -1,16 +1,78 @@
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
+
 use crate::{
-    app::{icons, main_page::option_to_string},
-    db::scheme::ArticlesRow,
+    app::{
+        icons,
+        main_page::{
+            layout::{Columns, Layout},
+            option_to_string, palette, sort,
+        },
+    },
+    db::{
+        hierarchy::{self, Tree},
+        scheme::{ArticleUsage, ArticlesRow},
+    },
 };
 use std::collections::BTreeMap;
 pub struct State {
     values: BTreeMap<i32, ArticlesRow>,
+    /// Сколько операций ссылается на статьи
+    usage: BTreeMap<i32, ArticleUsage>,
     edited: Option<(Option<i32>, ArticlesRow)>,
+    /// id в порядке показа и сортировка, по которой он посчитан
+    order: Vec<i32>,
+    sorted_by: Option<sort::Order<Column>>,
+    /// Показывать архивные статьи и то, что в них вложено
+    show_archived: bool,
+    /// Прокрутить к новой строке в следующем кадре
+    scroll_to_new: bool,
+}
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Column {
+    #[strum(serialize = "id")]
+    Id,
+    #[strum(serialize = "name")]
+    Name,
+    #[strum(serialize = "parent")]
+    Parent,
+    #[strum(serialize = "icon")]
+    Icon,
+    #[strum(serialize = "color")]
+    Color,
+    #[strum(serialize = "archived")]
+    Archived,
+    #[strum(serialize = "operations")]
+    Operations,
+    #[strum(serialize = "turnover")]
+    Turnover,
+}
+const COLUMNS: [Column; 8] = [
+    Column::Id,
+    Column::Name,
+    Column::Parent,
+    Column::Icon,
+    Column::Color,
+    Column::Archived,
+    Column::Operations,
+    Column::Turnover,
+];
+impl Columns for Column {
+    const ALL: &'static [Self] = &COLUMNS;
+    fn width(self) -> f32 {
+        match self {
+            Self::Id | Self::Icon | Self::Color => 60.0,
+            Self::Name | Self::Parent => 240.0,
+            Self::Operations | Self::Turnover | Self::Archived => 100.0,
+        }
+    }
 }
 pub enum Response {
     Update(i32, ArticlesRow),
     Insert(ArticlesRow),
     Delete(i32),
+    /// Открыть слияние статьи с другой
+    Merge(i32),
 }
 enum Edited {
     Confirm,
@@ -19,74 +81,154 @@ enum Edited {
 enum Regular {
     Edit,
     Delete,
+    Merge,
+    Archive,
 }
 impl State {
     pub fn new(values: BTreeMap<i32, ArticlesRow>) -> Self {
         Self {
             values,
+            usage: BTreeMap::new(),
             edited: None,
+            order: Vec::new(),
+            sorted_by: None,
+            show_archived: false,
+            scroll_to_new: false,
         }
     }
-    pub fn show(&mut self, ui: &mut egui::Ui, edit_enabled: bool) -> Option<Response> {
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        edit_enabled: bool,
+        (sort, layout): (&mut sort::Order<Column>, &mut Layout<Column>),
+    ) -> Option<Response> {
         let mut response = None;
-        let headers = ["id", "name", "Операции"];
+        if self.sorted_by.as_ref() != Some(sort) {
+            self.sort(sort);
+        }
         let regular_enabled = edit_enabled && self.edited.is_none();
-        egui::containers::ScrollArea::new([true, true]).show(ui, |ui| {
-            egui::Grid::new("Articles")
-                .num_columns(headers.len())
-                .show(ui, |ui| {
-                    for header in headers {
-                        ui.strong(header);
-                    }
-                    ui.end_row();
-                    for (id, row) in self.values.iter() {
-                        if let Some((Some(target), edited_row)) = &mut self.edited
-                            && *target == *id
+        let inserting = matches!(self.edited, Some((None, _)));
+        let total = self.order.len() + usize::from(inserting);
+        let row_height = ui.spacing().interact_size.y;
+        // Кнопки остаются под таблицей
+        let max_height = ui.available_height() - 3.0 * row_height;
+        let columns = layout.visible();
+        let builder = egui_extras::TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .max_scroll_height(max_height)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center));
+        let mut builder = layout.columns(builder, "Articles");
+        if std::mem::take(&mut self.scroll_to_new) {
+            builder = builder.scroll_to_row(total.saturating_sub(1), Some(egui::Align::BOTTOM));
+        }
+        builder
+            .column(egui_extras::Column::remainder().at_least(60.0))
+            .header(row_height, |mut header| {
+                layout.header(&mut header, sort);
+                header.col(|ui| {
+                    ui.strong("Операции");
+                });
+            })
+            .body(|body| {
+                body.rows(row_height, total, |mut table_row| {
+                    let Some((id, row)) = self
+                        .order
+                        .get(table_row.index())
+                        .and_then(|id| self.values.get_key_value(id))
+                    else {
+                        // Последняя строка — новая статья
+                        if let Some((None, edited_row)) = &mut self.edited
+                            && let Some(inner_response) = Self::show_edited_row(
+                                &mut table_row,
+                                (None, edited_row),
+                                (&columns, &self.values),
+                                edit_enabled,
+                            )
                         {
-                            if let Some(inner_response) =
-                                Self::show_edited_row(ui, Some(*target), edited_row, edit_enabled)
-                            {
-                                match inner_response {
-                                    Edited::Confirm => {
-                                        response = Some(Response::Update(*id, edited_row.clone()))
-                                    }
-                                    Edited::Cancel => {
-                                        self.edited = None;
-                                    }
+                            match inner_response {
+                                Edited::Confirm => {
+                                    response = Some(Response::Insert(edited_row.clone()))
                                 }
-                            }
-                        } else {
-                            if let Some(inner_response) =
-                                Self::show_normal_row(ui, *id, row, regular_enabled)
-                            {
-                                match inner_response {
-                                    Regular::Edit => self.edited = Some((Some(*id), row.clone())),
-                                    Regular::Delete => response = Some(Response::Delete(*id)),
+                                Edited::Cancel => {
+                                    self.edited = None;
                                 }
                             }
                         }
-                        ui.end_row();
-                    }
-                    if let Some((None, edited_row)) = &mut self.edited {
-                        if let Some(inner_response) =
-                            Self::show_edited_row(ui, None, edited_row, edit_enabled)
-                        {
+                        return;
+                    };
+                    if let Some((Some(target), edited_row)) = &mut self.edited
+                        && *target == *id
+                    {
+                        if let Some(inner_response) = Self::show_edited_row(
+                            &mut table_row,
+                            (Some(*target), edited_row),
+                            (&columns, &self.values),
+                            edit_enabled,
+                        ) {
                             match inner_response {
                                 Edited::Confirm => {
-                                    response = Some(Response::Insert(edited_row.clone()))
+                                    response = Some(Response::Update(*id, edited_row.clone()))
                                 }
                                 Edited::Cancel => {
                                     self.edited = None;
                                 }
                             }
                         }
+                    } else if let Some(inner_response) = Self::show_normal_row(
+                        &mut table_row,
+                        (*id, row),
+                        (&columns, &self.values, self.usage.get(id)),
+                        regular_enabled,
+                    ) {
+                        match inner_response {
+                            Regular::Edit => self.edited = Some((Some(*id), row.clone())),
+                            Regular::Delete => response = Some(Response::Delete(*id)),
+                            Regular::Merge => response = Some(Response::Merge(*id)),
+                            Regular::Archive => {
+                                let row = ArticlesRow {
+                                    archived: !row.archived,
+                                    ..row.clone()
+                                };
+                                response = Some(Response::Update(*id, row));
+                            }
+                        }
                     }
                 });
-        });
+            });
         response
     }
+    fn sort(&mut self, sort: &sort::Order<Column>) {
+        let parent = |row: &ArticlesRow| row.parent_id.map(|p| hierarchy::path(p, &self.values));
+        let usage = |id: &i32| self.usage.get(id);
+        let tree = Tree::new(&self.values);
+        let mut rows: Vec<_> = self
+            .values
+            .iter()
+            .filter(|(id, _)| self.show_archived || !tree.is_archived(**id))
+            .collect();
+        sort.sort(&mut rows, |column, (a_id, a), (b_id, b)| match column {
+            Column::Id => sort::nullable(Some(a_id), Some(b_id)),
+            Column::Name => sort::text(a.name.as_deref(), b.name.as_deref()),
+            Column::Parent => sort::text(parent(a).as_deref(), parent(b).as_deref()),
+            Column::Icon => sort::text(a.icon.as_deref(), b.icon.as_deref()),
+            Column::Color => sort::text(a.color.as_deref(), b.color.as_deref()),
+            Column::Archived => sort::nullable(Some(a.archived), Some(b.archived)),
+            Column::Operations => sort::nullable(
+                usage(a_id).map(|u| u.operations),
+                usage(b_id).map(|u| u.operations),
+            ),
+            Column::Turnover => sort::nullable(
+                usage(a_id).map(ArticleUsage::turnover),
+                usage(b_id).map(ArticleUsage::turnover),
+            ),
+        });
+        self.order = rows.into_iter().map(|(id, _)| *id).collect();
+        self.sorted_by = Some(sort.clone());
+    }
     pub fn insert_new_row(&mut self) {
         self.edited = Some((None, Default::default()));
+        self.scroll_to_new = true;
     }
     pub fn is_changing(&self) -> bool {
         self.edited.is_some()
@@ -94,16 +236,56 @@ impl State {
     pub fn inner(&self) -> &BTreeMap<i32, ArticlesRow> {
         &self.values
     }
+    pub fn set_show_archived(&mut self, show: bool) {
+        if self.show_archived != show {
+            self.show_archived = show;
+            self.sorted_by = None;
+        }
+    }
+    pub fn set_usage(&mut self, usage: BTreeMap<i32, ArticleUsage>) {
+        self.usage = usage;
+        self.sorted_by = None;
+    }
     fn show_normal_row(
-        ui: &mut egui::Ui,
-        id: i32,
-        row: &ArticlesRow,
+        table_row: &mut egui_extras::TableRow<'_, '_>,
+        (id, row): (i32, &ArticlesRow),
+        (columns, articles, usage): (
+            &[Column],
+            &BTreeMap<i32, ArticlesRow>,
+            Option<&ArticleUsage>,
+        ),
         enabled: bool,
     ) -> Option<Regular> {
-        ui.label(id.to_string());
-        ui.label(option_to_string(row.name.as_ref()));
+        for column in columns {
+            let cell = match column {
+                Column::Color => {
+                    table_row.col(|ui| {
+                        // Без своего цвета статья рисуется цветом предка или подобранным
+                        let color = palette::article_color(id, articles);
+                        let swatch = ui.colored_label(color, "■");
+                        if row.color.is_none() {
+                            swatch.on_hover_text("Цвет не задан");
+                        }
+                    });
+                    continue;
+                }
+                Column::Icon => option_to_string(row.icon.as_ref()),
+                Column::Archived => if row.archived { "в архиве" } else { "" }.into(),
+                Column::Id => id.to_string(),
+                Column::Name => option_to_string(row.name.as_ref()),
+                Column::Parent => row
+                    .parent_id
+                    .map(|p| hierarchy::path(p, articles))
+                    .unwrap_or_default(),
+                Column::Operations => usage.map_or(0, |u| u.operations).to_string(),
+                Column::Turnover => usage.map_or(0, ArticleUsage::turnover).to_string(),
+            };
+            table_row.col(|ui| {
+                ui.label(cell);
+            });
+        }
         let mut response = None;
-        ui.horizontal(|ui| {
+        table_row.col(|ui| {
             let edit = egui::Button::new(icons::EDIT).small();
             let remove = egui::Button::new(icons::REMOVE).small();
             if ui.add_enabled(enabled, edit).clicked() {
@@ -112,23 +294,76 @@ impl State {
             if ui.add_enabled(enabled, remove).clicked() {
                 response = Some(Regular::Delete);
             }
+            let merge = egui::Button::new(icons::MERGE).small();
+            if ui
+                .add_enabled(enabled, merge)
+                .on_hover_text("Слить с другой статьёй…")
+                .clicked()
+            {
+                response = Some(Regular::Merge);
+            }
+            let archive = egui::Button::new(icons::ARCHIVE)
+                .small()
+                .selected(row.archived);
+            let hover = if row.archived {
+                "Вернуть из архива"
+            } else {
+                "Убрать в архив: статьи не будет в списках для новых операций"
+            };
+            if ui
+                .add_enabled(enabled, archive)
+                .on_hover_text(hover)
+                .clicked()
+            {
+                response = Some(Regular::Archive);
+            }
         });
         response
     }
     fn show_edited_row(
-        ui: &mut egui::Ui,
-        id: Option<i32>,
-        edited_row: &mut ArticlesRow,
+        table_row: &mut egui_extras::TableRow<'_, '_>,
+        (id, edited_row): (Option<i32>, &mut ArticlesRow),
+        (columns, articles): (&[Column], &BTreeMap<i32, ArticlesRow>),
         enabled: bool,
     ) -> Option<Edited> {
-        ui.label(option_to_string(id.as_ref()));
-        ui.add_enabled(
-            enabled,
-            egui::TextEdit::singleline(edited_row.name.get_or_insert_default()),
-        );
+        for column in columns {
+            table_row.col(|ui| match column {
+                Column::Id => {
+                    ui.label(option_to_string(id.as_ref()));
+                }
+                Column::Name => {
+                    ui.add_enabled(
+                        enabled,
+                        egui::TextEdit::singleline(edited_row.name.get_or_insert_default()),
+                    );
+                }
+                Column::Parent => {
+                    ui.add_enabled_ui(enabled, |ui| {
+                        Self::parent_input(ui, id, edited_row, articles);
+                    });
+                }
+                Column::Icon => {
+                    ui.add_enabled(
+                        enabled,
+                        egui::TextEdit::singleline(edited_row.icon.get_or_insert_default())
+                            .hint_text("🙂"),
+                    );
+                }
+                Column::Color => {
+                    ui.add_enabled_ui(enabled, |ui| Self::color_input(ui, edited_row));
+                }
+                Column::Archived => {
+                    ui.add_enabled(
+                        enabled,
+                        egui::Checkbox::new(&mut edited_row.archived, "в архиве"),
+                    );
+                }
+                Column::Operations | Column::Turnover => {}
+            });
+        }
 
         let mut response = None;
-        ui.horizontal(|ui| {
+        table_row.col(|ui| {
             let confirm = egui::Button::new(icons::CONFIRM).small();
             let cancel = egui::Button::new(icons::CANCEL).small();
             if ui.add_enabled(enabled, confirm).clicked() {
@@ -140,4 +375,48 @@ impl State {
         });
         response
     }
+    /// Цвет статьи или «нет»: тогда берётся цвет предка
+    fn color_input(ui: &mut egui::Ui, edited_row: &mut ArticlesRow) {
+        let mut color = palette::own_color(edited_row).unwrap_or(egui::Color32::GRAY);
+        if ui.color_edit_button_srgba(&mut color).changed() {
+            edited_row.color = Some(palette::to_hex(color));
+        }
+        let clear = egui::Button::new(icons::CANCEL).small();
+        if ui
+            .add_enabled(edited_row.color.is_some(), clear)
+            .on_hover_text("Убрать цвет")
+            .clicked()
+        {
+            edited_row.color = None;
+        }
+    }
+    /// Статья, в которую вложена редактируемая. Саму статью и вложенные в неё
+    /// выбрать нельзя, иначе получится цикл.
+    fn parent_input(
+        ui: &mut egui::Ui,
+        id: Option<i32>,
+        edited_row: &mut ArticlesRow,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) {
+        let tree = Tree::new(articles);
+        let excluded = id.map(|id| tree.descendants(id)).unwrap_or_default();
+        let selected = edited_row
+            .parent_id
+            .map(|p| hierarchy::path(p, articles))
+            .unwrap_or_default();
+        egui::ComboBox::from_id_salt(("article parent", id))
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut edited_row.parent_id, None, "верхний уровень");
+                for (parent, depth) in tree.depth_first() {
+                    if !excluded.contains(&parent) {
+                        ui.selectable_value(
+                            &mut edited_row.parent_id,
+                            Some(parent),
+                            tree.indented(parent, depth),
+                        );
+                    }
+                }
+            });
+    }
 }
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+use super::table::Response;
+use crate::{
+    app::main_page::palette,
+    db::{hierarchy::Tree, scheme::ArticlesRow},
+};
+
+/// Перетаскиваемая статья
+struct Dragged(i32);
+
+/// Дерево статей. Статью перетаскивают на другую, чтобы вложить в неё,
+/// или на поле сверху, чтобы сделать статьёй верхнего уровня.
+/// Архивные статьи с вложенными показываются, только если `show_archived`.
+pub fn show(
+    ui: &mut egui::Ui,
+    articles: &BTreeMap<i32, ArticlesRow>,
+    (enabled, show_archived): (bool, bool),
+) -> Option<Response> {
+    let tree = Tree::new(articles);
+    let mut moved = None;
+    // Кнопки остаются под деревом
+    let max_height = ui.available_height() - 3.0 * ui.spacing().interact_size.y;
+    egui::ScrollArea::vertical()
+        .max_height(max_height)
+        .auto_shrink([false, true])
+        .show(ui, |ui| {
+            ui.add_enabled_ui(enabled, |ui| {
+                let (_, dropped) = ui.dnd_drop_zone::<Dragged, _>(
+                    egui::Frame::default().inner_margin(4.0),
+                    |ui| {
+                        ui.weak("Перетащите статью сюда, чтобы вынуть её из группы");
+                    },
+                );
+                if let Some(dragged) = dropped {
+                    moved = Some((dragged.0, None));
+                }
+                for id in tree.children(None) {
+                    node(ui, (&tree, articles, show_archived), *id, &mut moved);
+                }
+            });
+        });
+    let (id, parent) = moved?;
+    let row = articles.get(&id)?;
+    // Статью нельзя вложить в саму себя или в то, что уже вложено в неё
+    if row.parent_id == parent || parent.is_some_and(|p| tree.descendants(id).contains(&p)) {
+        return None;
+    }
+    log::info!("Переносим статью {id} в {parent:?}");
+    Some(Response::Update(
+        id,
+        ArticlesRow {
+            parent_id: parent,
+            ..row.clone()
+        },
+    ))
+}
+
+fn node(
+    ui: &mut egui::Ui,
+    (tree, articles, show_archived): (&Tree<'_>, &BTreeMap<i32, ArticlesRow>, bool),
+    id: i32,
+    moved: &mut Option<(i32, Option<i32>)>,
+) {
+    let archived = articles.get(&id).is_some_and(|a| a.archived);
+    if archived && !show_archived {
+        return;
+    }
+    let mut label = |ui: &mut egui::Ui| {
+        let response = ui
+            .dnd_drag_source(egui::Id::new(("article drag", id)), Dragged(id), |ui| {
+                ui.colored_label(palette::article_color(id, articles), "●");
+                let text = format!("{} ({id})", palette::label(id, articles));
+                if archived {
+                    ui.weak(text).on_hover_text("В архиве");
+                } else {
+                    ui.label(text);
+                }
+            })
+            .response;
+        if response.dnd_hover_payload::<Dragged>().is_some() {
+            ui.painter().rect_stroke(
+                response.rect.expand(2.0),
+                2.0,
+                ui.visuals().selection.stroke,
+                egui::StrokeKind::Outside,
+            );
+        }
+        if let Some(dragged) = response.dnd_release_payload::<Dragged>() {
+            *moved = Some((dragged.0, Some(id)));
+        }
+    };
+    let children = tree.children(Some(id));
+    if children.is_empty() {
+        ui.horizontal(|ui| {
+            ui.add_space(ui.spacing().indent);
+            label(ui);
+        });
+        return;
+    }
+    egui::collapsing_header::CollapsingState::load_with_default_open(
+        ui.ctx(),
+        egui::Id::new(("article tree", id)),
+        true,
+    )
+    .show_header(ui, label)
+    .body(|ui| {
+        for child in children {
+            node(ui, (tree, articles, show_archived), *child, moved);
+        }
+    });
+}
@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+
+use chrono::Local;
+
+use crate::{
+    app::drive_result_promise,
+    db::{
+        Db,
+        backup::{self, RestoreMode, Summary},
+    },
+    promise_lite::PromiseLite,
+};
+pub struct State {
+    path: String,
+    mode: RestoreMode,
+    message: Option<String>,
+    error_message: Option<String>,
+    action: Action,
+    /// Замена всех данных ждёт подтверждения
+    confirm_replace: bool,
+    result: Option<PromiseLite<Result<Summary, backup::Error>>>,
+}
+pub enum Response {
+    Restored,
+    None,
+}
+#[derive(Clone, Copy)]
+enum Action {
+    Backup,
+    Restore,
+}
+impl State {
+    pub fn new() -> Self {
+        Self {
+            path: format!("budget-{}.json", Local::now().format("%Y-%m-%d")),
+            mode: RestoreMode::Merge,
+            message: None,
+            error_message: None,
+            action: Action::Backup,
+            confirm_replace: false,
+            result: None,
+        }
+    }
+    pub fn view(&mut self, ui: &mut egui::Ui, db: &Db) {
+        ui.heading("Резервная копия");
+        let enabled = self.result.is_none();
+        ui.horizontal(|ui| {
+            ui.label("Файл:");
+            ui.add_enabled(enabled, egui::TextEdit::singleline(&mut self.path));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Восстановление:");
+            ui.radio_value(
+                &mut self.mode,
+                RestoreMode::Merge,
+                "Добавить к текущим данным",
+            );
+            ui.radio_value(&mut self.mode, RestoreMode::Replace, "Заменить всё");
+        });
+        ui.horizontal(|ui| {
+            let backup = egui::Button::new("Сохранить копию!");
+            if ui.add_enabled(enabled, backup).clicked() {
+                log::info!("Сохраняем резервную копию в {}", self.path);
+                self.start(Action::Backup, db.backup(PathBuf::from(&self.path)));
+            }
+            let restore = egui::Button::new("Восстановить!");
+            if ui
+                .add_enabled(enabled && !self.confirm_replace, restore)
+                .clicked()
+            {
+                match self.mode {
+                    RestoreMode::Replace => self.confirm_replace = true,
+                    RestoreMode::Merge => self.restore(db, RestoreMode::Merge),
+                }
+            }
+        });
+        if self.confirm_replace {
+            self.confirm_window(ui.ctx(), db);
+        }
+        if let Some(message) = &self.message {
+            ui.label(message);
+        }
+        if let Some(error) = &self.error_message {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+    /// Замена удаляет всё, что есть в базе, поэтому сначала спрашиваем
+    fn confirm_window(&mut self, ctx: &egui::Context, db: &Db) {
+        egui::Window::new("Заменить все данные?")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Из базы будут удалены все статьи, операции, балансы, \
+                    повторяющиеся операции, прикреплённые файлы, разбивки и бюджеты.",
+                );
+                ui.label(format!("Вместо них загрузятся данные из {}.", self.path));
+                ui.horizontal(|ui| {
+                    if ui.button("Заменить!").clicked() {
+                        self.confirm_replace = false;
+                        self.restore(db, RestoreMode::Replace);
+                    }
+                    if ui.button("Отмена").clicked() {
+                        self.confirm_replace = false;
+                    }
+                });
+            });
+    }
+    fn restore(&mut self, db: &Db, mode: RestoreMode) {
+        log::info!("Восстанавливаем резервную копию из {}", self.path);
+        self.start(Action::Restore, db.restore(PathBuf::from(&self.path), mode));
+    }
+    pub fn drive(&mut self) -> Response {
+        let mut response = Response::None;
+        drive_result_promise!(
+            self.result,
+            Ok(summary) => {
+                self.message = Some(match self.action {
+                    Action::Backup => format!("Копия сохранена: {summary}"),
+                    Action::Restore => {
+                        response = Response::Restored;
+                        format!("Копия восстановлена: {summary}")
+                    }
+                });
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+        response
+    }
+    fn start(&mut self, action: Action, promise: PromiseLite<Result<Summary, backup::Error>>) {
+        self.message = None;
+        self.action = action;
+        self.result = Some(promise);
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = err.to_string();
+        log::error!("{message}");
+        self.error_message = Some(message);
+    }
+}
@@ -1,80 +1,254 @@
+mod create;
+mod detail;
 use std::collections::BTreeMap;
 
-use tokio_postgres::Error;
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
 
 use crate::{
-    app::{drive_result_promise, main_page::option_to_string},
-    db::{Db, scheme::BalanceRow},
+    app::{
+        drive_result_promise,
+        main_page::{
+            layout::{Columns, Layout},
+            option_to_string, sort,
+            totals::Totals,
+        },
+    },
+    db::{
+        Db,
+        balance::Error,
+        scheme::{ArticlesRow, BalanceRow},
+    },
     promise_lite::PromiseLite,
 };
 pub struct State {
     table: Option<BTreeMap<i32, BalanceRow>>,
+    /// id в порядке показа и сортировка, по которой он посчитан
+    order: Vec<i32>,
+    sorted_by: Option<sort::Order<Column>>,
+    /// Открытое окно формирования баланса
+    create: Option<create::Create>,
+    /// Открытая выписка по балансу
+    detail: Option<detail::Detail>,
     error_message: Option<String>,
     result: Option<PromiseLite<Result<BTreeMap<i32, BalanceRow>, Error>>>,
+    /// Балансы сформированы или расформированы, операции пора перечитать
+    changed: bool,
+}
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Column {
+    #[strum(serialize = "id")]
+    Id,
+    #[strum(serialize = "create_date")]
+    CreateDate,
+    #[strum(serialize = "debit")]
+    Debit,
+    #[strum(serialize = "credit")]
+    Credit,
+    #[strum(serialize = "amount")]
+    Amount,
+}
+const COLUMNS: [Column; 5] = [
+    Column::Id,
+    Column::CreateDate,
+    Column::Debit,
+    Column::Credit,
+    Column::Amount,
+];
+impl Columns for Column {
+    const ALL: &'static [Self] = &COLUMNS;
 }
 impl State {
     pub fn new(db: &Db) -> Self {
         Self {
             table: None,
+            order: Vec::new(),
+            sorted_by: None,
+            create: None,
+            detail: None,
             error_message: None,
             result: Some(db.select_from_balance()),
+            changed: false,
         }
     }
-    pub fn view(&mut self, ui: &mut egui::Ui, db: &Db) {
+    pub fn view(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        articles: Option<&BTreeMap<i32, ArticlesRow>>,
+        (sort, layout): (&mut sort::Order<Column>, &mut Layout<Column>),
+    ) {
         ui.heading("Статьи");
         let enabled = self.result.is_none();
-        if let Some(table) = &mut self.table {
-            let headers = ["id", "create_date", "debit", "credit", "amount"];
-            egui::containers::ScrollArea::new([true, true]).show(ui, |ui| {
-                egui::Grid::new("Balance")
-                    .num_columns(headers.len())
-                    .show(ui, |ui| {
-                        for header in headers.iter() {
-                            ui.strong(*header);
+        if self.sorted_by.as_ref() != Some(sort) {
+            self.sort(sort);
+        }
+        if let Some(table) = &self.table {
+            let row_height = ui.spacing().interact_size.y;
+            // Итоги и кнопки остаются под таблицей
+            let max_height = ui.available_height() - (3.0 + Totals::ROWS) * row_height;
+            let columns = layout.visible();
+            let selected = self.detail.as_ref().map(detail::Detail::id);
+            let mut clicked = None;
+            let builder = egui_extras::TableBuilder::new(ui)
+                .sense(egui::Sense::click())
+                .striped(true)
+                .resizable(true)
+                .max_scroll_height(max_height)
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center));
+            layout
+                .columns(builder, "Balance")
+                .column(egui_extras::Column::remainder().at_least(30.0))
+                .header(row_height, |mut header| {
+                    layout.header(&mut header, sort);
+                })
+                .body(|body| {
+                    body.rows(row_height, self.order.len(), |mut table_row| {
+                        let Some((id, row)) = self
+                            .order
+                            .get(table_row.index())
+                            .and_then(|id| table.get_key_value(id))
+                        else {
+                            return;
+                        };
+                        table_row.set_selected(selected == Some(*id));
+                        for column in &columns {
+                            let cell = match column {
+                                Column::Id => id.to_string(),
+                                Column::CreateDate => option_to_string(row.create_date.as_ref()),
+                                Column::Debit => option_to_string(row.debit.as_ref()),
+                                Column::Credit => option_to_string(row.credit.as_ref()),
+                                Column::Amount => option_to_string(row.amount.as_ref()),
+                            };
+                            table_row.col(|ui| {
+                                ui.label(cell);
+                            });
                         }
-                        ui.end_row();
-                        for (id, row) in table {
-                            ui.label(id.to_string());
-                            ui.label(option_to_string(row.create_date.as_ref()));
-                            ui.label(option_to_string(row.debit.as_ref()));
-                            ui.label(option_to_string(row.credit.as_ref()));
-                            ui.label(option_to_string(row.amount.as_ref()));
-                            ui.end_row();
+                        if table_row.response().clicked() {
+                            clicked = Some(*id);
                         }
                     });
-            });
+                });
+            if let Some(id) = clicked {
+                self.detail = Some(detail::Detail::new(id, db));
+            }
+            let rows = self.order.iter().filter_map(|id| table.get(id));
+            Totals::new(rows.map(|row| (row.debit, row.credit))).show(ui, "Balance");
         }
         ui.horizontal(|ui| {
-            let create = egui::Button::new("Сформировать!");
-            if ui.add_enabled(enabled, create).clicked() {
-                self.result = Some(db.create_balance());
+            let create = egui::Button::new("Сформировать…");
+            if ui
+                .add_enabled(enabled && self.create.is_none(), create)
+                .clicked()
+            {
+                self.create = Some(create::Create::new(db));
             }
             let remove = egui::Button::new("Расформировать!");
             if ui.add_enabled(enabled, remove).clicked() {
-                self.result = Some(db.remove_balance())
+                self.changed = true;
+                self.result = Some(db.remove_balance());
             }
         });
         let reload = egui::Button::new("Перезагрузить!");
         if ui.add_enabled(enabled, reload).clicked() {
-            self.result = Some(db.select_from_balance())
+            self.reload(db);
         }
         if let Some(error) = &self.error_message {
             ui.colored_label(egui::Color32::RED, error);
         }
+        self.create_window(ui.ctx(), db, enabled);
+        self.detail_window(ui.ctx(), articles);
+    }
+    fn detail_window(
+        &mut self,
+        ctx: &egui::Context,
+        articles: Option<&BTreeMap<i32, ArticlesRow>>,
+    ) {
+        let Some(detail) = &mut self.detail else {
+            return;
+        };
+        // Расформированный баланс закрывает и свою выписку
+        let Some(balance) = self.table.as_ref().and_then(|t| t.get(&detail.id())) else {
+            self.detail = None;
+            return;
+        };
+        let empty = BTreeMap::new();
+        if !detail.show(ctx, balance, articles.unwrap_or(&empty)) {
+            self.detail = None;
+        }
+    }
+    fn create_window(&mut self, ctx: &egui::Context, db: &Db, enabled: bool) {
+        let Some(create) = &mut self.create else {
+            return;
+        };
+        match create.show(ctx, db, enabled) {
+            Some(create::Action::Confirm(cutoff)) => {
+                log::info!("Формируем баланс по {cutoff:?}");
+                self.changed = true;
+                self.result = Some(db.create_balance(cutoff));
+            }
+            Some(create::Action::Cancel) => self.create = None,
+            None => {}
+        }
+    }
+    /// Перечитывает балансы. Если ответа базы ещё ждём, он и так принесёт свежие суммы.
+    pub fn reload(&mut self, db: &Db) {
+        if self.is_idle() {
+            self.result = Some(db.select_from_balance());
+        }
+    }
+    pub fn is_idle(&self) -> bool {
+        self.result.is_none()
+    }
+    /// Балансы сформированы или расформированы, операции пора перечитать
+    pub fn take_changed(&mut self) -> bool {
+        self.is_idle() && std::mem::take(&mut self.changed)
     }
     pub fn drive(&mut self) {
         drive_result_promise!(
             self.result,
             Ok(values) => {
                 self.table = Some(values);
+                self.sorted_by = None;
+                // Перечитывание после чужих изменений не закрывает окно формирования
+                if self.changed {
+                    self.create = None;
+                }
                 self.error_message = None;
             },
-            Err(err) => self.set_err(err),
+            Err(err) => {
+                self.changed = false;
+                self.set_err(err);
+            },
         );
+        if let Some(create) = &mut self.create {
+            create.drive();
+        }
+        if let Some(detail) = &mut self.detail {
+            detail.drive();
+        }
+    }
+    pub fn table(&self) -> Option<&BTreeMap<i32, BalanceRow>> {
+        self.table.as_ref()
+    }
+    fn sort(&mut self, sort: &sort::Order<Column>) {
+        let Some(table) = &self.table else {
+            return;
+        };
+        let mut rows: Vec<_> = table.iter().collect();
+        sort.sort(&mut rows, |column, (a_id, a), (b_id, b)| match column {
+            Column::Id => sort::nullable(Some(a_id), Some(b_id)),
+            Column::CreateDate => sort::nullable(a.create_date, b.create_date),
+            Column::Debit => sort::nullable(a.debit, b.debit),
+            Column::Credit => sort::nullable(a.credit, b.credit),
+            Column::Amount => sort::nullable(a.amount, b.amount),
+        });
+        self.order = rows.into_iter().map(|(id, _)| *id).collect();
+        self.sorted_by = Some(sort.clone());
     }
     fn set_err(&mut self, err: impl std::error::Error) {
-        let message = format!("{err:?}");
-        log::error!("{}", message);
+        let message = err.to_string();
+        log::error!("{message}");
         self.error_message = Some(message);
     }
 }
@@ -0,0 +1,167 @@
+use chrono::{Days, Local, Months, NaiveDate};
+use tokio_postgres::Error;
+
+use crate::{
+    app::{
+        drive_result_promise,
+        main_page::{
+            budgets::{current_month, month_name},
+            option_to_string,
+        },
+    },
+    db::{Db, scheme::BalancePreview},
+    promise_lite::PromiseLite,
+};
+
+/// Формирование баланса: до какой даты брать операции и что в него войдёт
+pub struct Create {
+    choice: Choice,
+    date: NaiveDate,
+    /// Первое число месяца, конец которого станет границей
+    month: NaiveDate,
+    /// Граница, для которой посчитан или считается предпросмотр
+    previewed: Option<NaiveDate>,
+    preview: Option<BalancePreview>,
+    result: Option<PromiseLite<Result<BalancePreview, Error>>>,
+    error_message: Option<String>,
+}
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    All,
+    Date,
+    Month,
+}
+pub enum Action {
+    Confirm(Option<NaiveDate>),
+    Cancel,
+}
+impl Create {
+    pub fn new(db: &Db) -> Self {
+        Self {
+            choice: Choice::All,
+            date: Local::now().date_naive(),
+            // Обычно закрывают прошедший месяц
+            month: current_month() - Months::new(1),
+            previewed: None,
+            preview: None,
+            result: Some(db.balance_preview(None)),
+            error_message: None,
+        }
+    }
+    /// Последний день, операции которого попадут в баланс
+    fn cutoff(&self) -> Option<NaiveDate> {
+        match self.choice {
+            Choice::All => None,
+            Choice::Date => Some(self.date),
+            Choice::Month => (self.month + Months::new(1)).checked_sub_days(Days::new(1)),
+        }
+    }
+    pub fn show(&mut self, ctx: &egui::Context, db: &Db, enabled: bool) -> Option<Action> {
+        let mut action = None;
+        egui::Window::new("Формирование баланса")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("Какие свободные операции закрыть?");
+                ui.radio_value(&mut self.choice, Choice::All, "Все");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.choice, Choice::Date, "По дату включительно");
+                    ui.add_enabled_ui(self.choice == Choice::Date, |ui| {
+                        ui.add(
+                            egui_extras::DatePickerButton::new(&mut self.date)
+                                .id_salt("balance cutoff"),
+                        );
+                    });
+                });
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.choice, Choice::Month, "По конец месяца");
+                    ui.add_enabled_ui(self.choice == Choice::Month, |ui| {
+                        if ui.button("◀").clicked() {
+                            self.month = self.month - Months::new(1);
+                        }
+                        ui.strong(month_name(self.month));
+                        if ui.button("▶").clicked() {
+                            self.month = self.month + Months::new(1);
+                        }
+                    });
+                });
+                let cutoff = self.cutoff();
+                if self.previewed != cutoff {
+                    self.previewed = cutoff;
+                    self.preview = None;
+                    self.result = Some(db.balance_preview(cutoff));
+                }
+                ui.separator();
+                self.preview(ui);
+                if let Some(error) = &self.error_message {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+                ui.horizontal(|ui| {
+                    let ready = self.result.is_none()
+                        && self.preview.as_ref().is_some_and(|p| p.included.count > 0);
+                    let confirm = egui::Button::new("Сформировать!");
+                    if ui.add_enabled(enabled && ready, confirm).clicked() {
+                        action = Some(Action::Confirm(cutoff));
+                    }
+                    if ui.button("Отмена").clicked() {
+                        action = Some(Action::Cancel);
+                    }
+                });
+            });
+        action
+    }
+    /// Итоги операций, которые войдут в баланс
+    fn preview(&self, ui: &mut egui::Ui) {
+        let Some(preview) = &self.preview else {
+            ui.spinner();
+            return;
+        };
+        let included = &preview.included;
+        if included.count == 0 {
+            ui.label("Подходящих операций нет");
+        } else {
+            egui::Grid::new("balance preview").show(ui, |ui| {
+                ui.label("Операций");
+                ui.label(included.count.to_string());
+                ui.end_row();
+                ui.label("Период");
+                ui.label(format!(
+                    "{} — {}",
+                    option_to_string(included.first.as_ref()),
+                    option_to_string(included.last.as_ref())
+                ));
+                ui.end_row();
+                ui.label("Доходы");
+                ui.label(included.debit.to_string());
+                ui.end_row();
+                ui.label("Расходы");
+                ui.label(included.credit.to_string());
+                ui.end_row();
+                ui.label("Итог");
+                ui.strong((included.debit - included.credit).to_string());
+                ui.end_row();
+            });
+        }
+        if preview.left_out > 0 {
+            ui.weak(format!(
+                "Останутся вне баланса операций: {}",
+                preview.left_out
+            ));
+        }
+    }
+    pub fn drive(&mut self) {
+        drive_result_promise!(
+            self.result,
+            Ok(preview) => {
+                self.preview = Some(preview);
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = format!("{err:?}");
+        log::error!("{}", message);
+        self.error_message = Some(message);
+    }
+}
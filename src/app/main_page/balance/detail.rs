@@ -0,0 +1,181 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use tokio_postgres::Error;
+
+use crate::{
+    app::{
+        drive_result_promise,
+        main_page::{option_to_string, palette},
+    },
+    db::{
+        Db,
+        scheme::{ArticlesRow, BalanceRow, StatementLine},
+    },
+    promise_lite::PromiseLite,
+    statement::{Group, Statement},
+};
+
+/// Выписка по балансу: операции по статьям с итогами и сохранение в файл
+pub struct Detail {
+    id: i32,
+    /// Строки из базы, пока из них не собрана выписка
+    lines: Option<Vec<StatementLine>>,
+    statement: Option<Statement>,
+    path: String,
+    message: Option<String>,
+    error_message: Option<String>,
+    result: Option<PromiseLite<Result<Vec<StatementLine>, Error>>>,
+    save_result: Option<PromiseLite<Result<(), std::io::Error>>>,
+}
+impl Detail {
+    pub fn new(id: i32, db: &Db) -> Self {
+        Self {
+            id,
+            lines: None,
+            statement: None,
+            path: format!("statement-{id}.html"),
+            message: None,
+            error_message: None,
+            result: Some(db.select_balance_lines(id)),
+            save_result: None,
+        }
+    }
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+    /// Возвращает `false`, когда окно закрыли
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        balance: &BalanceRow,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) -> bool {
+        if let Some(lines) = self.lines.take() {
+            self.statement = Some(Statement::new(self.id, balance.clone(), lines, articles));
+        }
+        let mut open = true;
+        egui::Window::new(format!("Баланс №{}", self.id))
+            .open(&mut open)
+            .default_width(600.0)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Сформирован {}",
+                    option_to_string(balance.create_date.as_ref())
+                ));
+                let Some(statement) = &self.statement else {
+                    ui.spinner();
+                    self.errors(ui);
+                    return;
+                };
+                let max_height = ui.available_height() - 6.0 * ui.spacing().interact_size.y;
+                egui::ScrollArea::vertical()
+                    .max_height(max_height)
+                    .show(ui, |ui| {
+                        for group in &statement.groups {
+                            group_view(ui, group, articles);
+                        }
+                    });
+                ui.separator();
+                ui.strong(format!(
+                    "Операций: {}, доходы {}, расходы {}, итог {}",
+                    statement.count(),
+                    statement.debit(),
+                    statement.credit(),
+                    statement.debit() - statement.credit()
+                ));
+                self.export(ui);
+            });
+        open
+    }
+    fn export(&mut self, ui: &mut egui::Ui) {
+        let enabled = self.save_result.is_none();
+        ui.horizontal(|ui| {
+            ui.label("Файл:");
+            ui.add_enabled(enabled, egui::TextEdit::singleline(&mut self.path));
+        });
+        let save = egui::Button::new("Сохранить выписку!");
+        if ui.add_enabled(enabled, save).clicked()
+            && let Some(statement) = self.statement.clone()
+        {
+            log::info!("Сохраняем выписку в {}", self.path);
+            let path = PathBuf::from(&self.path);
+            let ctx = ui.ctx().clone();
+            self.message = None;
+            self.save_result = Some(PromiseLite::spawn(async move {
+                let res = statement.save(&path).await;
+                ctx.request_repaint();
+                res
+            }));
+        }
+        self.errors(ui);
+    }
+    fn errors(&self, ui: &mut egui::Ui) {
+        if let Some(message) = &self.message {
+            ui.label(message);
+        }
+        if let Some(error) = &self.error_message {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+    pub fn drive(&mut self) {
+        drive_result_promise!(
+            self.result,
+            Ok(lines) => {
+                self.lines = Some(lines);
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+        drive_result_promise!(
+            self.save_result,
+            Ok(_saved) => {
+                self.message = Some(format!("Выписка сохранена в {}", self.path));
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = err.to_string();
+        log::error!("{message}");
+        self.error_message = Some(message);
+    }
+}
+/// Статья с итогами в заголовке, её операции раскрываются по щелчку
+fn group_view(ui: &mut egui::Ui, group: &Group, articles: &BTreeMap<i32, ArticlesRow>) {
+    let id = ui.make_persistent_id(("balance group", group.article_id));
+    egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+        .show_header(ui, |ui| {
+            if let Some(article_id) = group.article_id {
+                ui.colored_label(palette::article_color(article_id, articles), "●");
+                ui.strong(palette::path(article_id, articles));
+            } else {
+                ui.strong(&group.name);
+            }
+            ui.label(format!(
+                "доходы {}, расходы {}, итог {}",
+                group.debit,
+                group.credit,
+                group.debit - group.credit
+            ));
+        })
+        .body(|ui| {
+            egui::Grid::new(id.with("lines"))
+                .striped(true)
+                .show(ui, |ui| {
+                    for title in ["id", "Дата", "Описание", "Доход", "Расход"]
+                    {
+                        ui.strong(title);
+                    }
+                    ui.end_row();
+                    for line in &group.lines {
+                        ui.label(line.operation_id.to_string());
+                        ui.label(option_to_string(line.create_date.as_ref()));
+                        ui.label(line.description.as_deref().unwrap_or_default());
+                        ui.label(option_to_string(line.debit.as_ref()));
+                        ui.label(option_to_string(line.credit.as_ref()));
+                        ui.end_row();
+                    }
+                });
+        });
+}
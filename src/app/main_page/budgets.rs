@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike as _, Local, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Error;
+
+use crate::{
+    app::{
+        drive_result_promise, icons,
+        main_page::{option_to_string, palette},
+    },
+    db::{
+        Db,
+        hierarchy::Tree,
+        scheme::{ArticlesRow, BudgetLine},
+    },
+    promise_lite::PromiseLite,
+};
+
+/// С какой доли бюджета, в процентах, на главной странице появляется предупреждение
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Threshold(pub u8);
+impl Default for Threshold {
+    fn default() -> Self {
+        Self(80)
+    }
+}
+
+pub struct State {
+    /// Первое число показанного месяца
+    month: NaiveDate,
+    lines: Option<BTreeMap<i32, BudgetLine>>,
+    /// Статья, бюджет которой редактируется, и новая сумма
+    edited: Option<(i32, i32)>,
+    error_message: Option<String>,
+    result: Option<PromiseLite<Result<BTreeMap<i32, BudgetLine>, Error>>>,
+    /// Текущий месяц для предупреждений, что бы ни было открыто в таблице
+    current: Option<BTreeMap<i32, BudgetLine>>,
+    current_result: Option<PromiseLite<Result<BTreeMap<i32, BudgetLine>, Error>>>,
+}
+/// Статья, потратившая больше заданной доли бюджета
+pub struct Alert {
+    article_id: i32,
+    planned: i32,
+    actual: i64,
+    percent: f64,
+}
+impl State {
+    pub fn new(db: &Db) -> Self {
+        let month = current_month();
+        Self {
+            month,
+            lines: None,
+            edited: None,
+            error_message: None,
+            result: Some(db.select_budget(month)),
+            current: None,
+            current_result: Some(db.select_budget(month)),
+        }
+    }
+    pub fn view(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        articles: Option<&BTreeMap<i32, ArticlesRow>>,
+        threshold: &mut Threshold,
+    ) {
+        ui.heading("Бюджеты");
+        let enabled = self.result.is_none();
+        ui.add_enabled_ui(enabled && self.edited.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                let mut month = self.month;
+                if ui.button("◀").clicked() {
+                    month = month - Months::new(1);
+                }
+                ui.strong(month_name(month));
+                if ui.button("▶").clicked() {
+                    month = month + Months::new(1);
+                }
+                if ui.button("Текущий месяц").clicked() {
+                    month = current_month();
+                }
+                if month != self.month {
+                    self.month = month;
+                    self.lines = None;
+                    self.result = Some(db.select_budget(month));
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Предупреждать, когда потрачено");
+            ui.add(
+                egui::DragValue::new(&mut threshold.0)
+                    .range(1..=100)
+                    .suffix("%"),
+            );
+            ui.label("бюджета");
+        });
+        let empty = BTreeMap::new();
+        let articles = articles.unwrap_or(&empty);
+        if let Some((article_id, amount)) = self.table(ui, enabled, articles, *threshold) {
+            self.result = Some(db.set_budget(self.month, article_id, amount));
+        }
+        let reload = egui::Button::new("Перезагрузить!");
+        if ui.add_enabled(enabled, reload).clicked() {
+            self.result = Some(db.select_budget(self.month));
+            self.refresh_alerts(db);
+        }
+        if let Some(error) = &self.error_message {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+    /// Статьи деревом: план, факт, остаток и доля потраченного.
+    /// Возвращает новый бюджет статьи, если его изменили.
+    fn table(
+        &mut self,
+        ui: &mut egui::Ui,
+        enabled: bool,
+        articles: &BTreeMap<i32, ArticlesRow>,
+        threshold: Threshold,
+    ) -> Option<(i32, Option<i32>)> {
+        let Self { lines, edited, .. } = self;
+        let lines = lines.as_ref()?;
+        let tree = Tree::new(articles);
+        // Архивные статьи — только если по ним есть план или расходы
+        let rows: Vec<_> = tree
+            .depth_first()
+            .into_iter()
+            .filter(|(id, _)| {
+                !tree.is_archived(*id)
+                    || lines
+                        .get(id)
+                        .is_some_and(|l| l.planned.is_some() || l.actual > 0)
+            })
+            .collect();
+        let row_height = ui.spacing().interact_size.y;
+        let max_height = ui.available_height() - 3.0 * row_height;
+        let mut response = None;
+        egui_extras::TableBuilder::new(ui)
+            .id_salt("Budgets")
+            .striped(true)
+            .resizable(true)
+            .max_scroll_height(max_height)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(
+                egui_extras::Column::initial(240.0)
+                    .at_least(30.0)
+                    .clip(true),
+            )
+            .columns(egui_extras::Column::initial(100.0).at_least(30.0), 3)
+            .column(egui_extras::Column::initial(160.0).at_least(60.0))
+            .column(egui_extras::Column::remainder().at_least(60.0))
+            .header(row_height, |mut header| {
+                for title in ["Статья", "План", "Факт", "Остаток", "Потрачено", "Бюджет"]
+                {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(row_height, rows.len(), |mut table_row| {
+                    let Some((id, depth)) = rows.get(table_row.index()).copied() else {
+                        return;
+                    };
+                    let line = lines.get(&id).cloned().unwrap_or_default();
+                    table_row.col(|ui| {
+                        ui.add_space(depth as f32 * ui.spacing().indent);
+                        ui.colored_label(palette::article_color(id, articles), "●");
+                        ui.label(palette::label(id, articles));
+                    });
+                    table_row.col(|ui| match edited {
+                        Some((edited, amount)) if *edited == id => {
+                            ui.add(egui::DragValue::new(amount).range(0..=i32::MAX));
+                        }
+                        _ => {
+                            ui.label(option_to_string(line.planned.as_ref()));
+                        }
+                    });
+                    table_row.col(|ui| {
+                        ui.label(line.actual.to_string());
+                    });
+                    table_row.col(|ui| {
+                        if let Some(planned) = line.planned {
+                            ui.label((i64::from(planned) - line.actual).to_string());
+                        }
+                    });
+                    table_row.col(|ui| progress(ui, &line, threshold));
+                    table_row.col(|ui| {
+                        if let Some(changed) = buttons(ui, edited, (id, &line), enabled) {
+                            response = Some(changed);
+                        }
+                    });
+                });
+            });
+        response
+    }
+    /// Перечитывает текущий месяц, например после изменения операций
+    pub fn refresh_alerts(&mut self, db: &Db) {
+        self.current_result = Some(db.select_budget(current_month()));
+    }
+    /// Статьи текущего месяца, потратившие не меньше `threshold` бюджета
+    pub fn alerts(&self, threshold: Threshold) -> Vec<Alert> {
+        let Some(current) = &self.current else {
+            return Vec::new();
+        };
+        current
+            .iter()
+            .filter_map(|(article_id, line)| {
+                let percent = line.spent_percent()?;
+                (percent >= f64::from(threshold.0)).then_some(Alert {
+                    article_id: *article_id,
+                    planned: line.planned?,
+                    actual: line.actual,
+                    percent,
+                })
+            })
+            .collect()
+    }
+    pub fn drive(&mut self) {
+        drive_result_promise!(
+            self.result,
+            Ok(lines) => {
+                if self.month == current_month() {
+                    self.current = Some(lines.clone());
+                }
+                self.lines = Some(lines);
+                self.edited = None;
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+        drive_result_promise!(
+            self.current_result,
+            Ok(lines) => self.current = Some(lines),
+            Err(err) => self.set_err(err),
+        );
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = format!("{err:?}");
+        log::error!("{}", message);
+        self.error_message = Some(message);
+    }
+}
+impl Alert {
+    /// Строка предупреждения, например «Продукты: 9000 из 10000 (90%)»
+    pub fn show(&self, ui: &mut egui::Ui, articles: &BTreeMap<i32, ArticlesRow>) -> bool {
+        let color = if self.percent > 100.0 {
+            ui.visuals().error_fg_color
+        } else {
+            ui.visuals().warn_fg_color
+        };
+        let text = format!(
+            "⚠ {}: {} из {} ({:.0}%)",
+            palette::label(self.article_id, articles),
+            self.actual,
+            self.planned,
+            self.percent
+        );
+        ui.add(egui::Label::new(egui::RichText::new(text).color(color)).sense(egui::Sense::click()))
+            .on_hover_text("Открыть бюджеты")
+            .clicked()
+    }
+}
+/// Кнопки строки: задать или убрать бюджет, подтвердить или отменить правку
+fn buttons(
+    ui: &mut egui::Ui,
+    edited: &mut Option<(i32, i32)>,
+    (id, line): (i32, &BudgetLine),
+    enabled: bool,
+) -> Option<(i32, Option<i32>)> {
+    let mut response = None;
+    match *edited {
+        Some((edited_id, amount)) if edited_id == id => {
+            let confirm = egui::Button::new(icons::CONFIRM).small();
+            let cancel = egui::Button::new(icons::CANCEL).small();
+            if ui.add_enabled(enabled, confirm).clicked() {
+                response = Some((id, Some(amount)));
+            }
+            if ui.add_enabled(enabled, cancel).clicked() {
+                *edited = None;
+            }
+        }
+        _ => {
+            let regular = enabled && edited.is_none();
+            let edit = egui::Button::new(icons::EDIT).small();
+            if ui
+                .add_enabled(regular, edit)
+                .on_hover_text("Задать бюджет")
+                .clicked()
+            {
+                *edited = Some((id, line.planned.unwrap_or_default()));
+            }
+            let remove = egui::Button::new(icons::REMOVE).small();
+            if ui
+                .add_enabled(regular && line.planned.is_some(), remove)
+                .on_hover_text("Убрать бюджет")
+                .clicked()
+            {
+                response = Some((id, None));
+            }
+        }
+    }
+    response
+}
+/// Полоса с долей потраченного. Перерасход — красным, близость к нему — жёлтым.
+fn progress(ui: &mut egui::Ui, line: &BudgetLine, threshold: Threshold) {
+    let Some(percent) = line.spent_percent() else {
+        return;
+    };
+    let fill = if percent > 100.0 {
+        ui.visuals().error_fg_color
+    } else if percent >= f64::from(threshold.0) {
+        ui.visuals().warn_fg_color
+    } else {
+        ui.visuals().selection.bg_fill
+    };
+    ui.add(
+        egui::ProgressBar::new((percent / 100.0) as f32)
+            .fill(fill)
+            .text(format!("{percent:.0}%")),
+    );
+}
+pub fn current_month() -> NaiveDate {
+    let today = Local::now().date_naive();
+    today.with_day(1).unwrap_or(today)
+}
+pub fn month_name(month: NaiveDate) -> String {
+    const NAMES: [&str; 12] = [
+        "Январь",
+        "Февраль",
+        "Март",
+        "Апрель",
+        "Май",
+        "Июнь",
+        "Июль",
+        "Август",
+        "Сентябрь",
+        "Октябрь",
+        "Ноябрь",
+        "Декабрь",
+    ];
+    let name = NAMES
+        .get(month.month0() as usize)
+        .copied()
+        .unwrap_or_default();
+    format!("{name} {}", month.year())
+}
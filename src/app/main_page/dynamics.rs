@@ -1,30 +1,55 @@
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
+use super::{export, palette};
 use crate::{
-    app::{drive_result_promise, main_page::option_to_string},
+    app::drive_result_promise,
+    chart::{Axis, Chart, Kind, Series},
     db::{
-        Db,
+        Db, Grouping,
+        hierarchy::Tree,
         scheme::{ArticlesRow, DynamicsPoint},
     },
     promise_lite::PromiseLite,
 };
 use chrono::{DateTime, Local, NaiveDate, NaiveTime};
 use egui::Color32;
-use egui_plot::PlotPoints;
+use egui_plot::{LineStyle, PlotPoints};
 use tokio_postgres::Error;
 pub struct State {
     start: NaiveDate,
     end: NaiveDate,
+    grouping: Grouping,
     chosen_articles: HashSet<i32>,
-    values: Option<Points>,
+    chosen_tags: HashSet<String>,
+    values: Option<Vec<Points>>,
     error_message: Option<String>,
     result: Option<PromiseLite<Result<Vec<DynamicsPoint>, Error>>>,
+    export: export::State,
 }
+/// Доходы и расходы одной статьи или метки
 #[derive(Clone)]
 struct Points {
+    article_id: Option<i32>,
+    tag: Option<String>,
     debits: Vec<egui_plot::PlotPoint>,
     credits: Vec<egui_plot::PlotPoint>,
 }
+impl Points {
+    /// Подпись и цвет линий
+    fn style(&self, articles: &BTreeMap<i32, ArticlesRow>) -> (String, Color32) {
+        match (self.article_id, &self.tag) {
+            (Some(id), _) => (
+                palette::label(id, articles),
+                palette::article_color(id, articles),
+            ),
+            (None, tag) => {
+                let tag = tag.clone().unwrap_or_default();
+                let color = palette::tag_color(&tag);
+                (tag, color)
+            }
+        }
+    }
+}
 impl State {
     pub fn new() -> Self {
         let now = Local::now()
@@ -34,10 +59,13 @@ impl State {
         Self {
             start: now,
             end: now,
+            grouping: Grouping::default(),
             chosen_articles: HashSet::new(),
+            chosen_tags: HashSet::new(),
             values: None,
             error_message: None,
             result: None,
+            export: export::State::new("dynamics"),
         }
     }
     pub fn view(
@@ -45,16 +73,25 @@ impl State {
         ui: &mut egui::Ui,
         db: &Db,
         articles: Option<&BTreeMap<i32, ArticlesRow>>,
+        tags: Option<&BTreeSet<String>>,
     ) {
         ui.heading("Прибыль");
         let enabled = self.result.is_none();
-        if let Some(articles) = articles {
+        ui.horizontal(|ui| {
+            for grouping in [Grouping::Articles, Grouping::Tags] {
+                ui.radio_value(&mut self.grouping, grouping, <&str>::from(grouping));
+            }
+        });
+        if let (Some(articles), Some(tags)) = (articles, tags) {
             egui::containers::ScrollArea::new([true, true]).show(ui, |ui| {
                 let size = ui.available_height();
                 ui.horizontal(|ui| {
-                    Self::table(ui, articles, &mut self.chosen_articles);
+                    match self.grouping {
+                        Grouping::Articles => Self::table(ui, articles, &mut self.chosen_articles),
+                        Grouping::Tags => Self::tags_table(ui, tags, &mut self.chosen_tags),
+                    }
                     if let Some(values) = &self.values {
-                        Self::plot(ui, values.clone(), size / 2.0);
+                        Self::plot(ui, values, articles, size / 2.0);
                     }
                 })
             });
@@ -66,7 +103,9 @@ impl State {
         let reload = egui::Button::new("Перезагрузить!");
         if ui.add_enabled(enabled, reload).clicked() {
             self.result = Some(db.show_dynamics(
+                self.grouping,
                 self.chosen_articles.iter().copied().collect(),
+                self.chosen_tags.iter().cloned().collect(),
                 self.start,
                 self.end,
             ))
@@ -74,48 +113,115 @@ impl State {
         if let Some(error) = &self.error_message {
             ui.colored_label(egui::Color32::RED, error);
         }
+        self.export.view(ui, || {
+            let articles = articles?;
+            self.values
+                .as_ref()
+                .map(|values| Self::chart(values, articles))
+        });
     }
     pub fn drive(&mut self) {
         drive_result_promise!(
             self.result,
             Ok(values) => {
-                self.values = Some(
-                    Points {
-                        debits: values.iter().map(|v| v.debit).collect(),
-                        credits: values.iter().map(|v| v.credit).collect()
-                    }
-                );
+                self.values = Some(Self::split(values));
                 self.error_message = None;
             },
             Err(err) => self.set_err(err),
         );
+        self.export.drive();
     }
+    /// Статьи деревом. Выбранная статья учитывается вместе с вложенными,
+    /// так что можно смотреть и группу целиком, и отдельную статью внутри неё.
+    /// Архивные статьи показываются, только если уже выбраны.
     fn table(ui: &mut egui::Ui, articles: &BTreeMap<i32, ArticlesRow>, chosen: &mut HashSet<i32>) {
         let header = ["id", "name", "Анализировать"];
+        let tree = Tree::new(articles);
         egui::Grid::new("articles").show(ui, |ui| {
             for label in header {
                 ui.strong(label);
             }
             ui.end_row();
-            for (i, article) in articles.iter() {
+            let rows = tree
+                .depth_first()
+                .into_iter()
+                .filter(|(i, _)| chosen.contains(i) || !tree.is_archived(*i))
+                .collect::<Vec<_>>();
+            for (i, depth) in rows {
                 ui.label(i.to_string());
-                ui.label(option_to_string(article.name.as_ref()));
-                let mut checked = chosen.contains(i);
+                ui.horizontal(|ui| {
+                    ui.add_space(depth as f32 * ui.spacing().indent);
+                    ui.colored_label(palette::article_color(i, articles), "●");
+                    ui.label(palette::label(i, articles));
+                });
+                let mut checked = chosen.contains(&i);
                 if ui.checkbox(&mut checked, "").changed() {
                     if checked {
-                        chosen.insert(*i);
+                        chosen.insert(i);
                     } else {
-                        chosen.remove(i);
+                        chosen.remove(&i);
                     }
                 }
                 ui.end_row();
             }
         });
     }
-    fn plot(ui: &mut egui::Ui, values: Points, size: f32) {
+    fn tags_table(ui: &mut egui::Ui, tags: &BTreeSet<String>, chosen: &mut HashSet<String>) {
+        egui::Grid::new("tags").show(ui, |ui| {
+            ui.strong("Метка");
+            ui.strong("Анализировать");
+            ui.end_row();
+            if tags.is_empty() {
+                ui.label("Меток пока нет");
+                ui.end_row();
+            }
+            for tag in tags {
+                ui.label(tag);
+                let mut checked = chosen.contains(tag);
+                if ui.checkbox(&mut checked, "").changed() {
+                    if checked {
+                        chosen.insert(tag.clone());
+                    } else {
+                        chosen.remove(tag);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    }
+    /// Точки приходят упорядоченными по статье или метке
+    fn split(values: Vec<DynamicsPoint>) -> Vec<Points> {
+        let mut result: Vec<Points> = Vec::new();
+        for point in values {
+            let same = result
+                .last()
+                .is_some_and(|last| last.article_id == point.article_id && last.tag == point.tag);
+            if !same {
+                result.push(Points {
+                    article_id: point.article_id,
+                    tag: point.tag.clone(),
+                    debits: Vec::new(),
+                    credits: Vec::new(),
+                });
+            }
+            if let Some(last) = result.last_mut() {
+                last.debits.push(point.debit);
+                last.credits.push(point.credit);
+            }
+        }
+        result
+    }
+    /// Доходы сплошной линией, расходы пунктиром, цвет — цвет статьи
+    fn plot(
+        ui: &mut egui::Ui,
+        values: &[Points],
+        articles: &BTreeMap<i32, ArticlesRow>,
+        size: f32,
+    ) {
         egui_plot::Plot::new("Profit")
             .height(size)
             .clamp_grid(true)
+            .legend(egui_plot::Legend::default())
             .x_axis_label("Время")
             .x_axis_formatter(|_, _| "".into())
             .y_axis_label("Деньги")
@@ -129,22 +235,45 @@ impl State {
                 )
             })
             .show(ui, |plot_ui| {
-                let debit_color = Color32::RED;
-                let credit_color = Color32::BLUE;
-                let debit = egui_plot::Line::new(
-                    "Прибыль от времени",
-                    PlotPoints::Borrowed(&values.debits),
-                )
-                .color(debit_color);
-                let credit = egui_plot::Line::new(
-                    "Расходы от времени",
-                    PlotPoints::Borrowed(&values.credits),
-                )
-                .color(credit_color);
-                plot_ui.line(debit);
-                plot_ui.line(credit);
+                for points in values {
+                    let (label, color) = points.style(articles);
+                    let debit = egui_plot::Line::new(
+                        format!("{label}: доходы"),
+                        PlotPoints::Borrowed(&points.debits),
+                    )
+                    .color(color);
+                    let credit = egui_plot::Line::new(
+                        format!("{label}: расходы"),
+                        PlotPoints::Borrowed(&points.credits),
+                    )
+                    .color(color)
+                    .style(LineStyle::dashed_loose());
+                    plot_ui.line(debit);
+                    plot_ui.line(credit);
+                }
             });
     }
+    fn chart(values: &[Points], articles: &BTreeMap<i32, ArticlesRow>) -> Chart {
+        let chart = Chart::new("Динамика", Axis::Time)
+            .x_label("Время")
+            .y_label("Деньги");
+        values.iter().fold(chart, |chart, points| {
+            let (label, color) = points.style(articles);
+            chart
+                .series(Series {
+                    name: format!("{label}: доходы"),
+                    color,
+                    kind: Kind::Line,
+                    points: points.debits.clone(),
+                })
+                .series(Series {
+                    name: format!("{label}: расходы"),
+                    color,
+                    kind: Kind::DashedLine,
+                    points: points.credits.clone(),
+                })
+        })
+    }
     fn set_err(&mut self, err: impl std::error::Error) {
         let message = format!("{err:?}");
         log::error!("{}", message);
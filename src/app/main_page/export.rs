@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    app::drive_result_promise,
+    chart::{self, Chart, Format},
+    promise_lite::PromiseLite,
+};
+/// Сохранение графика в файл. Встраивается в страницы индикаторов.
+pub struct State {
+    path: String,
+    format: Format,
+    width: u32,
+    height: u32,
+    message: Option<String>,
+    error_message: Option<String>,
+    result: Option<PromiseLite<Result<(), chart::Error>>>,
+}
+impl State {
+    pub fn new(name: &str) -> Self {
+        let format = Format::default();
+        Self {
+            path: format!("{name}.{}", format.extension()),
+            format,
+            width: 1200,
+            height: 600,
+            message: None,
+            error_message: None,
+            result: None,
+        }
+    }
+    /// `chart` вызывается только при нажатии на кнопку
+    pub fn view(&mut self, ui: &mut egui::Ui, chart: impl FnOnce() -> Option<Chart>) {
+        let enabled = self.result.is_none();
+        ui.collapsing("Сохранить график", |ui| {
+            ui.horizontal(|ui| {
+                let svg = ui.radio_value(&mut self.format, Format::Svg, "SVG");
+                let png = ui.radio_value(&mut self.format, Format::Png, "PNG");
+                if svg.changed() || png.changed() {
+                    self.path = Path::new(&self.path)
+                        .with_extension(self.format.extension())
+                        .to_string_lossy()
+                        .into_owned();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Размер:");
+                ui.add(egui::DragValue::new(&mut self.width).range(100..=8000));
+                ui.label("x");
+                ui.add(egui::DragValue::new(&mut self.height).range(100..=8000));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Файл:");
+                ui.add_enabled(enabled, egui::TextEdit::singleline(&mut self.path));
+            });
+            let save = egui::Button::new("Сохранить!");
+            if ui.add_enabled(enabled, save).clicked()
+                && let Some(chart) = chart()
+            {
+                log::info!("Сохраняем график в {}", self.path);
+                let (path, format) = (PathBuf::from(&self.path), self.format);
+                let (width, height) = (self.width, self.height);
+                let ctx = ui.ctx().clone();
+                self.message = None;
+                self.result = Some(PromiseLite::spawn(async move {
+                    let res = chart.save(&path, format, width, height).await;
+                    ctx.request_repaint();
+                    res
+                }));
+            }
+            if let Some(message) = &self.message {
+                ui.label(message);
+            }
+            if let Some(error) = &self.error_message {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+    }
+    pub fn drive(&mut self) {
+        drive_result_promise!(
+            self.result,
+            Ok(_saved) => {
+                self.message = Some(format!("График сохранён в {}", self.path));
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = err.to_string();
+        log::error!("{message}");
+        self.error_message = Some(message);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    app::main_page::option_to_string,
+    db::scheme::{ArticlesRow, OperationsRow, SplitRow},
+};
+
+/// Сколько последних изменений помнить
+const MAX_ENTRIES: usize = 100;
+
+/// Изменение одной строки: что было и что стало. `None` — строки нет.
+#[derive(Clone)]
+pub struct Change<T> {
+    pub id: i32,
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+#[derive(Clone)]
+pub enum Entry {
+    /// Вместе со строками разбивки удалённой операции: база удаляет их
+    /// вместе с ней, и отмена удаления должна их вернуть
+    Operation(Change<OperationsRow>, Vec<SplitRow>),
+    Article(Change<ArticlesRow>),
+}
+/// Отменённые изменения можно повторить, пока не сделано новое
+#[derive(Default)]
+pub struct History {
+    done: Vec<Entry>,
+    undone: Vec<Entry>,
+    /// Отмена или повтор, которые база ещё не подтвердила. Запись
+    /// переходит между списками только после подтверждения.
+    in_flight: Option<Action>,
+}
+#[derive(Clone, Copy)]
+pub enum Action {
+    Undo,
+    Redo,
+}
+impl<T> Change<T> {
+    fn reversed(self) -> Self {
+        Self {
+            id: self.id,
+            before: self.after,
+            after: self.before,
+        }
+    }
+    fn verb(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, _) => "добавлена",
+            (_, None) => "удалена",
+            _ => "изменена",
+        }
+    }
+}
+impl Entry {
+    fn reversed(self) -> Self {
+        match self {
+            Self::Operation(change, splits) => Self::Operation(change.reversed(), splits),
+            Self::Article(change) => Self::Article(change.reversed()),
+        }
+    }
+    fn describe(&self) -> String {
+        match self {
+            Self::Operation(change, _) => format!("Операция №{} {}", change.id, change.verb()),
+            Self::Article(change) => {
+                let name = change
+                    .after
+                    .as_ref()
+                    .or(change.before.as_ref())
+                    .and_then(|row| row.name.as_ref());
+                format!(
+                    "Статья №{} «{}» {}",
+                    change.id,
+                    option_to_string(name),
+                    change.verb()
+                )
+            }
+        }
+    }
+}
+impl History {
+    pub fn record(&mut self, entry: Entry) {
+        self.done.push(entry);
+        if self.done.len() > MAX_ENTRIES {
+            self.done.remove(0);
+        }
+        self.undone.clear();
+    }
+    /// Возвращает изменение, которое нужно применить к базе для отмены.
+    /// Результат нужно сообщить через [`Self::finish`].
+    pub fn undo(&mut self) -> Option<Entry> {
+        if !self.can_undo() {
+            return None;
+        }
+        let entry = self.done.last()?.clone();
+        self.in_flight = Some(Action::Undo);
+        Some(entry.reversed())
+    }
+    pub fn redo(&mut self) -> Option<Entry> {
+        if !self.can_redo() {
+            return None;
+        }
+        let entry = self.undone.last()?.clone();
+        self.in_flight = Some(Action::Redo);
+        Some(entry)
+    }
+    /// База применила (`applied`) или отвергла изменение из отмены или повтора.
+    /// Отвергнутое остаётся на своём месте, его можно попробовать ещё раз.
+    pub fn finish(&mut self, applied: bool) {
+        match (self.in_flight.take(), applied) {
+            (Some(Action::Undo), true) => {
+                if let Some(entry) = self.done.pop() {
+                    self.undone.push(entry);
+                }
+            }
+            (Some(Action::Redo), true) => {
+                if let Some(entry) = self.undone.pop() {
+                    self.done.push(entry);
+                }
+            }
+            _ => {}
+        }
+    }
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.undone.clear();
+        self.in_flight = None;
+    }
+    pub fn can_undo(&self) -> bool {
+        self.in_flight.is_none() && !self.done.is_empty()
+    }
+    pub fn can_redo(&self) -> bool {
+        self.in_flight.is_none() && !self.undone.is_empty()
+    }
+    pub fn view(&self, ui: &mut egui::Ui, enabled: bool) -> Option<Action> {
+        ui.heading("История");
+        let mut action = None;
+        ui.horizontal(|ui| {
+            let undo = egui::Button::new("Отменить").shortcut_text(ui.ctx().format_shortcut(&UNDO));
+            if ui.add_enabled(enabled && self.can_undo(), undo).clicked() {
+                action = Some(Action::Undo);
+            }
+            let redo =
+                egui::Button::new("Повторить").shortcut_text(ui.ctx().format_shortcut(&REDO));
+            if ui.add_enabled(enabled && self.can_redo(), redo).clicked() {
+                action = Some(Action::Redo);
+            }
+        });
+        if self.done.is_empty() && self.undone.is_empty() {
+            ui.label("Изменений пока не было");
+            return action;
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            // Сверху — то, что можно повторить, ниже — сделанное, от нового к старому
+            for entry in &self.undone {
+                ui.weak(format!("↷ {}", entry.describe()));
+            }
+            for (n, entry) in self.done.iter().rev().enumerate() {
+                let text = entry.describe();
+                if n == 0 {
+                    ui.strong(text);
+                } else {
+                    ui.label(text);
+                }
+            }
+        });
+        action
+    }
+}
+
+pub const UNDO: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+pub const REDO: egui::KeyboardShortcut = egui::KeyboardShortcut::new(
+    egui::Modifiers::COMMAND.plus(egui::Modifiers::SHIFT),
+    egui::Key::Z,
+);
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+use crate::app::main_page::sort;
+
+/// Столбцы таблицы, которые пользователь может настраивать
+pub trait Columns: Copy + PartialEq + Into<&'static str> + 'static {
+    /// Все столбцы в порядке по умолчанию
+    const ALL: &'static [Self];
+    /// Ширина по умолчанию
+    fn width(self) -> f32 {
+        120.0
+    }
+}
+
+/// Какие столбцы показаны, в каком порядке, какой ширины и с какими подписями.
+/// Хранится в настройках отдельно для каждой таблицы.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout<C> {
+    columns: Vec<Entry<C>>,
+    /// Меняется при сбросе, чтобы таблица забыла ширины, запомненные egui
+    generation: u32,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Entry<C> {
+    column: C,
+    visible: bool,
+    /// `None` — ширина по умолчанию
+    width: Option<f32>,
+    /// Подпись вместо стандартного названия
+    label: Option<String>,
+}
+
+impl<C: Columns> Default for Layout<C> {
+    fn default() -> Self {
+        Self {
+            columns: C::ALL.iter().map(|column| Entry::new(*column)).collect(),
+            generation: 0,
+        }
+    }
+}
+
+impl<C: Columns> Entry<C> {
+    fn new(column: C) -> Self {
+        Self {
+            column,
+            visible: true,
+            width: None,
+            label: None,
+        }
+    }
+    fn label(&self) -> &str {
+        self.label.as_deref().unwrap_or(self.column.into())
+    }
+}
+
+impl<C: Columns> Layout<C> {
+    /// Видимые столбцы по порядку
+    pub fn visible(&self) -> Vec<C> {
+        self.columns
+            .iter()
+            .filter(|e| e.visible)
+            .map(|e| e.column)
+            .collect()
+    }
+    /// Столбцы, появившиеся в программе после сохранения настроек, добавляются в конец
+    fn normalize(&mut self) {
+        self.columns.retain(|e| C::ALL.contains(&e.column));
+        for column in C::ALL {
+            if !self.columns.iter().any(|e| e.column == *column) {
+                self.columns.push(Entry::new(*column));
+            }
+        }
+    }
+    /// Добавляет видимые столбцы в таблицу. Ширины, изменённые мышью, egui помнит
+    /// сам, а при смене набора столбцов они берутся из настроек.
+    pub fn columns<'a>(
+        &mut self,
+        builder: egui_extras::TableBuilder<'a>,
+        id_salt: &str,
+    ) -> egui_extras::TableBuilder<'a> {
+        self.normalize();
+        let names: Vec<&'static str> = self.visible().into_iter().map(Into::into).collect();
+        let mut builder = builder.id_salt((id_salt, names, self.generation));
+        for entry in self.columns.iter().filter(|e| e.visible) {
+            builder = builder.column(
+                egui_extras::Column::initial(entry.width.unwrap_or(entry.column.width()))
+                    .at_least(30.0)
+                    .clip(true),
+            );
+        }
+        builder
+    }
+    /// Заголовки видимых столбцов с сортировкой. Заодно запоминает их ширину.
+    pub fn header(
+        &mut self,
+        header: &mut egui_extras::TableRow<'_, '_>,
+        order: &mut sort::Order<C>,
+    ) {
+        for entry in self.columns.iter_mut().filter(|e| e.visible) {
+            let (rect, _) = header.col(|ui| {
+                sort::header(ui, entry.label(), entry.column, order);
+            });
+            let width = rect.width();
+            if entry.width.is_none_or(|w| (w - width).abs() > 0.5) {
+                entry.width = Some(width);
+            }
+        }
+    }
+    /// Меню настройки: видимость, порядок и подписи столбцов
+    pub fn editor(&mut self, ui: &mut egui::Ui) {
+        self.normalize();
+        ui.menu_button("Столбцы", |ui| {
+            let visible = self.columns.iter().filter(|e| e.visible).count();
+            let last = self.columns.len().saturating_sub(1);
+            let mut swap = None;
+            egui::Grid::new("layout editor").show(ui, |ui| {
+                for (i, entry) in self.columns.iter_mut().enumerate() {
+                    // Хотя бы один столбец должен остаться
+                    ui.add_enabled_ui(!entry.visible || visible > 1, |ui| {
+                        ui.checkbox(&mut entry.visible, entry.column.into());
+                    });
+                    let default: &str = entry.column.into();
+                    let mut label = entry.label.clone().unwrap_or_default();
+                    if ui
+                        .add(egui::TextEdit::singleline(&mut label).hint_text(default))
+                        .changed()
+                    {
+                        entry.label = (!label.trim().is_empty()).then_some(label);
+                    }
+                    if ui
+                        .add_enabled(i > 0, egui::Button::new("⏶").small())
+                        .clicked()
+                    {
+                        swap = Some(i - 1);
+                    }
+                    if ui
+                        .add_enabled(i < last, egui::Button::new("⏷").small())
+                        .clicked()
+                    {
+                        swap = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(i) = swap {
+                self.columns.swap(i, i + 1);
+            }
+            if ui.button("Сбросить").clicked() {
+                *self = Self {
+                    generation: self.generation.wrapping_add(1),
+                    ..Self::default()
+                };
+            }
+        });
+    }
+}
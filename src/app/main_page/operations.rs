@@ -1,13 +1,18 @@
+mod attachments;
+mod splits;
 mod table;
-use std::collections::BTreeMap;
-
-use tokio_postgres::Error;
+use std::collections::{BTreeMap, BTreeSet};
+pub use table::Column;
 
 use crate::{
-    app::drive_result_promise,
+    app::{
+        drive_result_promise,
+        main_page::{history::Change, layout::Layout, option_to_string, sort},
+    },
     db::{
-        Db,
-        scheme::{ArticlesRow, OperationsRow},
+        Bulk, Db,
+        scheme::{ArticlesRow, BalanceRow, Inserted, OperationsRow, SplitRow},
+        validation::{Error, Rules},
     },
     promise_lite::PromiseLite,
 };
@@ -15,6 +20,27 @@ pub struct State {
     table: Option<table::State>,
     error_message: Option<String>,
     result: Option<PromiseLite<Result<BTreeMap<i32, OperationsRow>, Error>>>,
+    /// Добавление новой строки: база возвращает её id вместе со строками
+    inserted: Option<PromiseLite<Result<Inserted<OperationsRow>, Error>>>,
+    /// Изменение, которое попадёт в историю, когда база его подтвердит,
+    /// и разбивка удаляемой операции
+    pending: Option<(Change<OperationsRow>, Vec<SplitRow>)>,
+    /// Ждём ответа базы на изменение из истории
+    applying: bool,
+    /// Чем закончилось изменение из истории: `true` — применено
+    applied: Option<bool>,
+    /// База применила изменение из истории, разбивку нужно перечитать
+    splits_stale: bool,
+    /// Изменение пересчитало суммы сформированных балансов
+    balance_changed: bool,
+    /// После записи новой операции сразу открыть следующую
+    continue_entry: bool,
+    attachments: attachments::State,
+    splits: splits::State,
+    // Параметры действий над выделенными операциями
+    bulk_article: Option<i32>,
+    bulk_days: i32,
+    bulk_balance: Option<i32>,
 }
 impl State {
     pub fn new(db: &Db) -> Self {
@@ -22,6 +48,18 @@ impl State {
             table: None,
             error_message: None,
             result: Some(db.select_from_operations()),
+            inserted: None,
+            pending: None,
+            applying: false,
+            applied: None,
+            splits_stale: false,
+            balance_changed: false,
+            continue_entry: false,
+            attachments: attachments::State::new(db),
+            splits: splits::State::new(db),
+            bulk_article: None,
+            bulk_days: 1,
+            bulk_balance: None,
         }
     }
     pub fn view(
@@ -29,60 +67,331 @@ impl State {
         ui: &mut egui::Ui,
         db: &Db,
         articles: Option<&BTreeMap<i32, ArticlesRow>>,
+        balance: Option<&BTreeMap<i32, BalanceRow>>,
+        (sort, layout): (&mut sort::Order<Column>, &mut Layout<Column>),
+        rules: &mut Rules,
     ) {
         ui.heading("Операции");
-        let enabled = self.result.is_none();
+        let enabled = self.is_idle();
+        if std::mem::take(&mut self.splits_stale) {
+            self.splits.reload(db);
+        }
+        let can_insert = enabled && self.table.as_ref().is_some_and(|t| !t.is_changing());
+        if can_insert
+            && ui.input_mut(|i| i.consume_shortcut(&NEW_ROW))
+            && let Some(t) = &mut self.table
+        {
+            t.insert_new_row();
+        }
         if let (Some(table), Some(articles)) = (&mut self.table, articles) {
-            if let Some(response) = table.show(ui, enabled, articles) {
-                match response {
-                    table::Response::Update(id, operations_row) => {
-                        self.result = Some(db.update_in_operations(id, operations_row))
+            let related = table::Related {
+                attachments: self.attachments.counts(),
+                splits: self.splits.inner(),
+            };
+            let response = table.show(ui, enabled, articles, (sort, &mut *layout), rules, &related);
+            table.totals().show(ui, "Operations");
+            if let Some(response) = response {
+                let before = |id| table.inner().get(id).cloned();
+                let change = match &response {
+                    table::Response::Update(id, row) | table::Response::Override(id, Some(row)) => {
+                        Change {
+                            id: *id,
+                            before: before(id),
+                            after: Some(row.clone()),
+                        }
                     }
-                    table::Response::Delete(id) => {
-                        log::info!("Удаляем ряд с id: {}", id);
-                        self.result = Some(db.delete_from_operations(id))
+                    table::Response::Delete(id) | table::Response::Override(id, None) => Change {
+                        id: *id,
+                        before: before(id),
+                        after: None,
+                    },
+                    // id новой строки вернёт база
+                    table::Response::Insert(row, _) => Change {
+                        id: 0,
+                        before: None,
+                        after: Some(row.clone()),
+                    },
+                };
+                let splits = match &response {
+                    table::Response::Delete(id) | table::Response::Override(id, None) => {
+                        self.splits.inner().get(id).cloned().unwrap_or_default()
                     }
-                    table::Response::Insert(operations_row) => {
-                        self.result = Some(db.insert_to_operations(operations_row))
-                    }
-                }
+                    _ => Vec::new(),
+                };
+                self.pending = Some((change, splits));
+                self.send(db, response, *rules);
             }
         }
         ui.horizontal(|ui| {
-            let insert = egui::Button::new("Добавить!");
-            if ui
-                .add_enabled(
-                    enabled && self.table.as_ref().is_some_and(|t| !t.is_changing()),
-                    insert,
-                )
-                .clicked()
-            {
+            let insert =
+                egui::Button::new("Добавить!").shortcut_text(ui.ctx().format_shortcut(&NEW_ROW));
+            if ui.add_enabled(can_insert, insert).clicked() {
                 if let Some(t) = &mut self.table {
                     t.insert_new_row();
                 }
             }
             let reload = egui::Button::new("Перезагрузить!");
             if ui.add_enabled(enabled, reload).clicked() {
-                self.result = Some(db.select_from_operations())
+                self.reload(db);
             }
+            layout.editor(ui);
+        });
+        self.selected_details(ui, db, articles);
+        if let (Some(table), Some(articles)) = (&mut self.table, articles)
+            && !table.selected().is_empty()
+        {
+            let bulk = Self::bulk_actions(
+                ui,
+                enabled,
+                table,
+                articles,
+                balance,
+                (
+                    &mut self.bulk_article,
+                    &mut self.bulk_days,
+                    &mut self.bulk_balance,
+                ),
+            );
+            if let Some(action) = bulk {
+                self.balance_changed = matches!(action, Bulk::SetBalance(_));
+                let ids = table.selected().iter().copied().collect();
+                self.result = Some(db.bulk_operations(ids, action));
+            }
+        }
+        ui.collapsing("Правила проверки", |ui| {
+            ui.checkbox(&mut rules.require_article, "Статья обязательна");
+            ui.checkbox(&mut rules.require_date, "Дата обязательна");
+            ui.checkbox(&mut rules.allow_negative, "Разрешить отрицательные суммы");
+            ui.checkbox(
+                &mut rules.allow_both,
+                "Разрешить доход и расход в одной операции",
+            );
+            ui.checkbox(&mut rules.allow_zero, "Разрешить операции без суммы");
         });
         if let Some(error) = &self.error_message {
             ui.colored_label(egui::Color32::RED, error);
         }
     }
-    pub fn drive(&mut self) {
+    /// Возвращает подтверждённое базой изменение для истории
+    pub fn drive(&mut self) -> Option<(Change<OperationsRow>, Vec<SplitRow>)> {
+        self.attachments.drive();
+        self.splits.drive();
+        let mut change = None;
         drive_result_promise!(
             self.result,
-            Ok(values) => {
-                self.table = Some(table::State::new(values));
-                self.error_message = None;
+            Ok(values) => change = self.loaded(values, None),
+            Err(err) => self.failed(err),
+        );
+        drive_result_promise!(
+            self.inserted,
+            Ok(inserted) => {
+                let (id, values) = inserted;
+                change = self.loaded(values, Some(id));
             },
-            Err(err) => self.set_err(err),
+            Err(err) => self.failed(err),
         );
+        change
+    }
+    /// `inserted` — id добавленной строки, его получает изменение для истории
+    fn loaded(
+        &mut self,
+        values: BTreeMap<i32, OperationsRow>,
+        inserted: Option<i32>,
+    ) -> Option<(Change<OperationsRow>, Vec<SplitRow>)> {
+        if std::mem::take(&mut self.applying) {
+            self.applied = Some(true);
+            self.splits_stale = true;
+        }
+        let change = self.pending.take().map(|(mut pending, splits)| {
+            if let Some(id) = inserted {
+                pending.id = id;
+            }
+            (pending, splits)
+        });
+        let mut table = table::State::new(values);
+        if std::mem::take(&mut self.continue_entry) {
+            table.insert_new_row();
+        }
+        self.table = Some(table);
+        self.error_message = None;
+        change
+    }
+    fn failed(&mut self, err: impl std::error::Error) {
+        if std::mem::take(&mut self.applying) {
+            self.applied = Some(false);
+        }
+        self.balance_changed = false;
+        self.pending = None;
+        self.continue_entry = false;
+        self.set_err(err);
+    }
+    /// Метки из загруженных операций
+    pub fn tags(&self) -> Option<&BTreeSet<String>> {
+        self.table.as_ref().map(table::State::tags)
+    }
+    /// Применяет изменение из истории: приводит строку к состоянию `after`.
+    /// Возвращённая строка получает обратно свою разбивку `splits`.
+    pub fn apply(&mut self, db: &Db, change: Change<OperationsRow>, splits: Vec<SplitRow>) {
+        self.applying = true;
+        // Строку из баланса можно вернуть только с пересчётом баланса
+        let locked = change
+            .before
+            .as_ref()
+            .is_some_and(|r| r.balance_id.is_some());
+        self.balance_changed = locked
+            || change
+                .after
+                .as_ref()
+                .is_some_and(|r| r.balance_id.is_some());
+        self.result = Some(match change {
+            Change {
+                id, after: None, ..
+            } if locked => db.override_operation(id, None, Rules::NONE),
+            Change {
+                id, after: None, ..
+            } => db.delete_from_operations(id),
+            Change {
+                id,
+                before: None,
+                after: Some(row),
+            } => db.reinsert_to_operations(id, row, splits),
+            Change {
+                id,
+                after: Some(row),
+                ..
+            } if locked => db.override_operation(id, Some(row), Rules::NONE),
+            Change {
+                id,
+                after: Some(row),
+                ..
+            } => db.update_in_operations(id, row, Rules::NONE),
+        });
+    }
+    /// Результат последнего [`Self::apply`], когда база ответила
+    pub fn take_applied(&mut self) -> Option<bool> {
+        self.applied.take()
+    }
+    pub fn is_idle(&self) -> bool {
+        self.result.is_none() && self.inserted.is_none()
+    }
+    /// Перечитывает операции вместе с вложениями и разбивкой. Если ответа базы
+    /// ещё ждём, он и так принесёт свежие строки.
+    pub fn reload(&mut self, db: &Db) {
+        if !self.is_idle() {
+            return;
+        }
+        self.result = Some(db.select_from_operations());
+        self.attachments.reload(db);
+        self.splits.reload(db);
+    }
+    /// Изменение задело сформированные балансы, их пора перечитать
+    pub fn take_balance_changed(&mut self) -> bool {
+        self.is_idle() && std::mem::take(&mut self.balance_changed)
+    }
+    fn send(&mut self, db: &Db, response: table::Response, rules: Rules) {
+        match response {
+            table::Response::Update(id, operations_row) => {
+                self.result = Some(db.update_in_operations(id, operations_row, rules))
+            }
+            table::Response::Delete(id) => {
+                log::info!("Удаляем ряд с id: {}", id);
+                self.result = Some(db.delete_from_operations(id))
+            }
+            table::Response::Insert(operations_row, next) => {
+                self.continue_entry = next;
+                self.inserted = Some(db.insert_to_operations(operations_row, rules));
+            }
+            table::Response::Override(id, operations_row) => {
+                log::warn!("Меняем операцию {id} из сформированного баланса");
+                self.balance_changed = true;
+                self.result = Some(db.override_operation(id, operations_row, rules));
+            }
+        }
+    }
+    /// Разбивка и вложения операции, если выделена ровно одна
+    fn selected_details(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        articles: Option<&BTreeMap<i32, ArticlesRow>>,
+    ) {
+        let single = self.table.as_ref().and_then(|t| {
+            let id = t.single_selected()?;
+            Some((id, t.inner().get(&id)?))
+        });
+        if let Some(articles) = articles {
+            self.splits.view(ui, db, single, articles);
+        }
+        self.attachments.view(ui, db, single.map(|(id, _)| id));
+    }
+    fn bulk_actions(
+        ui: &mut egui::Ui,
+        enabled: bool,
+        table: &mut table::State,
+        articles: &BTreeMap<i32, ArticlesRow>,
+        balance: Option<&BTreeMap<i32, BalanceRow>>,
+        (article, days, balance_id): (&mut Option<i32>, &mut i32, &mut Option<i32>),
+    ) -> Option<Bulk> {
+        let mut action = None;
+        ui.horizontal_wrapped(|ui| {
+            ui.add_enabled_ui(enabled, |ui| {
+                ui.label(format!("Выбрано: {}", table.selected().len()));
+                if ui.button("Снять выделение").clicked() {
+                    table.clear_selection();
+                }
+                ui.separator();
+                if ui.button("Удалить").clicked() {
+                    action = Some(Bulk::Delete);
+                }
+                ui.separator();
+                egui::ComboBox::from_id_salt("bulk article")
+                    .selected_text(table::State::format_from_articles(*article, articles))
+                    .show_ui(ui, |ui| {
+                        for (id, label) in table::State::article_choices(articles) {
+                            ui.selectable_value(article, Some(id), label);
+                        }
+                    });
+                if ui
+                    .add_enabled(article.is_some(), egui::Button::new("Сменить статью"))
+                    .clicked()
+                {
+                    action = article.map(Bulk::SetArticle);
+                }
+                ui.separator();
+                ui.add(egui::DragValue::new(days).suffix(" дн."));
+                if ui.button("Сдвинуть дату").clicked() {
+                    action = Some(Bulk::ShiftDate(*days));
+                }
+                ui.separator();
+                if ui.button("Отвязать от баланса").clicked() {
+                    action = Some(Bulk::SetBalance(None));
+                }
+                egui::ComboBox::from_id_salt("bulk balance")
+                    .selected_text(option_to_string(balance_id.as_ref()))
+                    .show_ui(ui, |ui| {
+                        for id in balance.into_iter().flat_map(BTreeMap::keys) {
+                            ui.selectable_value(balance_id, Some(*id), id.to_string());
+                        }
+                    });
+                if ui
+                    .add_enabled(
+                        balance_id.is_some(),
+                        egui::Button::new("Привязать к балансу"),
+                    )
+                    .clicked()
+                {
+                    action = balance_id.map(|id| Bulk::SetBalance(Some(id)));
+                }
+            });
+        });
+        action
     }
     fn set_err(&mut self, err: impl std::error::Error) {
-        let message = format!("{err:?}");
+        let message = err.to_string();
         log::error!("{}", message);
         self.error_message = Some(message);
     }
 }
+
+pub const NEW_ROW: egui::KeyboardShortcut =
+    egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::N);
@@ -0,0 +1,211 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::{drive_result_promise, icons},
+    db::{
+        Db,
+        attachments::{Error, can_preview, format_size},
+        scheme::AttachmentRow,
+    },
+    promise_lite::PromiseLite,
+};
+
+/// Вложения выбранной операции: список, добавление, просмотр и выгрузка в файл
+pub struct State {
+    values: BTreeMap<i32, AttachmentRow>,
+    /// Сколько вложений у каждой операции, для значка в таблице
+    counts: BTreeMap<i32, usize>,
+    result: Option<PromiseLite<Result<BTreeMap<i32, AttachmentRow>, Error>>>,
+    saving: Option<PromiseLite<Result<PathBuf, Error>>>,
+    loading: Option<PromiseLite<Result<Vec<u8>, Error>>>,
+    /// Какое вложение загружается для просмотра
+    loading_id: i32,
+    /// Открытое в окне просмотра изображение
+    preview: Option<(i32, egui::load::Bytes)>,
+    /// Файл, который прикрепляется к операции
+    path: String,
+    /// Папка, куда выгружаются вложения
+    save_dir: String,
+    message: Option<String>,
+    error_message: Option<String>,
+}
+impl State {
+    pub fn new(db: &Db) -> Self {
+        Self {
+            values: BTreeMap::new(),
+            counts: BTreeMap::new(),
+            result: Some(db.select_from_attachments()),
+            saving: None,
+            loading: None,
+            loading_id: 0,
+            preview: None,
+            path: String::new(),
+            save_dir: ".".into(),
+            message: None,
+            error_message: None,
+        }
+    }
+    pub fn reload(&mut self, db: &Db) {
+        self.result = Some(db.select_from_attachments());
+    }
+    pub fn counts(&self) -> &BTreeMap<i32, usize> {
+        &self.counts
+    }
+    /// `operation` — единственная выделенная операция
+    pub fn view(&mut self, ui: &mut egui::Ui, db: &Db, operation: Option<i32>) {
+        self.preview_window(ui.ctx());
+        let Some(operation) = operation else {
+            return;
+        };
+        let enabled = self.result.is_none() && self.saving.is_none();
+        // Файлы, перетащенные в окно, прикрепляются к выбранной операции
+        let dropped: Vec<PathBuf> = ui.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect()
+        });
+        if enabled && let Some(path) = dropped.into_iter().next() {
+            self.attach(db, operation, path);
+        }
+        ui.collapsing(
+            format!("Вложения операции {operation}"),
+            |ui| {
+                self.list(ui, db, operation, enabled);
+                ui.horizontal(|ui| {
+                    ui.label("Файл:");
+                    ui.add_enabled(
+                        enabled,
+                        egui::TextEdit::singleline(&mut self.path).hint_text("путь к чеку"),
+                    );
+                    let attach = egui::Button::new("Прикрепить!");
+                    if ui
+                        .add_enabled(enabled && !self.path.trim().is_empty(), attach)
+                        .on_hover_text("Или перетащите файл в окно")
+                        .clicked()
+                    {
+                        self.attach(db, operation, PathBuf::from(self.path.trim()));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Сохранять в:");
+                    ui.add_enabled(enabled, egui::TextEdit::singleline(&mut self.save_dir));
+                });
+                if let Some(message) = &self.message {
+                    ui.label(message);
+                }
+                if let Some(error) = &self.error_message {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            },
+        );
+    }
+    fn list(&mut self, ui: &mut egui::Ui, db: &Db, operation: i32, enabled: bool) {
+        let rows: Vec<_> = self
+            .values
+            .iter()
+            .filter(|(_, row)| row.operation_id == operation)
+            .map(|(id, row)| (*id, row.clone()))
+            .collect();
+        if rows.is_empty() {
+            ui.label("Вложений нет");
+            return;
+        }
+        egui::Grid::new("attachments").striped(true).show(ui, |ui| {
+            for (id, row) in rows {
+                ui.label(&row.file_name);
+                ui.label(format_size(row.size));
+                ui.label(row.create_date.format("%d.%m.%Y %H:%M").to_string());
+                ui.add_enabled_ui(enabled, |ui| {
+                    if can_preview(&row.mime) && ui.button("Просмотр").clicked() {
+                        self.loading_id = id;
+                        self.loading = Some(db.attachment_content(id));
+                    }
+                    if ui.button("Сохранить").clicked() {
+                        let path = Path::new(self.save_dir.trim()).join(&row.file_name);
+                        self.message = None;
+                        self.saving = Some(db.save_attachment(id, path));
+                    }
+                    let remove = egui::Button::new(icons::REMOVE).small();
+                    if ui.add(remove).on_hover_text("Удалить вложение").clicked() {
+                        log::info!("Удаляем вложение {id}");
+                        self.result = Some(db.delete_from_attachments(id));
+                    }
+                });
+                ui.end_row();
+            }
+        });
+    }
+    fn preview_window(&mut self, ctx: &egui::Context) {
+        let Some((id, bytes)) = &self.preview else {
+            return;
+        };
+        let title = self
+            .values
+            .get(id)
+            .map_or_else(|| format!("Вложение {id}"), |row| row.file_name.clone());
+        let uri = format!("bytes://attachment/{id}");
+        let mut open = true;
+        egui::Window::new(title)
+            .id(egui::Id::new("attachment preview"))
+            .open(&mut open)
+            .default_size([600.0, 600.0])
+            .show(ctx, |ui| {
+                egui::ScrollArea::both().show(ui, |ui| {
+                    ui.add(egui::Image::from_bytes(uri.clone(), bytes.clone()).shrink_to_fit());
+                });
+            });
+        if !open {
+            ctx.forget_image(&uri);
+            self.preview = None;
+        }
+    }
+    fn attach(&mut self, db: &Db, operation: i32, path: PathBuf) {
+        log::info!("Прикрепляем {} к операции {operation}", path.display());
+        self.message = None;
+        self.result = Some(db.attach(operation, path));
+    }
+    pub fn drive(&mut self) {
+        drive_result_promise!(
+            self.result,
+            Ok(values) => {
+                self.counts = BTreeMap::new();
+                for row in values.values() {
+                    *self.counts.entry(row.operation_id).or_default() += 1;
+                }
+                if self.preview.as_ref().is_some_and(|(id, _)| !values.contains_key(id)) {
+                    self.preview = None;
+                }
+                self.values = values;
+                self.path.clear();
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+        drive_result_promise!(
+            self.saving,
+            Ok(path) => {
+                self.message = Some(format!("Сохранено в {}", path.display()));
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+        drive_result_promise!(
+            self.loading,
+            Ok(content) => {
+                self.preview = Some((self.loading_id, content.into()));
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = err.to_string();
+        log::error!("{message}");
+        self.error_message = Some(message);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use super::table;
+use crate::{
+    app::{drive_result_promise, icons},
+    db::{
+        Db,
+        scheme::{ArticlesRow, OperationsRow, SplitRow, Splits},
+        validation::Error,
+    },
+    promise_lite::PromiseLite,
+};
+
+enum Edited {
+    Save(Vec<SplitRow>),
+    Cancel,
+}
+
+/// Разбивка выбранной операции по нескольким статьям
+pub struct State {
+    values: Splits,
+    result: Option<PromiseLite<Result<Splits, Error>>>,
+    /// Операция и строки, которые сейчас редактируются
+    edited: Option<(i32, Vec<SplitRow>)>,
+    error_message: Option<String>,
+}
+impl State {
+    pub fn new(db: &Db) -> Self {
+        Self {
+            values: BTreeMap::new(),
+            result: Some(db.select_from_splits()),
+            edited: None,
+            error_message: None,
+        }
+    }
+    pub fn reload(&mut self, db: &Db) {
+        self.result = Some(db.select_from_splits());
+    }
+    pub fn inner(&self) -> &Splits {
+        &self.values
+    }
+    /// `operation` — единственная выделенная операция
+    pub fn view(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        operation: Option<(i32, &OperationsRow)>,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) {
+        let Some((id, row)) = operation else {
+            return;
+        };
+        if self
+            .edited
+            .as_ref()
+            .is_some_and(|(edited, _)| *edited != id || row.balance_id.is_some())
+        {
+            self.edited = None;
+        }
+        let enabled = self.result.is_none();
+        ui.collapsing(
+            format!("Разбивка операции {id} по статьям"),
+            |ui| {
+                ui.add_enabled_ui(enabled, |ui| {
+                    if let Some((_, lines)) = &mut self.edited {
+                        match Self::editor(ui, lines, row, articles) {
+                            Some(Edited::Save(lines)) => {
+                                self.result = Some(db.set_splits(id, lines));
+                            }
+                            Some(Edited::Cancel) => self.edited = None,
+                            None => {}
+                        }
+                        return;
+                    }
+                    let current = self.values.get(&id);
+                    Self::lines(ui, current, articles);
+                    if let Some(balance) = row.balance_id {
+                        ui.weak(format!(
+                            "Операция входит в баланс №{balance}, разбивку менять нельзя"
+                        ));
+                        return;
+                    }
+                    ui.horizontal(|ui| {
+                        let title = if current.is_some() {
+                            "Изменить разбивку"
+                        } else {
+                            "Разбить"
+                        };
+                        if ui.button(title).clicked() {
+                            let lines = current.cloned().unwrap_or_else(|| first_lines(row));
+                            self.edited = Some((id, lines));
+                        }
+                        if current.is_some() && ui.button("Убрать разбивку").clicked()
+                        {
+                            log::info!("Убираем разбивку операции {id}");
+                            self.result = Some(db.set_splits(id, Vec::new()));
+                        }
+                    });
+                });
+                if let Some(error) = &self.error_message {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            },
+        );
+    }
+    fn lines(
+        ui: &mut egui::Ui,
+        lines: Option<&Vec<SplitRow>>,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) {
+        let Some(lines) = lines else {
+            ui.label("Операция целиком относится к своей статье");
+            return;
+        };
+        egui::Grid::new("splits").striped(true).show(ui, |ui| {
+            ui.strong("Статья");
+            ui.strong("Доход");
+            ui.strong("Расход");
+            ui.end_row();
+            for line in lines {
+                ui.label(table::State::format_from_articles(
+                    line.article_id,
+                    articles,
+                ));
+                ui.label(line.debit.to_string());
+                ui.label(line.credit.to_string());
+                ui.end_row();
+            }
+        });
+    }
+    fn editor(
+        ui: &mut egui::Ui,
+        lines: &mut Vec<SplitRow>,
+        row: &OperationsRow,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) -> Option<Edited> {
+        let mut removed = None;
+        egui::Grid::new("splits editor").show(ui, |ui| {
+            ui.strong("Статья");
+            ui.strong("Доход");
+            ui.strong("Расход");
+            ui.end_row();
+            for (i, line) in lines.iter_mut().enumerate() {
+                egui::ComboBox::from_id_salt(("split article", i))
+                    .selected_text(table::State::format_from_articles(
+                        line.article_id,
+                        articles,
+                    ))
+                    .show_ui(ui, |ui| {
+                        for (id, label) in table::State::article_choices(articles) {
+                            ui.selectable_value(&mut line.article_id, Some(id), label);
+                        }
+                    });
+                ui.add(egui::DragValue::new(&mut line.debit));
+                ui.add(egui::DragValue::new(&mut line.credit));
+                if ui.add(egui::Button::new(icons::REMOVE).small()).clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = removed {
+            lines.remove(i);
+        }
+        if ui.button("Добавить строку").clicked() {
+            lines.push(SplitRow::default());
+        }
+        let (debit, credit) = remainder(lines, row);
+        let balanced = debit == 0 && credit == 0;
+        if balanced {
+            ui.label("Строки сходятся с суммой операции");
+        } else {
+            ui.colored_label(
+                egui::Color32::RED,
+                format!("Осталось распределить: доход {debit}, расход {credit}"),
+            );
+        }
+        let complete = lines.iter().all(|line| line.article_id.is_some());
+        if !complete {
+            ui.colored_label(egui::Color32::RED, "У каждой строки должна быть статья");
+        }
+        let mut response = None;
+        ui.horizontal(|ui| {
+            let save = egui::Button::new("Сохранить!");
+            if ui
+                .add_enabled(balanced && complete && lines.len() >= 2, save)
+                .on_disabled_hover_text("Нужно хотя бы две строки, которые дают сумму операции")
+                .clicked()
+            {
+                response = Some(Edited::Save(lines.clone()));
+            }
+            if ui.button("Отмена").clicked() {
+                response = Some(Edited::Cancel);
+            }
+        });
+        response
+    }
+    pub fn drive(&mut self) {
+        drive_result_promise!(
+            self.result,
+            Ok(values) => {
+                self.values = values;
+                self.edited = None;
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = err.to_string();
+        log::error!("{message}");
+        self.error_message = Some(message);
+    }
+}
+
+/// Сколько ещё не распределено по строкам
+fn remainder(lines: &[SplitRow], row: &OperationsRow) -> (i64, i64) {
+    let debit: i64 = lines.iter().map(|line| i64::from(line.debit)).sum();
+    let credit: i64 = lines.iter().map(|line| i64::from(line.credit)).sum();
+    (
+        i64::from(row.debit.unwrap_or(0)) - debit,
+        i64::from(row.credit.unwrap_or(0)) - credit,
+    )
+}
+/// Вся сумма в статье операции и пустая строка для второй статьи
+fn first_lines(row: &OperationsRow) -> Vec<SplitRow> {
+    vec![
+        SplitRow {
+            article_id: row.article_id,
+            debit: row.debit.unwrap_or(0),
+            credit: row.credit.unwrap_or(0),
+        },
+        SplitRow::default(),
+    ]
+}
@@ -1,35 +1,149 @@
-use chrono::{Local, NaiveTime};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
 
 use crate::{
     app::{
         icons,
-        main_page::{option_to_string, option_to_string_with},
+        main_page::{
+            layout::{Columns, Layout},
+            option_to_string, option_to_string_with, palette, sort,
+            totals::Totals,
+        },
+    },
+    db::{
+        hierarchy::Tree,
+        scheme::{ArticlesRow, OperationsRow, Splits},
+        validation::{Field, Rules, Violation},
     },
-    db::scheme::{ArticlesRow, OperationsRow},
 };
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 pub struct State {
     values: BTreeMap<i32, OperationsRow>,
     edited: Option<(Option<i32>, OperationsRow)>,
+    /// Редактируется операция из баланса, баланс будет пересчитан
+    overriding: bool,
+    /// id в порядке показа и сортировка, по которой он посчитан
+    order: Vec<i32>,
+    sorted_by: Option<sort::Order<Column>>,
+    filter: Filter,
+    filtered_by: Option<Filter>,
+    /// Все метки, которые встречаются в операциях
+    tags: BTreeSet<String>,
+    /// Прокрутить к новой строке в следующем кадре
+    scroll_to_new: bool,
+    selected: BTreeSet<i32>,
+    /// Строка, от которой отсчитывается выделение с Shift
+    anchor: Option<i32>,
+    input: Input,
+}
+/// Текст полей редактируемой строки: статью ищут по названию,
+/// суммы и дату вводят с клавиатуры
+#[derive(Default)]
+struct Input {
+    article: String,
+    debit: String,
+    credit: String,
+    date: String,
+    description: String,
+    tags: String,
+    /// Статья, выбранная стрелками в отфильтрованном списке
+    highlight: usize,
+    /// Поле, которое получит фокус в следующем кадре
+    focus: Option<Field>,
+}
+/// Сведения из связанных таблиц, которые показываются в строках операций
+pub struct Related<'a> {
+    /// Сколько файлов прикреплено к операции
+    pub attachments: &'a BTreeMap<i32, usize>,
+    pub splits: &'a Splits,
+}
+/// Показываются только операции, подходящие под поиск и выбранную метку
+#[derive(Default, Clone, PartialEq, Eq)]
+struct Filter {
+    text: String,
+    tag: Option<String>,
+}
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Column {
+    #[strum(serialize = "id")]
+    Id,
+    #[strum(serialize = "article_id")]
+    Article,
+    #[strum(serialize = "debit")]
+    Debit,
+    #[strum(serialize = "credit")]
+    Credit,
+    #[strum(serialize = "create_date")]
+    CreateDate,
+    #[strum(serialize = "description")]
+    Description,
+    #[strum(serialize = "tags")]
+    Tags,
+    #[strum(serialize = "balance_id")]
+    Balance,
 }
+impl Columns for Column {
+    const ALL: &'static [Self] = &COLUMNS;
+    /// Начальная ширина, дальше её можно менять мышью
+    fn width(self) -> f32 {
+        match self {
+            Self::Id | Self::Balance => 60.0,
+            Self::Debit | Self::Credit => 90.0,
+            Self::Article => 180.0,
+            Self::CreateDate => 150.0,
+            Self::Description => 200.0,
+            Self::Tags => 140.0,
+        }
+    }
+}
+const COLUMNS: [Column; 8] = [
+    Column::Id,
+    Column::Article,
+    Column::Debit,
+    Column::Credit,
+    Column::CreateDate,
+    Column::Description,
+    Column::Tags,
+    Column::Balance,
+];
 pub enum Response {
     Update(i32, OperationsRow),
-    Insert(OperationsRow),
+    /// `true` — после записи сразу начать следующую операцию
+    Insert(OperationsRow, bool),
     Delete(i32),
+    /// Изменение (`None` — удаление) операции из баланса с его пересчётом
+    Override(i32, Option<OperationsRow>),
 }
 enum Edited {
-    Confirm,
+    /// `next` — подтверждено клавишей Enter, ввод продолжается
+    Confirm {
+        next: bool,
+    },
     Cancel,
 }
 enum Regular {
     Edit,
     Delete,
+    OverrideEdit,
+    OverrideDelete,
 }
 impl State {
     pub fn new(values: BTreeMap<i32, OperationsRow>) -> Self {
+        let tags = values.values().flat_map(|row| row.tags.clone()).collect();
         Self {
             values,
             edited: None,
+            overriding: false,
+            order: Vec::new(),
+            sorted_by: None,
+            filter: Filter::default(),
+            filtered_by: None,
+            tags,
+            scroll_to_new: false,
+            selected: BTreeSet::new(),
+            anchor: None,
+            input: Input::default(),
         }
     }
     pub fn show(
@@ -37,97 +151,350 @@ impl State {
         ui: &mut egui::Ui,
         edit_enabled: bool,
         articles: &BTreeMap<i32, ArticlesRow>,
+        (sort, layout): (&mut sort::Order<Column>, &mut Layout<Column>),
+        rules: &Rules,
+        related: &Related<'_>,
     ) -> Option<Response> {
         let mut response = None;
-        let headers = [
-            "id",
-            "article_id",
-            "debit",
-            "credit",
-            "create_date",
-            "balance_id",
-            "Операции",
-        ];
+        self.cancel_on_escape(ui, edit_enabled);
+        self.filter_bar(ui, sort, articles);
         let regular_enabled = edit_enabled && self.edited.is_none();
-        egui::containers::ScrollArea::new([true, true]).show(ui, |ui| {
-            egui::Grid::new("Operations")
-                .num_columns(headers.len())
-                .show(ui, |ui| {
-                    for header in headers {
-                        ui.strong(header);
-                    }
-                    ui.end_row();
-                    for (id, row) in self.values.iter() {
-                        if let Some((Some(target), edited_row)) = &mut self.edited
-                            && *target == *id
-                        {
-                            if let Some(inner_response) = Self::show_edited_row(
-                                ui,
-                                Some(*target),
-                                edited_row,
+        let inserting = matches!(self.edited, Some((None, _)));
+        let total = self.order.len() + usize::from(inserting);
+        let row_height = ui.spacing().interact_size.y;
+        let modifiers = ui.input(|i| i.modifiers);
+        let mut clicked = None;
+        let columns = layout.visible();
+        self.builder(ui, total, layout)
+            .header(row_height, |mut header| {
+                layout.header(&mut header, sort);
+                header.col(|ui| {
+                    ui.strong("Операции");
+                });
+            })
+            .body(|body| {
+                body.rows(row_height, total, |mut table_row| {
+                    let Some((id, row)) = self
+                        .order
+                        .get(table_row.index())
+                        .and_then(|id| self.values.get_key_value(id))
+                    else {
+                        // Последняя строка — новая операция
+                        if let Some((None, edited_row)) = &mut self.edited
+                            && let Some(inner_response) = Self::show_edited_row(
+                                &mut table_row,
+                                (None, edited_row),
+                                &mut self.input,
+                                &columns,
                                 edit_enabled,
                                 articles,
-                            ) {
-                                match inner_response {
-                                    Edited::Confirm => {
-                                        response = Some(Response::Update(*id, edited_row.clone()))
-                                    }
-                                    Edited::Cancel => {
-                                        self.edited = None;
-                                    }
+                                rules,
+                            )
+                        {
+                            match inner_response {
+                                Edited::Confirm { next } => {
+                                    response = Some(Response::Insert(edited_row.clone(), next));
                                 }
-                            }
-                        } else {
-                            if let Some(inner_response) =
-                                Self::show_normal_row(ui, *id, row, regular_enabled, articles)
-                            {
-                                match inner_response {
-                                    Regular::Edit => self.edited = Some((Some(*id), row.clone())),
-                                    Regular::Delete => response = Some(Response::Delete(*id)),
+                                Edited::Cancel => {
+                                    self.edited = None;
                                 }
                             }
                         }
-                        ui.end_row();
-                    }
-                    if let Some((None, edited_row)) = &mut self.edited {
-                        if let Some(inner_response) =
-                            Self::show_edited_row(ui, None, edited_row, edit_enabled, articles)
-                        {
+                        return;
+                    };
+                    table_row.set_selected(self.selected.contains(id));
+                    if let Some((Some(target), edited_row)) = &mut self.edited
+                        && *target == *id
+                    {
+                        if let Some(inner_response) = Self::show_edited_row(
+                            &mut table_row,
+                            (Some(*target), edited_row),
+                            &mut self.input,
+                            &columns,
+                            edit_enabled,
+                            articles,
+                            rules,
+                        ) {
                             match inner_response {
-                                Edited::Confirm => {
-                                    response = Some(Response::Insert(edited_row.clone()))
+                                Edited::Confirm { .. } if self.overriding => {
+                                    response =
+                                        Some(Response::Override(*id, Some(edited_row.clone())));
+                                }
+                                Edited::Confirm { .. } => {
+                                    response = Some(Response::Update(*id, edited_row.clone()));
                                 }
                                 Edited::Cancel => {
                                     self.edited = None;
                                 }
                             }
                         }
+                    } else if let Some(inner_response) = Self::show_normal_row(
+                        &mut table_row,
+                        *id,
+                        row,
+                        regular_enabled,
+                        (articles, related),
+                        &columns,
+                    ) {
+                        match inner_response {
+                            Regular::Edit | Regular::OverrideEdit => {
+                                self.input = Input::new(row, articles);
+                                self.edited = Some((Some(*id), row.clone()));
+                                self.overriding = matches!(inner_response, Regular::OverrideEdit);
+                            }
+                            Regular::Delete => response = Some(Response::Delete(*id)),
+                            Regular::OverrideDelete => {
+                                response = Some(Response::Override(*id, None));
+                            }
+                        }
+                    } else if regular_enabled && table_row.response().clicked() {
+                        clicked = Some(*id);
                     }
                 });
-        });
+            });
+        if let Some(id) = clicked {
+            self.select(id, modifiers);
+        }
         response
     }
+    /// Итоги по отфильтрованным строкам; редактируемая строка учитывается
+    /// с уже введёнными суммами
+    pub fn totals(&self) -> Totals {
+        let edited = self.edited.as_ref();
+        let rows = self.order.iter().filter_map(|id| match edited {
+            Some((Some(target), row)) if target == id => Some(row),
+            _ => self.values.get(id),
+        });
+        let inserted = edited.filter(|(id, _)| id.is_none()).map(|(_, row)| row);
+        Totals::new(rows.chain(inserted).map(|row| (row.debit, row.credit)))
+    }
+    fn builder<'a>(
+        &mut self,
+        ui: &'a mut egui::Ui,
+        total: usize,
+        layout: &mut Layout<Column>,
+    ) -> egui_extras::TableBuilder<'a> {
+        // Итоги, кнопки и действия над выделенными остаются под таблицей
+        let max_height =
+            ui.available_height() - (5.0 + Totals::ROWS) * ui.spacing().interact_size.y;
+        let builder = egui_extras::TableBuilder::new(ui)
+            .sense(egui::Sense::click())
+            .striped(true)
+            .resizable(true)
+            .max_scroll_height(max_height)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center));
+        let mut builder = layout.columns(builder, "Operations");
+        if std::mem::take(&mut self.scroll_to_new) {
+            builder = builder.scroll_to_row(total.saturating_sub(1), Some(egui::Align::BOTTOM));
+        }
+        builder.column(egui_extras::Column::remainder().at_least(60.0))
+    }
+    /// Escape отменяет редактирование, если не закрывает открытый список
+    fn cancel_on_escape(&mut self, ui: &egui::Ui, enabled: bool) {
+        if enabled
+            && ui.input(|i| i.key_pressed(egui::Key::Escape))
+            && !egui::Popup::is_any_open(ui.ctx())
+        {
+            self.edited = None;
+        }
+    }
+    /// Щелчок выделяет одну строку, с Ctrl добавляет или убирает её,
+    /// с Shift выделяет диапазон от предыдущего щелчка
+    fn select(&mut self, id: i32, modifiers: egui::Modifiers) {
+        let position = |id| self.order.iter().position(|o| *o == id);
+        if modifiers.shift
+            && let Some(anchor) = self.anchor
+            && let (Some(from), Some(to)) = (position(anchor), position(id))
+        {
+            let range = self
+                .order
+                .get(from.min(to)..=from.max(to))
+                .unwrap_or_default();
+            if !modifiers.command {
+                self.selected.clear();
+            }
+            self.selected.extend(range);
+            return;
+        }
+        if modifiers.command {
+            if !self.selected.remove(&id) {
+                self.selected.insert(id);
+            }
+        } else {
+            self.selected = BTreeSet::from([id]);
+        }
+        self.anchor = Some(id);
+    }
+    pub fn inner(&self) -> &BTreeMap<i32, OperationsRow> {
+        &self.values
+    }
+    pub fn selected(&self) -> &BTreeSet<i32> {
+        &self.selected
+    }
+    /// Выделенная операция, если она одна
+    pub fn single_selected(&self) -> Option<i32> {
+        let mut selected = self.selected.iter();
+        selected
+            .next()
+            .copied()
+            .filter(|_| selected.next().is_none())
+    }
+    pub fn clear_selection(&mut self) {
+        self.selected.clear();
+        self.anchor = None;
+    }
+    /// Поиск по описанию, меткам и названию статьи и выбор одной метки.
+    /// Если фильтр или порядок изменились, строки пересобираются.
+    fn filter_bar(
+        &mut self,
+        ui: &mut egui::Ui,
+        sort: &sort::Order<Column>,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label("Поиск:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.filter.text)
+                    .hint_text("описание, метка или статья"),
+            );
+            ui.label("Метка:");
+            egui::ComboBox::from_id_salt("filter tag")
+                .selected_text(self.filter.tag.as_deref().unwrap_or("все"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.tag, None, "все");
+                    for tag in &self.tags {
+                        ui.selectable_value(&mut self.filter.tag, Some(tag.clone()), tag);
+                    }
+                });
+            if self.filter != Filter::default() {
+                ui.label(format!(
+                    "Показано {} из {}",
+                    self.order.len(),
+                    self.values.len()
+                ));
+                if ui.button("Сбросить").clicked() {
+                    self.filter = Filter::default();
+                }
+            }
+        });
+        if self.sorted_by.as_ref() != Some(sort) || self.filtered_by.as_ref() != Some(&self.filter)
+        {
+            self.sort(sort, articles);
+        }
+    }
+    /// Статьи сравниваются по названию, а не по id. Заодно отбрасываются
+    /// строки, не подходящие под фильтр, и снимается выделение с них.
+    fn sort(&mut self, sort: &sort::Order<Column>, articles: &BTreeMap<i32, ArticlesRow>) {
+        let name = |row: &OperationsRow| articles.get(&row.article_id?)?.name.as_deref();
+        let tags = |row: &OperationsRow| (!row.tags.is_empty()).then(|| row.tags.join(", "));
+        let text = self.filter.text.trim().to_lowercase();
+        let matches = |row: &OperationsRow| {
+            let found = |value: &str| value.to_lowercase().contains(&text);
+            let tagged = self
+                .filter
+                .tag
+                .as_ref()
+                .is_none_or(|tag| row.tags.contains(tag));
+            tagged
+                && (text.is_empty()
+                    || row.description.as_deref().is_some_and(found)
+                    || row.tags.iter().any(|tag| found(tag))
+                    || name(row).is_some_and(found))
+        };
+        let mut rows: Vec<_> = self.values.iter().filter(|(_, row)| matches(row)).collect();
+        sort.sort(&mut rows, |column, (a_id, a), (b_id, b)| match column {
+            Column::Id => sort::nullable(Some(a_id), Some(b_id)),
+            Column::Article => sort::text(name(a), name(b)),
+            Column::Debit => sort::nullable(a.debit, b.debit),
+            Column::Credit => sort::nullable(a.credit, b.credit),
+            Column::CreateDate => sort::nullable(a.create_date, b.create_date),
+            Column::Description => sort::text(a.description.as_deref(), b.description.as_deref()),
+            Column::Tags => sort::text(tags(a).as_deref(), tags(b).as_deref()),
+            Column::Balance => sort::nullable(a.balance_id, b.balance_id),
+        });
+        self.order = rows.into_iter().map(|(id, _)| *id).collect();
+        self.selected.retain(|id| self.order.contains(id));
+        self.sorted_by = Some(sort.clone());
+        self.filtered_by = Some(self.filter.clone());
+    }
+    pub fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+    /// Новая строка с сегодняшней датой, фокус сразу на статье
     pub fn insert_new_row(&mut self) {
-        self.edited = Some((None, Default::default()));
+        let row = OperationsRow {
+            create_date: Some(Local::now().date_naive().into()),
+            ..Default::default()
+        };
+        self.input = Input::new(&row, &BTreeMap::new());
+        self.input.focus = Some(Field::Article);
+        self.edited = Some((None, row));
+        self.scroll_to_new = true;
     }
     pub fn is_changing(&self) -> bool {
         self.edited.is_some()
     }
     fn show_normal_row(
-        ui: &mut egui::Ui,
+        table_row: &mut egui_extras::TableRow<'_, '_>,
         id: i32,
         row: &OperationsRow,
         enabled: bool,
-        articles: &BTreeMap<i32, ArticlesRow>,
+        (articles, related): (&BTreeMap<i32, ArticlesRow>, &Related<'_>),
+        columns: &[Column],
     ) -> Option<Regular> {
-        ui.label(id.to_string());
-        ui.label(Self::format_from_articles(row.article_id, articles));
-        ui.label(option_to_string(row.debit.as_ref()));
-        ui.label(option_to_string(row.credit.as_ref()));
-        ui.label(option_to_string(row.create_date.as_ref()));
-        ui.label(option_to_string_with(row.balance_id.as_ref(), "[null]"));
+        for column in columns {
+            let cell = match column {
+                Column::Id => id.to_string(),
+                Column::Article => {
+                    table_row.col(|ui| match related.splits.get(&id) {
+                        Some(lines) => {
+                            ui.label(icons::SPLIT);
+                            for line in lines {
+                                Self::article_cell(ui, line.article_id, articles);
+                            }
+                        }
+                        None => Self::article_cell(ui, row.article_id, articles),
+                    });
+                    continue;
+                }
+                Column::Debit => option_to_string(row.debit.as_ref()),
+                Column::Credit => option_to_string(row.credit.as_ref()),
+                Column::CreateDate => option_to_string(row.create_date.as_ref()),
+                Column::Description => option_to_string(row.description.as_ref()),
+                Column::Tags => row.tags.join(", "),
+                Column::Balance => {
+                    option_to_string_with(row.balance_id.as_ref(), "[null]").into_owned()
+                }
+            };
+            table_row.col(|ui| {
+                ui.label(cell);
+            });
+        }
         let mut response = None;
-        ui.horizontal(|ui| {
+        table_row.col(|ui| {
+            if let Some(count) = related.attachments.get(&id) {
+                ui.label(icons::ATTACHMENT)
+                    .on_hover_text(format!("Вложений: {count}"));
+            }
+            if let Some(balance_id) = row.balance_id {
+                // Операции из баланса меняются только с его пересчётом
+                ui.add_enabled_ui(enabled, |ui| {
+                    ui.menu_button(icons::LOCKED, |ui| {
+                        ui.label(format!("Входит в баланс №{balance_id}"));
+                        if ui.button("Изменить с пересчётом баланса").clicked()
+                        {
+                            response = Some(Regular::OverrideEdit);
+                        }
+                        if ui.button("Удалить с пересчётом баланса").clicked()
+                        {
+                            response = Some(Regular::OverrideDelete);
+                        }
+                    })
+                    .response
+                    .on_hover_text("Операция входит в сформированный баланс");
+                });
+                return;
+            }
             let edit = egui::Button::new(icons::EDIT).small();
             let remove = egui::Button::new(icons::REMOVE).small();
             if ui.add_enabled(enabled, edit).clicked() {
@@ -140,72 +507,365 @@ impl State {
         response
     }
     fn show_edited_row(
-        ui: &mut egui::Ui,
-        id: Option<i32>,
-        edited_row: &mut OperationsRow,
+        table_row: &mut egui_extras::TableRow<'_, '_>,
+        (id, edited_row): (Option<i32>, &mut OperationsRow),
+        input: &mut Input,
+        columns: &[Column],
         enabled: bool,
         articles: &BTreeMap<i32, ArticlesRow>,
+        rules: &Rules,
     ) -> Option<Edited> {
-        ui.label(option_to_string(id.as_ref()));
-        egui::ComboBox::from_id_salt("choose article")
-            .selected_text(Self::format_from_articles(edited_row.article_id, articles))
-            .show_ui(ui, |ui| {
-                if enabled {
-                    for (id, article) in articles {
-                        ui.selectable_value(
-                            &mut edited_row.article_id,
-                            Some(*id),
-                            Self::format_article(*id, article),
-                        );
-                    }
-                }
-            });
-        let mut debit = edited_row.debit.unwrap_or(0);
-        ui.add_enabled(enabled, egui::DragValue::new(&mut debit).speed(0.5));
-        edited_row.debit = Some(debit);
-
-        let mut credit = edited_row.credit.unwrap_or(0);
-        ui.add_enabled(enabled, egui::DragValue::new(&mut credit).speed(0.5));
-        edited_row.credit = Some(credit);
-
-        let mut create_date = edited_row.create_date.map(|t| t.date()).unwrap_or_else(|| {
-            Local::now()
-                .with_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap())
-                .unwrap()
-                .date_naive()
-        });
-        ui.add_enabled(
+        let violations = input.violations(edited_row, rules);
+        let cell = EditedCell {
+            id,
             enabled,
-            egui_extras::DatePickerButton::new(&mut create_date),
-        );
-        edited_row.create_date = Some(create_date.into());
-
-        ui.label(option_to_string_with(
-            edited_row.balance_id.as_ref(),
-            "[null]",
-        ));
+            articles,
+            violations: &violations,
+            focus: input.focus.take(),
+        };
+        // Enter в поле суммы или даты подтверждает строку
+        let mut enter = false;
+        for column in columns {
+            table_row.col(|ui| {
+                enter |= cell.show(ui, *column, edited_row, input);
+            });
+        }
         let mut response = None;
-        ui.horizontal(|ui| {
+        table_row.col(|ui| {
             let confirm = egui::Button::new(icons::CONFIRM).small();
             let cancel = egui::Button::new(icons::CANCEL).small();
-            if ui.add_enabled(enabled, confirm).clicked() {
-                response = Some(Edited::Confirm);
+            let valid = violations.is_empty();
+            let mut messages: Vec<&str> = violations.iter().map(|v| v.message).collect();
+            messages.dedup();
+            if ui
+                .add_enabled(enabled && valid, confirm)
+                .on_disabled_hover_text(messages.join("\n"))
+                .clicked()
+            {
+                response = Some(Edited::Confirm { next: false });
             }
             if ui.add_enabled(enabled, cancel).clicked() {
                 response = Some(Edited::Cancel);
             }
         });
+        if enter && enabled {
+            // Значения могли измениться в этом же кадре, проверяем заново
+            match input.violations(edited_row, rules).first() {
+                None => response = Some(Edited::Confirm { next: true }),
+                Some(violation) => input.focus = Some(violation.field),
+            }
+        }
         response
     }
-    fn format_from_articles(id: Option<i32>, articles: &BTreeMap<i32, ArticlesRow>) -> String {
+    /// Дату можно ввести текстом или выбрать в календаре рядом
+    fn date_input(
+        ui: &mut egui::Ui,
+        edited_row: &mut OperationsRow,
+        input: &mut Input,
+        enabled: bool,
+    ) -> egui::Response {
+        let text = ui.add_enabled(
+            enabled,
+            egui::TextEdit::singleline(&mut input.date)
+                .hint_text("ДД.ММ.ГГГГ")
+                .desired_width(80.0),
+        );
+        if text.changed() {
+            edited_row.create_date = parse_date(&input.date).map(Into::into);
+        }
+        let mut picked = edited_row
+            .create_date
+            .map_or_else(|| Local::now().date_naive(), |t| t.date());
+        let picker = ui.add_enabled(
+            enabled,
+            egui_extras::DatePickerButton::new(&mut picked)
+                .id_salt("operation date")
+                .format(""),
+        );
+        if picker.changed() {
+            edited_row.create_date = Some(picked.into());
+            input.date = picked.format(DATE_FORMAT).to_string();
+        }
+        text
+    }
+    /// Поле поиска статьи: список под ним сужается по мере ввода,
+    /// стрелки выбирают статью, Enter или Tab подтверждают выбор
+    fn article_input(
+        ui: &mut egui::Ui,
+        edited_row: &mut OperationsRow,
+        input: &mut Input,
+        enabled: bool,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) -> egui::Response {
+        let text = ui.add_enabled(
+            enabled,
+            egui::TextEdit::singleline(&mut input.article).hint_text("Статья"),
+        );
+        if text.gained_focus() {
+            // Пока поле в фокусе, в нём только поисковый запрос
+            input.article.clear();
+            input.highlight = 0;
+        }
+        let query = input.article.trim().to_lowercase();
+        // Полный путь статьи, чтобы по названию группы находились и вложенные.
+        // Архивные статьи не предлагаются.
+        let matches: Vec<(i32, String)> = Tree::new(articles)
+            .active()
+            .into_iter()
+            .map(|(id, _)| (id, Self::format_from_articles(Some(id), articles)))
+            .filter(|(_, name)| name.to_lowercase().contains(&query))
+            .collect();
+        if text.has_focus() {
+            let (up, down) = ui.input_mut(|i| {
+                (
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                    i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                )
+            });
+            if text.gained_focus()
+                && let Some(current) = matches
+                    .iter()
+                    .position(|(id, _)| Some(*id) == edited_row.article_id)
+            {
+                input.highlight = current;
+            } else if up {
+                input.highlight = input.highlight.saturating_sub(1);
+            } else if down {
+                input.highlight = (input.highlight + 1).min(matches.len().saturating_sub(1));
+            } else if text.changed() {
+                input.highlight = 0;
+            }
+            if up || down || text.changed() {
+                edited_row.article_id = if query.is_empty() && !(up || down) {
+                    None
+                } else {
+                    matches.get(input.highlight).map(|(id, _)| *id)
+                };
+            }
+        }
+        let open = text.has_focus() && !matches.is_empty();
+        egui::Popup::from_response(&text)
+            .open_memory(open.then_some(egui::SetOpenCommand::Bool(true)))
+            .show(|ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for (n, (id, name)) in matches.iter().enumerate() {
+                            let selected = n == input.highlight;
+                            let label = ui.selectable_label(selected, name);
+                            if selected && (text.changed() || text.has_focus()) {
+                                label.scroll_to_me(None);
+                            }
+                            if label.clicked() {
+                                edited_row.article_id = Some(*id);
+                                input.article = name.clone();
+                            }
+                        }
+                    });
+            });
+        if text.lost_focus() {
+            input.article = Self::format_from_articles(edited_row.article_id, articles);
+            if !ui.input(|i| i.pointer.any_click()) {
+                egui::Popup::close_id(ui.ctx(), egui::Popup::default_response_id(&text));
+            }
+        }
+        text
+    }
+    /// Красная рамка вокруг поля с ошибкой и значок с пояснением
+    fn mark(ui: &mut egui::Ui, response: &egui::Response, violations: &[Violation], field: Field) {
+        let messages: Vec<&str> = violations
+            .iter()
+            .filter(|v| v.field == field)
+            .map(|v| v.message)
+            .collect();
+        if messages.is_empty() {
+            return;
+        }
+        ui.painter().rect_stroke(
+            response.rect.expand(1.0),
+            2.0,
+            egui::Stroke::new(1.5, ui.visuals().error_fg_color),
+            egui::StrokeKind::Outside,
+        );
+        ui.colored_label(ui.visuals().error_fg_color, "⚠")
+            .on_hover_text(messages.join("\n"));
+    }
+    pub fn format_from_articles(id: Option<i32>, articles: &BTreeMap<i32, ArticlesRow>) -> String {
         if let Some(id) = id {
-            if let Some(article) = articles.get(&id) {
-                return Self::format_article(id, article);
+            if articles.contains_key(&id) {
+                return format!("{id} ({})", palette::path(id, articles));
             }
         }
         "".into()
     }
-    fn format_article(id: i32, article: &ArticlesRow) -> String {
-        format!("{id} ({})", option_to_string(article.name.as_ref()))
+    /// Статья с меткой её цвета
+    fn article_cell(ui: &mut egui::Ui, id: Option<i32>, articles: &BTreeMap<i32, ArticlesRow>) {
+        let Some(id) = id.filter(|id| articles.contains_key(id)) else {
+            return;
+        };
+        ui.colored_label(palette::article_color(id, articles), "●");
+        ui.label(Self::format_from_articles(Some(id), articles));
+    }
+    /// Статьи деревом для выпадающих списков: вложенные с отступом, без архивных
+    pub fn article_choices(articles: &BTreeMap<i32, ArticlesRow>) -> Vec<(i32, String)> {
+        let tree = Tree::new(articles);
+        tree.active()
+            .into_iter()
+            .map(|(id, depth)| (id, format!("{} {id}", tree.indented(id, depth))))
+            .collect()
+    }
+}
+
+/// Общее для всех ячеек строки редактирования
+struct EditedCell<'a> {
+    id: Option<i32>,
+    enabled: bool,
+    articles: &'a BTreeMap<i32, ArticlesRow>,
+    violations: &'a [Violation],
+    /// Поле, в которое нужно перевести фокус в этом кадре
+    focus: Option<Field>,
+}
+impl EditedCell<'_> {
+    /// Возвращает `true`, если в поле нажали Enter и строку пора подтвердить
+    fn show(
+        &self,
+        ui: &mut egui::Ui,
+        column: Column,
+        edited_row: &mut OperationsRow,
+        input: &mut Input,
+    ) -> bool {
+        let enter_pressed = |ui: &egui::Ui, response: &egui::Response| {
+            response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter))
+        };
+        let enabled = self.enabled;
+        let (field, text) = match column {
+            Column::Id => {
+                ui.label(option_to_string(self.id.as_ref()));
+                return false;
+            }
+            Column::Balance => {
+                ui.label(option_to_string_with(
+                    edited_row.balance_id.as_ref(),
+                    "[null]",
+                ));
+                return false;
+            }
+            Column::Article => {
+                let text = State::article_input(ui, edited_row, input, enabled, self.articles);
+                if self.focus == Some(Field::Article) {
+                    text.request_focus();
+                }
+                // Enter в статье переводит к сумме, а не подтверждает строку
+                if enter_pressed(ui, &text) {
+                    input.focus = Some(Field::Debit);
+                }
+                State::mark(ui, &text, self.violations, Field::Article);
+                return false;
+            }
+            Column::Debit | Column::Credit => {
+                let (field, value, amount) = if column == Column::Debit {
+                    (Field::Debit, &mut input.debit, &mut edited_row.debit)
+                } else {
+                    (Field::Credit, &mut input.credit, &mut edited_row.credit)
+                };
+                let text = ui.add_enabled(enabled, egui::TextEdit::singleline(value));
+                if let Some(parsed) = parse_amount(value) {
+                    *amount = Some(parsed);
+                }
+                (Some(field), text)
+            }
+            Column::CreateDate => (
+                Some(Field::CreateDate),
+                State::date_input(ui, edited_row, input, enabled),
+            ),
+            Column::Description => {
+                let text = ui.add_enabled(
+                    enabled,
+                    egui::TextEdit::singleline(&mut input.description).hint_text("Описание"),
+                );
+                let description = input.description.trim();
+                edited_row.description = (!description.is_empty()).then(|| description.to_owned());
+                (None, text)
+            }
+            Column::Tags => {
+                let text = ui
+                    .add_enabled(
+                        enabled,
+                        egui::TextEdit::singleline(&mut input.tags).hint_text("Метки"),
+                    )
+                    .on_hover_text("Через запятую");
+                edited_row.tags = parse_tags(&input.tags);
+                (None, text)
+            }
+        };
+        if let Some(field) = field {
+            if self.focus == Some(field) {
+                text.request_focus();
+            }
+            State::mark(ui, &text, self.violations, field);
+        }
+        enter_pressed(ui, &text)
+    }
+}
+
+const DATE_FORMAT: &str = "%d.%m.%Y";
+impl Input {
+    fn new(row: &OperationsRow, articles: &BTreeMap<i32, ArticlesRow>) -> Self {
+        Self {
+            article: State::format_from_articles(row.article_id, articles),
+            debit: option_to_string(row.debit.as_ref()),
+            credit: option_to_string(row.credit.as_ref()),
+            date: row
+                .create_date
+                .map(|t| t.format(DATE_FORMAT).to_string())
+                .unwrap_or_default(),
+            description: row.description.clone().unwrap_or_default(),
+            tags: row.tags.join(", "),
+            highlight: 0,
+            focus: None,
+        }
     }
+    /// Нарушения правил и текст, который не удалось разобрать
+    fn violations(&self, row: &OperationsRow, rules: &Rules) -> Vec<Violation> {
+        let mut violations = rules.violations(row);
+        for (field, value) in [(Field::Debit, &self.debit), (Field::Credit, &self.credit)] {
+            if parse_amount(value).is_none() {
+                violations.push(Violation {
+                    field,
+                    message: "Введите целое число",
+                });
+            }
+        }
+        if !self.date.trim().is_empty() && parse_date(&self.date).is_none() {
+            violations.push(Violation {
+                field: Field::CreateDate,
+                message: "Дата в формате ДД.ММ.ГГГГ",
+            });
+        }
+        violations
+    }
+}
+/// Пустое поле — ноль, пробелы между разрядами допускаются
+fn parse_amount(text: &str) -> Option<i32> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() {
+        return Some(0);
+    }
+    digits.parse().ok()
+}
+/// Метки через запятую, без пустых и повторов
+fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in text.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        if !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_owned());
+        }
+    }
+    tags
+}
+fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim();
+    [DATE_FORMAT, "%Y-%m-%d"]
+        .into_iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
 }
@@ -0,0 +1,71 @@
+//! Цвета и значки статей на графиках и в таблицах.
+
+use std::collections::BTreeMap;
+
+use egui::{Color32, ecolor::Hsva};
+
+use crate::db::{hierarchy, scheme::ArticlesRow};
+
+/// Цвет статьи. Статья без своего цвета берёт цвет ближайшего предка,
+/// а если цвета нет ни у кого — постоянный цвет по её id.
+pub fn article_color(id: i32, articles: &BTreeMap<i32, ArticlesRow>) -> Color32 {
+    let mut current = Some(id);
+    for _ in 0..=articles.len() {
+        let Some(article) = current.and_then(|id| articles.get(&id)) else {
+            break;
+        };
+        if let Some(color) = own_color(article) {
+            return color;
+        }
+        current = article.parent_id;
+    }
+    generated(id.unsigned_abs())
+}
+
+/// Постоянный цвет метки
+pub fn tag_color(tag: &str) -> Color32 {
+    let seed = tag.chars().fold(0u32, |hash, c| {
+        hash.wrapping_mul(31).wrapping_add(u32::from(c))
+    });
+    generated(seed)
+}
+
+/// Цвет, заданный самой статье
+pub fn own_color(article: &ArticlesRow) -> Option<Color32> {
+    Color32::from_hex(article.color.as_deref()?).ok()
+}
+
+/// Цвет в том виде, в каком он хранится в базе
+pub fn to_hex(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+/// Название статьи со значком, например «🍞 Продукты»
+pub fn label(id: i32, articles: &BTreeMap<i32, ArticlesRow>) -> String {
+    with_icon(
+        articles.get(&id),
+        articles
+            .get(&id)
+            .and_then(|a| a.name.clone())
+            .unwrap_or_default(),
+    )
+}
+
+/// Полный путь статьи со значком, например «🍞 Еда → Продукты»
+pub fn path(id: i32, articles: &BTreeMap<i32, ArticlesRow>) -> String {
+    with_icon(articles.get(&id), hierarchy::path(id, articles))
+}
+
+fn with_icon(article: Option<&ArticlesRow>, text: String) -> String {
+    match article.and_then(|a| a.icon.as_deref()).map(str::trim) {
+        Some(icon) if !icon.is_empty() => format!("{icon} {text}"),
+        _ => text,
+    }
+}
+
+/// Соседние id получают далёкие друг от друга оттенки
+fn generated(seed: u32) -> Color32 {
+    const GOLDEN_RATIO: f32 = 0.618_034;
+    let hue = (seed as f32 * GOLDEN_RATIO).fract();
+    Hsva::new(hue, 0.65, 0.85, 1.0).into()
+}
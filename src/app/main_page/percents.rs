@@ -1,88 +1,180 @@
+use std::collections::BTreeMap;
+
+use super::{export, palette};
 use crate::{
     app::drive_result_promise,
-    db::{Db, scheme::PercentsBar},
+    chart::{Axis, Chart, Kind, Series},
+    db::{
+        Db, Grouping,
+        hierarchy::{self, Rollup, Tree},
+        scheme::{ArticlesRow, PercentsBar},
+    },
     promise_lite::PromiseLite,
 };
-use egui_plot::BarChart;
+use egui::Color32;
+use egui_plot::{Bar, BarChart};
 use tokio_postgres::Error;
 pub struct State {
+    grouping: Grouping,
+    rollup: Rollup,
     values: Option<Bars>,
     error_message: Option<String>,
     result: Option<PromiseLite<Result<Vec<PercentsBar>, Error>>>,
+    export: export::State,
 }
 pub struct Bars {
-    debits: Vec<egui_plot::Bar>,
-    credits: Vec<egui_plot::Bar>,
+    /// Группировка, по которой посчитаны столбцы
+    grouping: Grouping,
+    bars: Vec<PercentsBar>,
+}
+/// Столбец на графике: подпись и цвет статьи или метки
+struct Styled<'a> {
+    bar: &'a PercentsBar,
+    label: String,
+    color: Color32,
 }
 impl State {
     pub fn new(db: &Db) -> Self {
         Self {
+            grouping: Grouping::default(),
+            rollup: Rollup::default(),
             values: None,
             error_message: None,
-            result: Some(db.show_percents()),
+            result: Some(db.show_percents(Grouping::default(), Rollup::default())),
+            export: export::State::new("percents"),
         }
     }
-    pub fn view(&mut self, ui: &mut egui::Ui, db: &Db) {
+    pub fn view(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        articles: Option<&BTreeMap<i32, ArticlesRow>>,
+    ) {
         ui.heading("Проценты");
         let enabled = self.result.is_none();
-        if let Some(values) = &mut self.values {
+        ui.add_enabled_ui(enabled, |ui| {
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                for grouping in [Grouping::Articles, Grouping::Tags] {
+                    changed |= ui
+                        .radio_value(&mut self.grouping, grouping, <&str>::from(grouping))
+                        .changed();
+                }
+            });
+            if let (Grouping::Articles, Some(articles)) = (self.grouping, articles) {
+                changed |= Self::rollup(ui, &mut self.rollup, articles);
+            }
+            if changed {
+                self.result = Some(db.show_percents(self.grouping, self.rollup));
+            }
+        });
+        let empty = BTreeMap::new();
+        let articles = articles.unwrap_or(&empty);
+        if let Some(values) = &self.values {
+            if values.grouping == Grouping::Tags {
+                ui.weak("Операция с несколькими метками учитывается в каждой из них");
+            }
+            let label = axis_label(values.grouping);
+            let styled = values.styled(articles);
             egui::containers::ScrollArea::new([true, true]).show(ui, |ui| {
                 let size = ui.available_height() / 2.0;
                 ui.horizontal(|ui| {
-                    egui_plot::Plot::new("Debit Percents")
-                        .height(size)
-                        .width(size)
-                        .clamp_grid(true)
-                        .x_axis_label("Статьи")
-                        .y_axis_label("Проценты доходов")
-                        .y_axis_formatter(|m, _| format!("{}%", m.value))
-                        .show(ui, |plot_ui| {
-                            plot_ui.bar_chart(BarChart::new(
-                                "Проценты по прибылям",
-                                values.debits.clone(),
-                            ));
-                        });
-                    egui_plot::Plot::new("Credit Percents")
-                        .height(size)
-                        .width(size)
-                        .clamp_grid(true)
-                        .x_axis_label("Статьи")
-                        .y_axis_label("Проценты расходов")
-                        .y_axis_formatter(|m, _| format!("{}%", m.value))
-                        .show(ui, |plot_ui| {
-                            plot_ui.bar_chart(BarChart::new(
-                                "Проценты по расходам",
-                                values.credits.clone(),
-                            ));
-                        });
+                    let plots = [
+                        ("Debit Percents", "Проценты доходов", true),
+                        ("Credit Percents", "Проценты расходов", false),
+                    ];
+                    for (id, y_label, debit) in plots {
+                        egui_plot::Plot::new(id)
+                            .height(size)
+                            .width(size)
+                            .clamp_grid(true)
+                            .legend(egui_plot::Legend::default())
+                            .x_axis_label(label)
+                            .y_axis_label(y_label)
+                            .y_axis_formatter(|m, _| format!("{}%", m.value))
+                            .show(ui, |plot_ui| {
+                                // Отдельный набор на каждую статью, чтобы она попала в легенду
+                                for (i, styled) in styled.iter().enumerate() {
+                                    let value = if debit {
+                                        styled.bar.debit
+                                    } else {
+                                        styled.bar.credit
+                                    };
+                                    let bar = Bar::new(i as f64, value).name(&styled.label);
+                                    plot_ui.bar_chart(
+                                        BarChart::new(&styled.label, vec![bar]).color(styled.color),
+                                    );
+                                }
+                            });
+                    }
                 });
             });
         }
         let reload = egui::Button::new("Перезагрузить!");
         if ui.add_enabled(enabled, reload).clicked() {
-            self.result = Some(db.show_percents())
+            self.result = Some(db.show_percents(self.grouping, self.rollup))
         }
         if let Some(error) = &self.error_message {
             ui.colored_label(egui::Color32::RED, error);
         }
+        self.export
+            .view(ui, || self.values.as_ref().map(|v| v.chart(articles)));
     }
     pub fn drive(&mut self) {
         drive_result_promise!(
             self.result,
-            Ok(values) => {
-                let (debits, credits) = values
-                        .into_iter()
-                        .enumerate()
-                        .map(|(i, b)| (
-                            egui_plot::Bar::new(i as f64, b.debit).name(b.article_name.clone()),
-                            egui_plot::Bar::new(i as f64, b.credit).name(b.article_name),
-                        ))
-                        .collect();
-                self.values = Some(Bars { debits, credits });
+            Ok(bars) => {
+                self.values = Some(Bars {
+                    grouping: self.grouping,
+                    bars,
+                });
                 self.error_message = None;
             },
             Err(err) => self.set_err(err),
         );
+        self.export.drive();
+    }
+    /// Внутри какой статьи и на сколько уровней вглубь смотреть.
+    /// Возвращает `true`, если что-то поменялось.
+    fn rollup(
+        ui: &mut egui::Ui,
+        rollup: &mut Rollup,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) -> bool {
+        let tree = Tree::new(articles);
+        let before = *rollup;
+        ui.horizontal(|ui| {
+            ui.label("Внутри:");
+            let selected = rollup.within.map_or_else(
+                || "всех статей".to_owned(),
+                |id| hierarchy::path(id, articles),
+            );
+            egui::ComboBox::from_id_salt("percents within")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut rollup.within, None, "всех статей");
+                    for (id, depth) in tree.depth_first() {
+                        if tree.children(Some(id)).is_empty() {
+                            continue;
+                        }
+                        ui.selectable_value(&mut rollup.within, Some(id), tree.indented(id, depth));
+                    }
+                });
+            let parent = rollup
+                .within
+                .and_then(|id| articles.get(&id))
+                .map(|article| article.parent_id);
+            if ui
+                .add_enabled(parent.is_some(), egui::Button::new("Наверх"))
+                .clicked()
+            {
+                rollup.within = parent.flatten();
+            }
+            ui.label("Уровней:");
+            ui.add(egui::DragValue::new(&mut rollup.level).range(1..=10));
+        });
+        ui.weak("Более глубокие статьи складываются в статью последнего показанного уровня");
+        *rollup != before
     }
     fn set_err(&mut self, err: impl std::error::Error) {
         let message = format!("{err:?}");
@@ -90,3 +182,49 @@ impl State {
         self.error_message = Some(message);
     }
 }
+impl Bars {
+    fn styled<'a>(&'a self, articles: &BTreeMap<i32, ArticlesRow>) -> Vec<Styled<'a>> {
+        self.bars
+            .iter()
+            .map(|bar| match bar.article_id {
+                Some(id) if articles.contains_key(&id) => Styled {
+                    bar,
+                    label: palette::label(id, articles),
+                    color: palette::article_color(id, articles),
+                },
+                _ => Styled {
+                    bar,
+                    label: bar.article_name.clone(),
+                    color: palette::tag_color(&bar.article_name),
+                },
+            })
+            .collect()
+    }
+    /// Статьи — наборы столбцов, доходы и расходы — две группы по оси x
+    fn chart(&self, articles: &BTreeMap<i32, ArticlesRow>) -> Chart {
+        let kinds = vec!["Доходы".to_owned(), "Расходы".to_owned()];
+        self.styled(articles).into_iter().fold(
+            Chart::new("Проценты", Axis::Categories(kinds))
+                .x_label(axis_label(self.grouping))
+                .y_label("Проценты")
+                .y_suffix("%"),
+            |chart, styled| {
+                chart.series(Series {
+                    name: styled.label,
+                    color: styled.color,
+                    kind: Kind::Bars,
+                    points: vec![
+                        egui_plot::PlotPoint::new(0.0, styled.bar.debit),
+                        egui_plot::PlotPoint::new(1.0, styled.bar.credit),
+                    ],
+                })
+            },
+        )
+    }
+}
+fn axis_label(grouping: Grouping) -> &'static str {
+    match grouping {
+        Grouping::Articles => "Статьи",
+        Grouping::Tags => "Метки",
+    }
+}
@@ -1,5 +1,7 @@
+use super::export;
 use crate::{
     app::drive_result_promise,
+    chart::{Axis, Chart, Kind, Series},
     db::{Db, scheme::ProfitPoint},
     promise_lite::PromiseLite,
 };
@@ -11,6 +13,7 @@ pub struct State {
     values: Option<Vec<egui_plot::PlotPoint>>,
     error_message: Option<String>,
     result: Option<PromiseLite<Result<Vec<ProfitPoint>, Error>>>,
+    export: export::State,
 }
 impl State {
     pub fn new(db: &Db) -> Self {
@@ -18,6 +21,7 @@ impl State {
             values: None,
             error_message: None,
             result: Some(db.show_profit()),
+            export: export::State::new("profit"),
         }
     }
     pub fn view(&mut self, ui: &mut egui::Ui, db: &Db) {
@@ -66,6 +70,8 @@ impl State {
         if let Some(error) = &self.error_message {
             ui.colored_label(egui::Color32::RED, error);
         }
+        self.export
+            .view(ui, || self.values.as_deref().map(Self::chart));
     }
     pub fn drive(&mut self) {
         drive_result_promise!(
@@ -76,6 +82,18 @@ impl State {
             },
             Err(err) => self.set_err(err),
         );
+        self.export.drive();
+    }
+    fn chart(values: &[egui_plot::PlotPoint]) -> Chart {
+        Chart::new("Прибыль", Axis::Time)
+            .x_label("Время")
+            .y_label("Деньги")
+            .series(Series {
+                name: "Прибыль от времени".into(),
+                color: Color32::RED,
+                kind: Kind::LineWithPoints,
+                points: values.to_vec(),
+            })
     }
     fn set_err(&mut self, err: impl std::error::Error) {
         let message = format!("{err:?}");
@@ -0,0 +1,559 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike as _, Local, NaiveDate};
+use tokio_postgres::Error;
+
+use crate::{
+    app::{drive_result_promise, icons, main_page::option_to_string},
+    db::{
+        Db,
+        hierarchy::{self, Tree},
+        recurring::{FREQUENCIES, Frequency, NthWeekday, Posted, Schedule, WEEKDAYS, weekday_name},
+        scheme::{ArticlesRow, RecurringRow},
+        validation::Rules,
+    },
+    promise_lite::PromiseLite,
+};
+pub struct State {
+    templates: Option<BTreeMap<i32, RecurringRow>>,
+    error_message: Option<String>,
+    result: Option<PromiseLite<Result<BTreeMap<i32, RecurringRow>, Error>>>,
+    posting: Option<PromiseLite<Result<Posted, Error>>>,
+    edited: Option<Editor>,
+    /// Окно с наступившими повторениями. «Позже» скрывает его до следующего входа.
+    confirm_open: bool,
+    /// Шаблоны, отмеченные в окне подтверждения
+    chosen: BTreeSet<i32>,
+    /// Сколько операций создано последним разом
+    created: Option<usize>,
+    /// Шаблоны, по которым операции не созданы из-за правил проверки
+    rejected: Vec<i32>,
+    /// Создать операции по шаблонам без подтверждения, как только станут известны правила
+    auto_post: bool,
+}
+struct Editor {
+    id: Option<i32>,
+    row: RecurringRow,
+    /// Создать операции и за даты между началом и сегодняшним днём
+    catch_up: bool,
+}
+/// Шаблон, по которому наступили повторения
+struct Due<'a> {
+    id: i32,
+    row: &'a RecurringRow,
+    dates: Vec<NaiveDate>,
+}
+/// Сколько дат показывать в окне подтверждения, остальные — числом
+const SHOWN_DATES: usize = 3;
+impl State {
+    /// При входе сразу создаются операции по шаблонам, не требующим подтверждения.
+    /// Создаёт их первый вызов [`Self::confirm_window`], куда приходят правила проверки.
+    pub fn new() -> Self {
+        Self {
+            templates: None,
+            error_message: None,
+            result: None,
+            posting: None,
+            edited: None,
+            confirm_open: true,
+            chosen: BTreeSet::new(),
+            created: None,
+            rejected: Vec::new(),
+            auto_post: true,
+        }
+    }
+    /// Перечитывает шаблоны, не создавая операций и не открывая подтверждение заново
+    pub fn reload(&mut self, db: &Db) {
+        self.result = Some(db.select_from_recurring());
+    }
+    pub fn view(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        articles: Option<&BTreeMap<i32, ArticlesRow>>,
+        rules: &Rules,
+    ) {
+        ui.heading("Повторяющиеся операции");
+        let enabled = self.is_idle();
+        let empty = BTreeMap::new();
+        let articles = articles.unwrap_or(&empty);
+        if let Some(action) = self.table(ui, enabled && self.edited.is_none(), articles) {
+            match action {
+                Action::Edit(id) => {
+                    self.edited = self.templates.as_ref().and_then(|t| {
+                        Some(Editor {
+                            id: Some(id),
+                            row: t.get(&id)?.clone(),
+                            catch_up: false,
+                        })
+                    });
+                }
+                Action::Delete(id) => self.result = Some(db.delete_from_recurring(id)),
+            }
+        }
+        ui.horizontal(|ui| {
+            let insert = egui::Button::new("Добавить!");
+            if ui
+                .add_enabled(enabled && self.edited.is_none(), insert)
+                .clicked()
+            {
+                self.edited = Some(Editor::new());
+            }
+            let post = egui::Button::new("Создать наступившие");
+            if ui.add_enabled(enabled, post).clicked() {
+                self.confirm_open = true;
+                self.posting = Some(db.post_recurring(None, true, *rules));
+            }
+            let reload = egui::Button::new("Перезагрузить!");
+            if ui.add_enabled(enabled, reload).clicked() {
+                self.reload(db);
+            }
+        });
+        if let Some(created) = self.created {
+            ui.label(format!("Создано операций по шаблонам: {created}"));
+        }
+        if !self.rejected.is_empty() {
+            let ids: Vec<_> = self.rejected.iter().map(|id| format!("№{id}")).collect();
+            ui.colored_label(
+                ui.visuals().error_fg_color,
+                format!(
+                    "Операции по шаблонам {} не проходят правила проверки и не созданы. \
+                    Исправьте шаблоны или правила",
+                    ids.join(", ")
+                ),
+            );
+        }
+        if let Some(editor) = &mut self.edited {
+            ui.separator();
+            match editor.show(ui, enabled, articles, rules) {
+                Some(true) => {
+                    let (id, row) = editor.finish();
+                    self.result = Some(match id {
+                        Some(id) => db.update_in_recurring(id, row),
+                        None => db.insert_to_recurring(row),
+                    });
+                }
+                Some(false) => self.edited = None,
+                None => {}
+            }
+        }
+        if let Some(error) = &self.error_message {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+    fn table(
+        &self,
+        ui: &mut egui::Ui,
+        enabled: bool,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) -> Option<Action> {
+        let templates = self.templates.as_ref()?;
+        let row_height = ui.spacing().interact_size.y;
+        let max_height = ui.available_height() - 3.0 * row_height;
+        let mut action = None;
+        egui_extras::TableBuilder::new(ui)
+            .id_salt("Recurring")
+            .striped(true)
+            .resizable(true)
+            .max_scroll_height(max_height)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(egui_extras::Column::initial(40.0).at_least(30.0))
+            .column(
+                egui_extras::Column::initial(140.0)
+                    .at_least(30.0)
+                    .clip(true),
+            )
+            .columns(egui_extras::Column::initial(80.0).at_least(30.0), 2)
+            .column(
+                egui_extras::Column::initial(260.0)
+                    .at_least(30.0)
+                    .clip(true),
+            )
+            .columns(egui_extras::Column::initial(100.0).at_least(30.0), 2)
+            .column(egui_extras::Column::remainder().at_least(60.0))
+            .header(row_height, |mut header| {
+                let titles = [
+                    "id",
+                    "Статья",
+                    "Доход",
+                    "Расход",
+                    "Расписание",
+                    "Следующая",
+                    "Подтверждение",
+                    "Шаблоны",
+                ];
+                for title in titles {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                let rows: Vec<_> = templates.iter().collect();
+                body.rows(row_height, rows.len(), |mut table_row| {
+                    let Some((id, row)) = rows.get(table_row.index()) else {
+                        return;
+                    };
+                    let cells = [
+                        id.to_string(),
+                        article_name(row.article_id, articles),
+                        option_to_string(row.debit.as_ref()),
+                        option_to_string(row.credit.as_ref()),
+                        row.schedule.describe(),
+                        row.next_due.map_or_else(
+                            || "закончено".into(),
+                            |d| d.format("%d.%m.%Y").to_string(),
+                        ),
+                        if row.confirm {
+                            "спрашивать"
+                        } else {
+                            ""
+                        }
+                        .into(),
+                    ];
+                    for cell in cells {
+                        table_row.col(|ui| {
+                            ui.label(cell);
+                        });
+                    }
+                    table_row.col(|ui| {
+                        let edit = egui::Button::new(icons::EDIT).small();
+                        let remove = egui::Button::new(icons::REMOVE).small();
+                        if ui.add_enabled(enabled, edit).clicked() {
+                            action = Some(Action::Edit(**id));
+                        }
+                        if ui.add_enabled(enabled, remove).clicked() {
+                            action = Some(Action::Delete(**id));
+                        }
+                    });
+                });
+            });
+        action
+    }
+    /// Окно со списком наступивших повторений, которые требуют подтверждения
+    pub fn confirm_window(
+        &mut self,
+        ctx: &egui::Context,
+        db: &Db,
+        articles: Option<&BTreeMap<i32, ArticlesRow>>,
+        rules: &Rules,
+    ) {
+        if self.posting.is_none() && std::mem::take(&mut self.auto_post) {
+            self.posting = Some(db.post_recurring(None, true, *rules));
+        }
+        let today = Local::now().date_naive();
+        let due = due(self.templates.as_ref(), today);
+        if !self.confirm_open || due.is_empty() {
+            return;
+        }
+        let empty = BTreeMap::new();
+        let articles = articles.unwrap_or(&empty);
+        let enabled = self.is_idle();
+        let mut post = None;
+        egui::Window::new("Наступили повторяющиеся операции")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                for Due { id, row, dates } in &due {
+                    let mut chosen = self.chosen.contains(id);
+                    let text = format!(
+                        "{}: {} — {}",
+                        article_name(row.article_id, articles),
+                        amount(row),
+                        format_dates(dates),
+                    );
+                    if ui.checkbox(&mut chosen, text).changed() {
+                        if chosen {
+                            self.chosen.insert(*id);
+                        } else {
+                            self.chosen.remove(id);
+                        }
+                    }
+                }
+                ui.horizontal(|ui| {
+                    let any = enabled && !self.chosen.is_empty();
+                    if ui.add_enabled(any, egui::Button::new("Создать")).clicked() {
+                        post = Some(true);
+                    }
+                    if ui
+                        .add_enabled(any, egui::Button::new("Пропустить"))
+                        .on_hover_text("Перейти к следующим датам, не создавая операций")
+                        .clicked()
+                    {
+                        post = Some(false);
+                    }
+                    if ui.button("Позже").clicked() {
+                        self.confirm_open = false;
+                    }
+                });
+            });
+        if let Some(create) = post {
+            let ids = self.chosen.iter().copied().collect();
+            self.posting = Some(db.post_recurring(Some(ids), create, *rules));
+        }
+    }
+    /// Возвращает `true`, если по шаблонам созданы операции
+    pub fn drive(&mut self) -> bool {
+        let mut created = false;
+        drive_result_promise!(
+            self.result,
+            Ok(values) => {
+                self.templates = Some(values);
+                self.edited = None;
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+        drive_result_promise!(
+            self.posting,
+            Ok(posted) => {
+                created = posted.created > 0;
+                self.created = Some(posted.created).filter(|c| *c > 0);
+                self.rejected = posted.rejected;
+                self.templates = Some(posted.templates);
+                // По умолчанию отмечено всё, что наступило
+                let today = Local::now().date_naive();
+                self.chosen = due(self.templates.as_ref(), today)
+                    .iter()
+                    .map(|due| due.id)
+                    .collect();
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+        created
+    }
+    fn is_idle(&self) -> bool {
+        self.result.is_none() && self.posting.is_none()
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = format!("{err:?}");
+        log::error!("{}", message);
+        self.error_message = Some(message);
+    }
+}
+enum Action {
+    Edit(i32),
+    Delete(i32),
+}
+impl Editor {
+    fn new() -> Self {
+        Self {
+            id: None,
+            row: RecurringRow {
+                article_id: None,
+                debit: Some(0),
+                credit: Some(0),
+                schedule: Schedule::new(Local::now().date_naive()),
+                next_due: None,
+                confirm: false,
+            },
+            catch_up: false,
+        }
+    }
+    /// Дата следующего повторения считается заново по изменённому расписанию
+    fn finish(&mut self) -> (Option<i32>, RecurringRow) {
+        let today = Local::now().date_naive();
+        let schedule = &self.row.schedule;
+        let from = if self.catch_up {
+            schedule.start
+        } else {
+            self.row.next_due.unwrap_or(today)
+        };
+        self.row.next_due = schedule.first_from(from);
+        (self.id, self.row.clone())
+    }
+    /// `Some(true)` — сохранить, `Some(false)` — отменить
+    fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        enabled: bool,
+        articles: &BTreeMap<i32, ArticlesRow>,
+        rules: &Rules,
+    ) -> Option<bool> {
+        ui.strong(match self.id {
+            Some(id) => format!("Шаблон №{id}"),
+            None => "Новый шаблон".into(),
+        });
+        let row = &mut self.row;
+        egui::Grid::new("recurring editor")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Статья");
+                egui::ComboBox::from_id_salt("recurring article")
+                    .selected_text(article_name(row.article_id, articles))
+                    .show_ui(ui, |ui| {
+                        let tree = Tree::new(articles);
+                        for (id, depth) in tree.active() {
+                            ui.selectable_value(
+                                &mut row.article_id,
+                                Some(id),
+                                tree.indented(id, depth),
+                            );
+                        }
+                    });
+                ui.end_row();
+                for (label, value) in [("Доход", &mut row.debit), ("Расход", &mut row.credit)]
+                {
+                    ui.label(label);
+                    let mut amount = value.unwrap_or(0);
+                    ui.add(egui::DragValue::new(&mut amount).speed(0.5));
+                    *value = Some(amount);
+                    ui.end_row();
+                }
+                Self::schedule(ui, &mut row.schedule);
+                ui.label("Подтверждение");
+                ui.checkbox(&mut row.confirm, "Спрашивать перед созданием операций");
+                ui.end_row();
+            });
+        if self.id.is_none() {
+            ui.checkbox(
+                &mut self.catch_up,
+                "Создать операции и за прошедшие даты с начала",
+            );
+        }
+        let violations = rules.violations(&row.operation(row.schedule.start));
+        for violation in &violations {
+            ui.colored_label(ui.visuals().error_fg_color, violation.message);
+        }
+        let mut response = None;
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(
+                    enabled && violations.is_empty(),
+                    egui::Button::new("Сохранить"),
+                )
+                .clicked()
+            {
+                response = Some(true);
+            }
+            if ui
+                .add_enabled(enabled, egui::Button::new("Отмена"))
+                .clicked()
+            {
+                response = Some(false);
+            }
+        });
+        response
+    }
+    fn schedule(ui: &mut egui::Ui, schedule: &mut Schedule) {
+        ui.label("Повторять");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("recurring frequency")
+                .selected_text(<&str>::from(schedule.frequency))
+                .show_ui(ui, |ui| {
+                    for frequency in FREQUENCIES {
+                        ui.selectable_value(
+                            &mut schedule.frequency,
+                            frequency,
+                            <&str>::from(frequency),
+                        );
+                    }
+                });
+            ui.label("раз в");
+            ui.add(egui::DragValue::new(&mut schedule.every).range(1..=365));
+        });
+        ui.end_row();
+        if matches!(schedule.frequency, Frequency::Monthly | Frequency::Yearly) {
+            ui.label("День");
+            ui.horizontal(|ui| {
+                let mut by_weekday = schedule.nth_weekday.is_some();
+                ui.checkbox(&mut by_weekday, "по дню недели");
+                schedule.nth_weekday = by_weekday.then(|| {
+                    schedule.nth_weekday.unwrap_or(NthWeekday {
+                        n: 1,
+                        weekday: schedule.start.weekday(),
+                    })
+                });
+                if let Some(nth) = &mut schedule.nth_weekday {
+                    egui::ComboBox::from_id_salt("recurring nth")
+                        .selected_text(nth_name(nth.n))
+                        .show_ui(ui, |ui| {
+                            for n in [1, 2, 3, 4, -1] {
+                                ui.selectable_value(&mut nth.n, n, nth_name(n));
+                            }
+                        });
+                    egui::ComboBox::from_id_salt("recurring weekday")
+                        .selected_text(weekday_name(nth.weekday))
+                        .show_ui(ui, |ui| {
+                            for weekday in WEEKDAYS {
+                                ui.selectable_value(
+                                    &mut nth.weekday,
+                                    weekday,
+                                    weekday_name(weekday),
+                                );
+                            }
+                        });
+                }
+            });
+            ui.end_row();
+        } else {
+            schedule.nth_weekday = None;
+        }
+        ui.label("Начало");
+        ui.add(egui_extras::DatePickerButton::new(&mut schedule.start).id_salt("recurring start"));
+        ui.end_row();
+        ui.label("Окончание");
+        ui.horizontal(|ui| {
+            let mut has_end = schedule.end.is_some();
+            ui.checkbox(&mut has_end, "до даты");
+            if has_end {
+                let mut end = schedule.end.unwrap_or(schedule.start);
+                ui.add(egui_extras::DatePickerButton::new(&mut end).id_salt("recurring end"));
+                schedule.end = Some(end);
+            } else {
+                schedule.end = None;
+            }
+        });
+        ui.end_row();
+        ui.label("Описание");
+        ui.label(schedule.describe());
+        ui.end_row();
+    }
+}
+/// Шаблоны с подтверждением, по которым наступили повторения
+fn due(templates: Option<&BTreeMap<i32, RecurringRow>>, today: NaiveDate) -> Vec<Due<'_>> {
+    let Some(templates) = templates else {
+        return Vec::new();
+    };
+    templates
+        .iter()
+        .filter(|(_, row)| row.confirm)
+        .map(|(id, row)| Due {
+            id: *id,
+            row,
+            dates: row.schedule.due(row.next_due, today),
+        })
+        .filter(|due| !due.dates.is_empty())
+        .collect()
+}
+fn article_name(id: Option<i32>, articles: &BTreeMap<i32, ArticlesRow>) -> String {
+    id.map(|id| hierarchy::path(id, articles))
+        .unwrap_or_default()
+}
+fn amount(row: &RecurringRow) -> String {
+    match (row.debit.unwrap_or(0), row.credit.unwrap_or(0)) {
+        (0, credit) => format!("−{credit}"),
+        (debit, 0) => format!("+{debit}"),
+        (debit, credit) => format!("+{debit} / −{credit}"),
+    }
+}
+fn format_dates(dates: &[NaiveDate]) -> String {
+    let mut text = dates
+        .iter()
+        .take(SHOWN_DATES)
+        .map(|d| d.format("%d.%m.%Y").to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if dates.len() > SHOWN_DATES {
+        text.push_str(&format!(" и ещё {}", dates.len() - SHOWN_DATES));
+    }
+    text
+}
+fn nth_name(n: i32) -> String {
+    if n > 0 {
+        format!("{n}-я неделя")
+    } else {
+        "последняя неделя".into()
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use chrono::{Datelike as _, Days, Local, NaiveDate};
+
+use crate::{
+    app::{drive_result_promise, main_page::option_to_string},
+    db::{Db, scheme::BalanceRow},
+    promise_lite::PromiseLite,
+    report::{self, Period},
+};
+pub struct State {
+    by_balance: bool,
+    start: NaiveDate,
+    end: NaiveDate,
+    balance_id: Option<i32>,
+    path: String,
+    message: Option<String>,
+    error_message: Option<String>,
+    result: Option<PromiseLite<Result<(), report::Error>>>,
+}
+impl State {
+    pub fn new() -> Self {
+        // По умолчанию — прошлый месяц целиком
+        let today = Local::now().date_naive();
+        let end = today
+            .with_day(1)
+            .and_then(|d| d.checked_sub_days(Days::new(1)))
+            .unwrap_or(today);
+        let start = end.with_day(1).unwrap_or(end);
+        Self {
+            by_balance: false,
+            start,
+            end,
+            balance_id: None,
+            path: format!("report-{}.html", today.format("%Y-%m-%d")),
+            message: None,
+            error_message: None,
+            result: None,
+        }
+    }
+    pub fn view(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &Db,
+        balance: Option<&BTreeMap<i32, BalanceRow>>,
+    ) {
+        ui.heading("Отчёт");
+        let enabled = self.result.is_none();
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.by_balance, false, "За период");
+            ui.radio_value(&mut self.by_balance, true, "По балансу");
+        });
+        if self.by_balance {
+            egui::ComboBox::from_label("Баланс")
+                .selected_text(Self::format_from_balance(self.balance_id, balance))
+                .show_ui(ui, |ui| {
+                    for (id, row) in balance.into_iter().flatten() {
+                        ui.selectable_value(
+                            &mut self.balance_id,
+                            Some(*id),
+                            Self::format_balance(*id, row),
+                        );
+                    }
+                });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label("С");
+                ui.add(egui_extras::DatePickerButton::new(&mut self.start).id_salt("report start"));
+                ui.label("по");
+                ui.add(egui_extras::DatePickerButton::new(&mut self.end).id_salt("report end"));
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Файл:");
+            ui.add_enabled(enabled, egui::TextEdit::singleline(&mut self.path));
+        });
+        let period = self.period();
+        let generate = egui::Button::new("Сформировать отчёт!");
+        if ui
+            .add_enabled(enabled && period.is_some(), generate)
+            .clicked()
+            && let Some(period) = period
+        {
+            log::info!("Формируем отчёт в {}", self.path);
+            self.message = None;
+            self.result = Some(db.save_report(period, PathBuf::from(&self.path)));
+        }
+        if let Some(message) = &self.message {
+            ui.label(message);
+        }
+        if let Some(error) = &self.error_message {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    }
+    pub fn drive(&mut self) {
+        drive_result_promise!(
+            self.result,
+            Ok(_saved) => {
+                self.message = Some(format!("Отчёт сохранён в {}", self.path));
+                self.error_message = None;
+            },
+            Err(err) => self.set_err(err),
+        );
+    }
+    fn period(&self) -> Option<Period> {
+        if self.by_balance {
+            self.balance_id.map(Period::Balance)
+        } else {
+            (self.start <= self.end).then_some(Period::Dates {
+                start: self.start,
+                end: self.end,
+            })
+        }
+    }
+    fn format_from_balance(id: Option<i32>, balance: Option<&BTreeMap<i32, BalanceRow>>) -> String {
+        if let Some(id) = id
+            && let Some(row) = balance.and_then(|b| b.get(&id))
+        {
+            return Self::format_balance(id, row);
+        }
+        String::new()
+    }
+    fn format_balance(id: i32, row: &BalanceRow) -> String {
+        format!("{id} ({})", option_to_string(row.create_date.as_ref()))
+    }
+    fn set_err(&mut self, err: impl std::error::Error) {
+        let message = err.to_string();
+        log::error!("{message}");
+        self.error_message = Some(message);
+    }
+}
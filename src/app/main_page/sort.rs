@@ -0,0 +1,121 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+/// Сколько столбцов участвует в сортировке: основной и дополнительный
+const MAX_KEYS: usize = 2;
+
+/// Порядок строк таблицы. Пустой порядок — по id, как пришло из базы.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Order<C> {
+    keys: Vec<Key<C>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Key<C> {
+    pub column: C,
+    pub descending: bool,
+}
+
+impl<C> Default for Order<C> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<C: Copy + PartialEq> Order<C> {
+    /// Нажатие на заголовок. Повторное нажатие на основной столбец меняет
+    /// направление, новый столбец становится основным, а прежний — дополнительным.
+    pub fn click(&mut self, column: C) {
+        if let Some(first) = self.keys.first_mut()
+            && first.column == column
+        {
+            first.descending = !first.descending;
+            return;
+        }
+        self.keys.retain(|k| k.column != column);
+        self.keys.insert(
+            0,
+            Key {
+                column,
+                descending: false,
+            },
+        );
+        self.keys.truncate(MAX_KEYS);
+    }
+    /// Устойчивая сортировка: при равенстве по всем ключам сохраняется порядок по id
+    pub fn sort<T>(&self, items: &mut [T], compare: impl Fn(C, &T, &T) -> Compared) {
+        items.sort_by(|a, b| {
+            self.keys
+                .iter()
+                .map(|key| {
+                    let Compared { ordering, null } = compare(key.column, a, b);
+                    // Пустые значения всегда в конце, независимо от направления
+                    if key.descending && !null {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                })
+                .find(|o| o.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+    fn position(&self, column: C) -> Option<(usize, &Key<C>)> {
+        self.keys
+            .iter()
+            .enumerate()
+            .find(|(_, k)| k.column == column)
+    }
+}
+
+/// Результат сравнения по одному столбцу
+pub struct Compared {
+    ordering: Ordering,
+    /// Порядок решило отсутствие значения, направление сортировки его не меняет
+    null: bool,
+}
+
+/// Сравнение значений, которые могут отсутствовать
+pub fn nullable<T: Ord>(a: Option<T>, b: Option<T>) -> Compared {
+    let (ordering, null) = match (a, b) {
+        (Some(a), Some(b)) => (a.cmp(&b), false),
+        (Some(_), None) => (Ordering::Less, true),
+        (None, Some(_)) => (Ordering::Greater, true),
+        (None, None) => (Ordering::Equal, false),
+    };
+    Compared { ordering, null }
+}
+
+/// Названия сравниваем без учёта регистра
+pub fn text(a: Option<&str>, b: Option<&str>) -> Compared {
+    nullable(a.map(str::to_lowercase), b.map(str::to_lowercase))
+}
+
+/// Заголовок столбца, по нажатию на который меняется сортировка
+pub fn header<C: Copy + PartialEq>(
+    ui: &mut egui::Ui,
+    label: &str,
+    column: C,
+    order: &mut Order<C>,
+) -> bool {
+    let text = match order.position(column) {
+        Some((i, key)) => {
+            let arrow = if key.descending { "⏷" } else { "⏶" };
+            if order.keys.len() > 1 {
+                format!("{label} {arrow}{}", i + 1)
+            } else {
+                format!("{label} {arrow}")
+            }
+        }
+        None => label.to_owned(),
+    };
+    let clicked = ui
+        .add(egui::Button::new(egui::RichText::new(text).strong()).frame(false))
+        .on_hover_text("Сортировать")
+        .clicked();
+    if clicked {
+        order.click(column);
+    }
+    clicked
+}
@@ -0,0 +1,95 @@
+/// Итоги по видимым строкам таблицы: сумма, среднее и крайние значения
+/// дохода и расхода и разница между ними
+#[derive(Default)]
+pub struct Totals {
+    debit: Stats,
+    credit: Stats,
+}
+
+/// Пустые суммы не учитываются ни в среднем, ни в минимуме с максимумом
+#[derive(Default)]
+struct Stats {
+    sum: i64,
+    count: u32,
+    min: Option<i32>,
+    max: Option<i32>,
+}
+
+impl Stats {
+    fn add(&mut self, value: Option<i32>) {
+        let Some(value) = value else {
+            return;
+        };
+        self.sum += i64::from(value);
+        self.count += 1;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+    fn cells(&self) -> [String; 4] {
+        let average =
+            (self.count > 0).then(|| format!("{:.2}", self.sum as f64 / f64::from(self.count)));
+        [
+            self.sum.to_string(),
+            average.unwrap_or_default(),
+            self.min.map(|min| min.to_string()).unwrap_or_default(),
+            self.max.map(|max| max.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+impl Totals {
+    /// Строка под таблицей: заголовок и три строки итогов
+    pub const ROWS: f32 = 4.0;
+
+    /// `rows` — доход и расход каждой видимой строки
+    pub fn new(rows: impl IntoIterator<Item = (Option<i32>, Option<i32>)>) -> Self {
+        let mut totals = Self::default();
+        for (debit, credit) in rows {
+            totals.debit.add(debit);
+            totals.credit.add(credit);
+        }
+        totals
+    }
+    /// Доход минус расход
+    pub fn net(&self) -> i64 {
+        self.debit.sum - self.credit.sum
+    }
+    /// Числа копируются в буфер обмена щелчком
+    pub fn show(&self, ui: &mut egui::Ui, id_salt: &str) {
+        egui::Grid::new(("totals", id_salt))
+            .striped(true)
+            .show(ui, |ui| {
+                for title in ["Итого", "Сумма", "Среднее", "Мин.", "Макс."]
+                {
+                    ui.strong(title);
+                }
+                ui.end_row();
+                for (title, stats) in [("Доход", &self.debit), ("Расход", &self.credit)]
+                {
+                    ui.label(title);
+                    for cell in stats.cells() {
+                        copyable(ui, cell);
+                    }
+                    ui.end_row();
+                }
+                ui.label("Результат");
+                copyable(ui, self.net().to_string());
+                ui.end_row();
+            });
+    }
+}
+
+fn copyable(ui: &mut egui::Ui, text: String) {
+    if text.is_empty() {
+        ui.label("");
+        return;
+    }
+    let label = egui::Label::new(&text).sense(egui::Sense::click());
+    if ui
+        .add(label)
+        .on_hover_text("Нажмите, чтобы скопировать")
+        .clicked()
+    {
+        ui.ctx().copy_text(text);
+    }
+}
@@ -0,0 +1,424 @@
+use std::{fmt, path::Path};
+
+use chrono::DateTime;
+use egui::Color32;
+use egui_plot::PlotPoint;
+use resvg::{tiny_skia, usvg};
+
+/// Описание графика, не зависящее от egui: из него рисуются
+/// картинки для отчётов и файлов.
+pub struct Chart {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub x_axis: Axis,
+    pub y_suffix: &'static str,
+    pub series: Vec<Series>,
+}
+
+pub enum Axis {
+    /// Значения по x — unix timestamp в секундах
+    Time,
+    /// Значения по x — номера категорий
+    Categories(Vec<String>),
+}
+
+pub struct Series {
+    pub name: String,
+    pub color: Color32,
+    pub kind: Kind,
+    pub points: Vec<PlotPoint>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Line,
+    /// Линия пунктиром, чтобы отличать её от линии того же цвета
+    DashedLine,
+    Points,
+    LineWithPoints,
+    Bars,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Svg,
+    Png,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Svg(usvg::Error),
+    Png(String),
+}
+
+struct Frame {
+    left: f64,
+    top: f64,
+    right: f64,
+    bottom: f64,
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+const TICKS: usize = 6;
+const BAR_WIDTH: f64 = 0.6;
+const DAY: f64 = 86400.0;
+
+impl Chart {
+    pub fn new(title: impl Into<String>, x_axis: Axis) -> Self {
+        Self {
+            title: title.into(),
+            x_label: String::new(),
+            y_label: String::new(),
+            x_axis,
+            y_suffix: "",
+            series: Vec::new(),
+        }
+    }
+    pub fn x_label(mut self, label: impl Into<String>) -> Self {
+        self.x_label = label.into();
+        self
+    }
+    pub fn y_label(mut self, label: impl Into<String>) -> Self {
+        self.y_label = label.into();
+        self
+    }
+    pub fn y_suffix(mut self, suffix: &'static str) -> Self {
+        self.y_suffix = suffix;
+        self
+    }
+    pub fn series(mut self, series: Series) -> Self {
+        self.series.push(series);
+        self
+    }
+    /// Рисует график в выбранном формате и сохраняет в файл
+    pub async fn save(
+        &self,
+        path: &Path,
+        format: Format,
+        width: u32,
+        height: u32,
+    ) -> Result<(), Error> {
+        let bytes = match format {
+            Format::Svg => self.to_svg(width, height).into_bytes(),
+            Format::Png => self.to_png(width, height)?,
+        };
+        tokio::fs::write(path, bytes).await?;
+        Ok(())
+    }
+    /// Растеризует SVG. Шрифты берём встроенные в egui, чтобы
+    /// картинка не зависела от установленных в системе.
+    pub fn to_png(&self, width: u32, height: u32) -> Result<Vec<u8>, Error> {
+        let mut options = usvg::Options::default();
+        let fontdb = options.fontdb_mut();
+        fontdb.load_font_data(epaint_default_fonts::UBUNTU_LIGHT.to_vec());
+        fontdb.load_font_data(epaint_default_fonts::NOTO_EMOJI_REGULAR.to_vec());
+        let family = fontdb
+            .faces()
+            .next()
+            .and_then(|face| face.families.first())
+            .map(|(name, _)| name.clone());
+        if let Some(family) = family {
+            fontdb.set_sans_serif_family(family);
+        }
+        let tree = usvg::Tree::from_str(&self.to_svg(width, height), &options)?;
+        let mut pixmap = tiny_skia::Pixmap::new(width, height)
+            .ok_or_else(|| Error::Png(format!("Недопустимый размер {width}x{height}")))?;
+        resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+        pixmap
+            .encode_png()
+            .map_err(|err| Error::Png(err.to_string()))
+    }
+    /// Рисует график в SVG заданного размера
+    pub fn to_svg(&self, width: u32, height: u32) -> String {
+        let frame = self.frame(f64::from(width), f64::from(height));
+        let mut svg = String::new();
+        svg.push_str(&format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif" font-size="12">"#
+        ));
+        svg.push_str(&format!(
+            r#"<rect width="{width}" height="{height}" fill="white"/>"#
+        ));
+        svg.push_str(&format!(r#"<text x="{}" y="22" font-size="16" font-weight="bold" text-anchor="middle">{}</text>"#,
+            f64::from(width) / 2.0,
+            escape(&self.title)
+        ));
+        self.write_grid(&mut svg, &frame);
+        for (index, series) in self.series.iter().enumerate() {
+            self.write_series(&mut svg, &frame, series, index);
+        }
+        self.write_legend(&mut svg, &frame);
+        svg.push_str(&format!(
+            r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            f64::midpoint(frame.left, frame.right),
+            f64::from(height) - 8.0,
+            escape(&self.x_label)
+        ));
+        svg.push_str(&format!(
+            r#"<text transform="translate(16 {}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            f64::midpoint(frame.top, frame.bottom),
+            escape(&self.y_label)
+        ));
+        svg.push_str("</svg>\n");
+        svg
+    }
+    fn frame(&self, width: f64, height: f64) -> Frame {
+        let points = self.series.iter().flat_map(|s| s.points.iter());
+        let (mut x_min, mut x_max, mut y_min, mut y_max) = points.fold(
+            (
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(x0, x1, y0, y1), p| (x0.min(p.x), x1.max(p.x), y0.min(p.y), y1.max(p.y)),
+        );
+        if let Axis::Categories(names) = &self.x_axis {
+            x_min = -0.5;
+            x_max = names.len().max(1) as f64 - 0.5;
+        }
+        if self.series.iter().any(|s| s.kind == Kind::Bars) {
+            y_min = y_min.min(0.0);
+            y_max = y_max.max(0.0);
+        }
+        if !x_min.is_finite() {
+            (x_min, x_max) = (0.0, 1.0);
+        }
+        if !y_min.is_finite() {
+            (y_min, y_max) = (0.0, 1.0);
+        }
+        if x_max <= x_min {
+            (x_min, x_max) = (x_min - DAY, x_max + DAY);
+        }
+        if matches!(self.x_axis, Axis::Time) {
+            let pad = (x_max - x_min) * 0.05;
+            (x_min, x_max) = (x_min - pad, x_max + pad);
+        }
+        if y_max <= y_min {
+            (y_min, y_max) = (y_min - 1.0, y_max + 1.0);
+        }
+        let pad = (y_max - y_min) * 0.05;
+        Frame {
+            left: 80.0,
+            top: 40.0,
+            right: width - 20.0,
+            bottom: height - 60.0,
+            x_min,
+            x_max,
+            y_min: if y_min < 0.0 { y_min - pad } else { y_min },
+            y_max: y_max + pad,
+        }
+    }
+    fn write_grid(&self, svg: &mut String, frame: &Frame) {
+        svg.push_str(&format!(
+            r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#888"/>"##,
+            frame.left,
+            frame.top,
+            frame.right - frame.left,
+            frame.bottom - frame.top
+        ));
+        for y in nice_ticks(frame.y_min, frame.y_max) {
+            let sy = frame.y(y);
+            svg.push_str(&format!(r##"<line x1="{}" y1="{sy}" x2="{}" y2="{sy}" stroke="#ddd"/><text x="{}" y="{}" text-anchor="end">{}{}</text>"##,
+                frame.left,
+                frame.right,
+                frame.left - 6.0,
+                sy + 4.0,
+                format_number(y),
+                self.y_suffix
+            ));
+        }
+        let label_y = frame.bottom + 18.0;
+        match &self.x_axis {
+            Axis::Categories(names) => {
+                let rotate = names.len() > 8;
+                for (i, name) in names.iter().enumerate() {
+                    let sx = frame.x(i as f64);
+                    if rotate {
+                        svg.push_str(&format!(r#"<text transform="translate({sx} {label_y}) rotate(-30)" text-anchor="end">{}</text>"#,
+                            escape(name)
+                        ));
+                    } else {
+                        svg.push_str(&format!(
+                            r#"<text x="{sx}" y="{label_y}" text-anchor="middle">{}</text>"#,
+                            escape(name)
+                        ));
+                    }
+                }
+            }
+            Axis::Time => {
+                // Деления по целым дням
+                let days = nice_ticks(frame.x_min / DAY, frame.x_max / DAY);
+                for x in days.into_iter().map(|d| d * DAY) {
+                    let sx = frame.x(x);
+                    let label = format_time(x);
+                    svg.push_str(&format!(r##"<line x1="{sx}" y1="{}" x2="{sx}" y2="{}" stroke="#ddd"/><text x="{sx}" y="{label_y}" text-anchor="middle">{label}</text>"##,
+                        frame.top, frame.bottom
+                    ));
+                }
+            }
+        }
+    }
+    fn write_series(&self, svg: &mut String, frame: &Frame, series: &Series, index: usize) {
+        let color = hex(series.color);
+        match series.kind {
+            Kind::Bars => {
+                let bars: Vec<usize> = self
+                    .series
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| s.kind == Kind::Bars)
+                    .map(|(i, _)| i)
+                    .collect();
+                let count = bars.len().max(1) as f64;
+                let slot = bars.iter().position(|i| *i == index).unwrap_or(0) as f64;
+                let width = BAR_WIDTH / count;
+                for point in &series.points {
+                    let x0 = point.x - BAR_WIDTH / 2.0 + slot * width;
+                    let (sx0, sx1) = (frame.x(x0), frame.x(x0 + width));
+                    let (sy0, sy1) = (frame.y(point.y.max(0.0)), frame.y(point.y.min(0.0)));
+                    svg.push_str(&format!(
+                        r#"<rect x="{sx0}" y="{sy0}" width="{}" height="{}" fill="{color}"/>"#,
+                        sx1 - sx0,
+                        sy1 - sy0
+                    ));
+                }
+            }
+            Kind::Line | Kind::DashedLine | Kind::Points | Kind::LineWithPoints => {
+                if series.kind != Kind::Points && !series.points.is_empty() {
+                    let path: Vec<String> = series
+                        .points
+                        .iter()
+                        .map(|p| format!("{},{}", frame.x(p.x), frame.y(p.y)))
+                        .collect();
+                    let dash = if series.kind == Kind::DashedLine {
+                        r#" stroke-dasharray="6 4""#
+                    } else {
+                        ""
+                    };
+                    svg.push_str(&format!(
+                        r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="2"{dash}/>"#,
+                        path.join(" ")
+                    ));
+                }
+                if matches!(series.kind, Kind::Points | Kind::LineWithPoints) {
+                    for p in &series.points {
+                        svg.push_str(&format!(
+                            r#"<circle cx="{}" cy="{}" r="4" fill="{color}"/>"#,
+                            frame.x(p.x),
+                            frame.y(p.y)
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    fn write_legend(&self, svg: &mut String, frame: &Frame) {
+        let x = frame.right - 190.0;
+        for (i, series) in self.series.iter().enumerate() {
+            let y = frame.top + 10.0 + 18.0 * i as f64;
+            svg.push_str(&format!(r#"<rect x="{x}" y="{y}" width="12" height="12" fill="{}"/><text x="{}" y="{}">{}</text>"#,
+                hex(series.color),
+                x + 18.0,
+                y + 11.0,
+                escape(&series.name)
+            ));
+        }
+    }
+}
+
+impl Frame {
+    fn x(&self, x: f64) -> f64 {
+        self.left + (x - self.x_min) / (self.x_max - self.x_min) * (self.right - self.left)
+    }
+    fn y(&self, y: f64) -> f64 {
+        self.bottom - (y - self.y_min) / (self.y_max - self.y_min) * (self.bottom - self.top)
+    }
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Svg => "svg",
+            Self::Png => "png",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "Ошибка файла: {err}"),
+            Self::Svg(err) => write!(f, "Ошибка SVG: {err}"),
+            Self::Png(err) => write!(f, "Ошибка PNG: {err}"),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<usvg::Error> for Error {
+    fn from(err: usvg::Error) -> Self {
+        Self::Svg(err)
+    }
+}
+
+/// Круглые значения делений на отрезке `[min, max]`
+fn nice_ticks(min: f64, max: f64) -> Vec<f64> {
+    let raw = (max - min) / TICKS as f64;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= raw)
+        .unwrap_or(raw);
+    let mut tick = (min / step).ceil() * step;
+    let mut ticks = Vec::new();
+    while tick <= max {
+        ticks.push(tick);
+        tick += step;
+    }
+    ticks
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract().abs() < 1e-9 {
+        format!("{value:.0}")
+    } else {
+        format!("{value:.2}")
+    }
+}
+
+fn format_time(timestamp: f64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+fn hex(color: Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+/// Экранирует текст для вставки в SVG и HTML
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
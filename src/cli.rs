@@ -0,0 +1,323 @@
+//! Консольный режим для запуска по расписанию без окна.
+//!
+//! Результаты печатаются в stdout в JSON, ошибки — в stderr.
+//! Код возврата: 0 — успех, 1 — ошибка базы или файла, 2 — неверные аргументы.
+//!
+//! В Windows релизная сборка не имеет консоли (см. `main.rs`), поэтому вывод
+//! виден только при перенаправлении в файл, а код возврата — при ожидании
+//! процесса, например через `start /wait` или планировщик заданий.
+
+use std::{collections::BTreeMap, io::Write as _, path::PathBuf, process::ExitCode};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{
+    db::{
+        Db, Grouping,
+        backup::{Entry, RestoreMode},
+        hierarchy::Rollup,
+    },
+    report::Period,
+};
+
+const USAGE: &str = "\
+Использование: dbgui [--user ИМЯ] [--password-file ФАЙЛ] КОМАНДА
+
+Без команды запускается графический интерфейс.
+Пользователь также берётся из DBGUI_USER, пароль — из DBGUI_PASSWORD
+или из первой строки файла. В аргументах пароль не принимается:
+их видно в списке процессов и в журналах планировщика.
+
+Команды:
+  export [--output ФАЙЛ]                 резервная копия в JSON
+  import ФАЙЛ [--replace]                восстановить копию (по умолчанию — добавить)
+  balance create [--to ДАТА]             сформировать баланс из операций не позже даты
+  balance remove                         расформировать последний баланс
+  report (--from ДАТА --to ДАТА | --balance ID) [--output ФАЙЛ]
+                                         HTML отчёт, даты в формате ГГГГ-ММ-ДД
+  query (operations | articles | balance | percents)
+                                         содержимое таблицы в JSON
+  help                                   эта справка
+";
+
+enum Command {
+    Export {
+        output: Option<PathBuf>,
+    },
+    Import {
+        input: PathBuf,
+        mode: RestoreMode,
+    },
+    BalanceCreate {
+        cutoff: Option<NaiveDate>,
+    },
+    BalanceRemove,
+    Report {
+        period: Period,
+        output: Option<PathBuf>,
+    },
+    Query(Table),
+}
+
+#[derive(Clone, Copy)]
+enum Table {
+    Operations,
+    Articles,
+    Balance,
+    Percents,
+}
+
+struct Options {
+    user: String,
+    password: String,
+    command: Command,
+}
+
+enum Parsed {
+    Help,
+    Run(Options),
+}
+
+enum Failure {
+    Usage(String),
+    Runtime(String),
+}
+
+pub fn run(args: Vec<String>) -> ExitCode {
+    let result = parse(args).and_then(|parsed| {
+        let Parsed::Run(options) = parsed else {
+            return print(USAGE.trim_end());
+        };
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .map_err(runtime)?;
+        rt.block_on(execute(options))
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(message)) => {
+            report_error(&message);
+            report_error(USAGE);
+            ExitCode::from(2)
+        }
+        Err(Failure::Runtime(message)) => {
+            log::error!("{message}");
+            report_error(&message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn execute(options: Options) -> Result<(), Failure> {
+    let db = Db::new_headless(options.user, options.password)
+        .await
+        .map_err(runtime)?
+        .map_err(runtime)?;
+    match options.command {
+        Command::Export { output } => {
+            let backup = db.dump().await.map_err(runtime)?.map_err(runtime)?;
+            match output {
+                Some(path) => {
+                    let summary = backup.save(&path).await.map_err(runtime)?;
+                    print_json(&summary)
+                }
+                None => print(&backup.to_json().map_err(runtime)?),
+            }
+        }
+        Command::Import { input, mode } => {
+            let summary = db
+                .restore(input, mode)
+                .await
+                .map_err(runtime)?
+                .map_err(runtime)?;
+            print_json(&summary)
+        }
+        Command::BalanceCreate { cutoff } => {
+            let balance = db.create_balance(cutoff).await.map_err(runtime)?;
+            print_json(&entries(balance.map_err(runtime)?))
+        }
+        Command::BalanceRemove => {
+            let balance = db.remove_balance().await.map_err(runtime)?;
+            print_json(&entries(balance.map_err(runtime)?))
+        }
+        Command::Report { period, output } => {
+            let report = db.report(period).await.map_err(runtime)?;
+            let html = report.map_err(runtime)?.to_html();
+            match output {
+                Some(path) => tokio::fs::write(path, html).await.map_err(runtime),
+                None => print(&html),
+            }
+        }
+        Command::Query(table) => match table {
+            Table::Operations => {
+                let rows = db.select_from_operations().await.map_err(runtime)?;
+                print_json(&entries(rows.map_err(runtime)?))
+            }
+            Table::Articles => {
+                let rows = db.select_from_articles().await.map_err(runtime)?;
+                print_json(&entries(rows.map_err(runtime)?))
+            }
+            Table::Balance => {
+                let rows = db.select_from_balance().await.map_err(runtime)?;
+                print_json(&entries(rows.map_err(runtime)?))
+            }
+            Table::Percents => {
+                let rows = db
+                    .show_percents(Grouping::Articles, Rollup::default())
+                    .await
+                    .map_err(runtime)?;
+                print_json(&rows.map_err(runtime)?)
+            }
+        },
+    }
+}
+
+fn parse(args: Vec<String>) -> Result<Parsed, Failure> {
+    let mut user = std::env::var("DBGUI_USER").ok();
+    let mut password = std::env::var("DBGUI_PASSWORD").ok();
+    let mut flags = BTreeMap::new();
+    let mut help = false;
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replace" => {
+                flags.insert(arg, String::new());
+            }
+            "-h" | "--help" => help = true,
+            "--password" => {
+                return Err(usage(
+                    "Пароль в аргументах виден другим пользователям, \
+                     используйте DBGUI_PASSWORD или --password-file"
+                        .into(),
+                ));
+            }
+            flag if flag.starts_with("--") => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| usage(format!("Нет значения для {flag}")))?;
+                match flag {
+                    "--user" => user = Some(value),
+                    "--password-file" => password = Some(read_password(&value)?),
+                    "--output" | "--from" | "--to" | "--balance" => {
+                        flags.insert(arg, value);
+                    }
+                    _ => return Err(usage(format!("Неизвестный флаг {flag}"))),
+                }
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+    if help || positional.first() == Some(&"help") {
+        return Ok(Parsed::Help);
+    }
+    let output = |flags: &mut BTreeMap<String, String>| flags.remove("--output").map(PathBuf::from);
+    let command = match positional.as_slice() {
+        ["export"] => Command::Export {
+            output: output(&mut flags),
+        },
+        ["import", input] => Command::Import {
+            input: PathBuf::from(input),
+            mode: if flags.remove("--replace").is_some() {
+                RestoreMode::Replace
+            } else {
+                RestoreMode::Merge
+            },
+        },
+        ["balance", "create"] => Command::BalanceCreate {
+            cutoff: flags.remove("--to").map(|to| parse_date(&to)).transpose()?,
+        },
+        ["balance", "remove"] => Command::BalanceRemove,
+        ["report"] => Command::Report {
+            period: parse_period(&mut flags)?,
+            output: output(&mut flags),
+        },
+        ["query", table] => Command::Query(match *table {
+            "operations" => Table::Operations,
+            "articles" => Table::Articles,
+            "balance" => Table::Balance,
+            "percents" => Table::Percents,
+            _ => return Err(usage(format!("Неизвестная таблица {table}"))),
+        }),
+        [] => return Err(usage("Не указана команда".into())),
+        _ => {
+            return Err(usage(format!(
+                "Неизвестная команда: {}",
+                positional.join(" ")
+            )));
+        }
+    };
+    // Лишний флаг скорее всего опечатка, молча его пропускать нельзя
+    if let Some(flag) = flags.keys().next() {
+        return Err(usage(format!(
+            "Флаг {flag} не подходит к команде {}",
+            positional.join(" ")
+        )));
+    }
+    Ok(Parsed::Run(Options {
+        user: user.ok_or_else(|| usage("Не указан пользователь".into()))?,
+        password: password.ok_or_else(|| usage("Не указан пароль".into()))?,
+        command,
+    }))
+}
+
+fn read_password(path: &str) -> Result<String, Failure> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| Failure::Runtime(format!("Не удалось прочитать {path}: {err}")))?;
+    Ok(content.lines().next().unwrap_or_default().to_owned())
+}
+
+fn parse_period(flags: &mut BTreeMap<String, String>) -> Result<Period, Failure> {
+    match (
+        flags.remove("--from"),
+        flags.remove("--to"),
+        flags.remove("--balance"),
+    ) {
+        (Some(start), Some(end), None) => Ok(Period::Dates {
+            start: parse_date(&start)?,
+            end: parse_date(&end)?,
+        }),
+        (None, None, Some(id)) => id
+            .parse()
+            .map(Period::Balance)
+            .map_err(|err| usage(format!("Неверный id баланса {id}: {err}"))),
+        _ => Err(usage(
+            "Нужно указать либо --from и --to, либо --balance".into(),
+        )),
+    }
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, Failure> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|err| usage(format!("Неверная дата {value}: {err}")))
+}
+
+fn entries<T>(rows: BTreeMap<i32, T>) -> Vec<Entry<T>> {
+    rows.into_iter()
+        .map(|(id, row)| Entry { id, row })
+        .collect()
+}
+
+fn print_json(value: &impl Serialize) -> Result<(), Failure> {
+    print(&serde_json::to_string_pretty(value).map_err(runtime)?)
+}
+
+fn print(text: &str) -> Result<(), Failure> {
+    writeln!(std::io::stdout().lock(), "{text}").map_err(runtime)
+}
+
+fn report_error(message: &str) {
+    // Если не получилось написать даже в stderr, сообщить уже некуда
+    writeln!(std::io::stderr().lock(), "{message}").ok();
+}
+
+fn usage(message: String) -> Failure {
+    Failure::Usage(message)
+}
+
+fn runtime(err: impl std::fmt::Display) -> Failure {
+    Failure::Runtime(err.to_string())
+}
@@ -1,13 +1,32 @@
+pub mod attachments;
+pub mod backup;
+pub mod balance;
+pub mod hierarchy;
 mod inner;
+pub mod recurring;
 pub mod scheme;
+pub mod validation;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
 use crate::{
-    db::scheme::{ArticlesRow, BalanceRow, DynamicsPoint, OperationsRow, PercentsBar, ProfitPoint},
+    db::{
+        attachments::File,
+        backup::{Backup, RestoreMode, Summary},
+        hierarchy::Rollup,
+        recurring::Posted,
+        scheme::{
+            ArticleUsage, ArticlesRow, AttachmentRow, BalancePreview, BalanceRow, BudgetLine,
+            DynamicsPoint, Inserted, OperationsRow, PercentsBar, ProfitPoint, RecurringRow,
+            SplitRow, Splits, StatementLine,
+        },
+        validation::Rules,
+    },
     promise_lite::PromiseLite,
+    report::{self, Period, Report},
 };
-use chrono::NaiveDate;
+use chrono::{Local, NaiveDate};
+use strum::IntoStaticStr;
 use tokio_postgres::Error;
 
 macro_rules! wrap {
@@ -15,21 +34,63 @@ macro_rules! wrap {
         let $clone = $self.clone();
         PromiseLite::spawn(async move {
             let res = $future.await;
-            $clone.ctx.request_repaint();
+            if let Some(ctx) = &$clone.ctx {
+                ctx.request_repaint();
+            }
             res
         })
     }};
 }
+/// Действие сразу над несколькими операциями
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Bulk {
+    Delete,
+    SetArticle(i32),
+    /// Сдвиг даты на число дней, может быть отрицательным
+    ShiftDate(i32),
+    /// `None` — отвязать от баланса
+    SetBalance(Option<i32>),
+}
+/// Что делать с операциями удаляемой статьи
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Orphans {
+    /// Перенести в другую статью, как при слиянии
+    Reassign(i32),
+    /// Оставить операции без статьи
+    Unlink,
+    /// Удалить операции вместе со статьёй
+    Delete,
+}
+/// По чему считаются проценты и динамика
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Grouping {
+    #[default]
+    #[strum(serialize = "По статьям")]
+    Articles,
+    #[strum(serialize = "По меткам")]
+    Tags,
+}
 #[derive(Clone)]
 pub struct Db {
     inner: Arc<inner::Inner>,
-    ctx: egui::Context,
+    // Нет в консольном режиме, там перерисовывать нечего
+    ctx: Option<egui::Context>,
 }
 impl Db {
     pub fn new(
         user: String,
         password: String,
         ctx: egui::Context,
+    ) -> PromiseLite<Result<Self, Error>> {
+        Self::connect(user, password, Some(ctx))
+    }
+    pub fn new_headless(user: String, password: String) -> PromiseLite<Result<Self, Error>> {
+        Self::connect(user, password, None)
+    }
+    fn connect(
+        user: String,
+        password: String,
+        ctx: Option<egui::Context>,
     ) -> PromiseLite<Result<Self, Error>> {
         PromiseLite::spawn(async move {
             inner::Inner::new(user, password).await.map(|i| Db {
@@ -43,74 +104,299 @@ impl Db {
     }
     pub fn select_from_operations(
         &self,
-    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, Error>> {
-        wrap!(self, |clone| clone.inner.select_from_operations())
+    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.select_from_operations().await?)
+        })
     }
     pub fn update_in_operations(
         &self,
         id: i32,
         row: OperationsRow,
-    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, Error>> {
-        wrap!(self, |clone| clone.inner.update_in_operations(id, row))
+        rules: Rules,
+    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, validation::Error>> {
+        wrap!(self, |clone| clone
+            .inner
+            .update_in_operations(id, row, rules))
     }
     pub fn insert_to_operations(
         &self,
         row: OperationsRow,
-    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, Error>> {
-        wrap!(self, |clone| clone.inner.insert_to_operations(row))
+        rules: Rules,
+    ) -> PromiseLite<Result<Inserted<OperationsRow>, validation::Error>> {
+        wrap!(self, |clone| clone.inner.insert_to_operations(row, rules))
+    }
+    pub fn reinsert_to_operations(
+        &self,
+        id: i32,
+        row: OperationsRow,
+        splits: Vec<SplitRow>,
+    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.reinsert_to_operations(id, row, splits).await?)
+        })
     }
     pub fn delete_from_operations(
         &self,
         id: i32,
-    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, Error>> {
+    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, validation::Error>> {
         wrap!(self, |clone| clone.inner.delete_from_operations(id))
     }
-    pub fn select_from_articles(&self) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, Error>> {
-        wrap!(self, |clone| clone.inner.select_from_articles())
+    pub fn override_operation(
+        &self,
+        id: i32,
+        row: Option<OperationsRow>,
+        rules: Rules,
+    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, validation::Error>> {
+        wrap!(self, |clone| clone.inner.override_operation(id, row, rules))
+    }
+    pub fn bulk_operations(
+        &self,
+        ids: Vec<i32>,
+        action: Bulk,
+    ) -> PromiseLite<Result<BTreeMap<i32, OperationsRow>, validation::Error>> {
+        wrap!(self, |clone| clone.inner.bulk_operations(ids, action))
+    }
+    pub fn select_from_articles(
+        &self,
+    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.select_from_articles().await?)
+        })
     }
     pub fn update_in_articles(
         &self,
         id: i32,
         row: ArticlesRow,
-    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, Error>> {
-        wrap!(self, |clone| clone.inner.update_in_articles(id, row))
+    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.update_in_articles(id, row).await?)
+        })
     }
     pub fn insert_to_articles(
         &self,
         row: ArticlesRow,
-    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, Error>> {
-        wrap!(self, |clone| clone.inner.insert_to_articles(row))
+    ) -> PromiseLite<Result<Inserted<ArticlesRow>, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.insert_to_articles(row).await?)
+        })
+    }
+    pub fn reinsert_to_articles(
+        &self,
+        id: i32,
+        row: ArticlesRow,
+    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.reinsert_to_articles(id, row).await?)
+        })
     }
     pub fn delete_from_articles(
         &self,
         id: i32,
-    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, Error>> {
-        wrap!(self, |clone| clone.inner.delete_from_articles(id))
+    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.delete_from_articles(id).await?)
+        })
     }
-    pub fn select_from_balance(&self) -> PromiseLite<Result<BTreeMap<i32, BalanceRow>, Error>> {
-        wrap!(self, |clone| clone.inner.select_from_balance())
+    pub fn select_article_usage(&self) -> PromiseLite<Result<BTreeMap<i32, ArticleUsage>, Error>> {
+        wrap!(self, |clone| clone.inner.select_article_usage())
     }
-    pub fn create_balance(&self) -> PromiseLite<Result<BTreeMap<i32, BalanceRow>, Error>> {
-        wrap!(self, |clone| clone.inner.create_balance())
+    pub fn delete_article(
+        &self,
+        id: i32,
+        orphans: Orphans,
+    ) -> PromiseLite<Result<BTreeMap<i32, ArticlesRow>, validation::Error>> {
+        wrap!(self, |clone| clone.inner.delete_article(id, orphans))
     }
-    pub fn remove_balance(&self) -> PromiseLite<Result<BTreeMap<i32, BalanceRow>, Error>> {
-        wrap!(self, |clone| clone.inner.remove_balance())
+    /// `month` — первое число месяца
+    pub fn select_budget(
+        &self,
+        month: NaiveDate,
+    ) -> PromiseLite<Result<BTreeMap<i32, BudgetLine>, Error>> {
+        wrap!(self, |clone| clone.inner.select_budget(month))
     }
-    pub fn show_percents(&self) -> PromiseLite<Result<Vec<PercentsBar>, Error>> {
-        wrap!(self, |clone| clone.inner.show_percents())
+    pub fn set_budget(
+        &self,
+        month: NaiveDate,
+        article_id: i32,
+        amount: Option<i32>,
+    ) -> PromiseLite<Result<BTreeMap<i32, BudgetLine>, Error>> {
+        wrap!(self, |clone| clone
+            .inner
+            .set_budget(month, article_id, amount))
+    }
+    pub fn select_from_recurring(&self) -> PromiseLite<Result<BTreeMap<i32, RecurringRow>, Error>> {
+        wrap!(self, |clone| clone.inner.select_from_recurring())
+    }
+    pub fn insert_to_recurring(
+        &self,
+        row: RecurringRow,
+    ) -> PromiseLite<Result<BTreeMap<i32, RecurringRow>, Error>> {
+        wrap!(self, |clone| clone.inner.insert_to_recurring(row))
+    }
+    pub fn update_in_recurring(
+        &self,
+        id: i32,
+        row: RecurringRow,
+    ) -> PromiseLite<Result<BTreeMap<i32, RecurringRow>, Error>> {
+        wrap!(self, |clone| clone.inner.update_in_recurring(id, row))
+    }
+    pub fn delete_from_recurring(
+        &self,
+        id: i32,
+    ) -> PromiseLite<Result<BTreeMap<i32, RecurringRow>, Error>> {
+        wrap!(self, |clone| clone.inner.delete_from_recurring(id))
+    }
+    /// Повторения, наступившие к сегодняшнему дню
+    pub fn post_recurring(
+        &self,
+        ids: Option<Vec<i32>>,
+        create: bool,
+        rules: Rules,
+    ) -> PromiseLite<Result<Posted, Error>> {
+        let today = Local::now().date_naive();
+        wrap!(self, |clone| clone
+            .inner
+            .post_recurring(ids, today, create, rules))
+    }
+    pub fn select_from_splits(&self) -> PromiseLite<Result<Splits, validation::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.select_from_splits().await?)
+        })
+    }
+    pub fn set_splits(
+        &self,
+        operation_id: i32,
+        lines: Vec<SplitRow>,
+    ) -> PromiseLite<Result<Splits, validation::Error>> {
+        wrap!(self, |clone| clone.inner.set_splits(operation_id, lines))
+    }
+    pub fn select_from_attachments(
+        &self,
+    ) -> PromiseLite<Result<BTreeMap<i32, AttachmentRow>, attachments::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.select_from_attachments().await?)
+        })
+    }
+    /// Читает файл с диска и прикрепляет его к операции
+    pub fn attach(
+        &self,
+        operation_id: i32,
+        path: PathBuf,
+    ) -> PromiseLite<Result<BTreeMap<i32, AttachmentRow>, attachments::Error>> {
+        wrap!(self, |clone| async move {
+            let file = File::read(&path).await?;
+            clone.inner.insert_to_attachments(operation_id, file).await
+        })
+    }
+    pub fn attachment_content(&self, id: i32) -> PromiseLite<Result<Vec<u8>, attachments::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.attachment_content(id).await?)
+        })
+    }
+    /// Выгружает вложение из базы в файл
+    pub fn save_attachment(
+        &self,
+        id: i32,
+        path: PathBuf,
+    ) -> PromiseLite<Result<PathBuf, attachments::Error>> {
+        wrap!(self, |clone| async move {
+            let content = clone.inner.attachment_content(id).await?;
+            tokio::fs::write(&path, content).await?;
+            Ok(path)
+        })
+    }
+    pub fn delete_from_attachments(
+        &self,
+        id: i32,
+    ) -> PromiseLite<Result<BTreeMap<i32, AttachmentRow>, attachments::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.delete_from_attachments(id).await?)
+        })
+    }
+    pub fn select_from_balance(
+        &self,
+    ) -> PromiseLite<Result<BTreeMap<i32, BalanceRow>, balance::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.select_from_balance().await?)
+        })
+    }
+    pub fn create_balance(
+        &self,
+        cutoff: Option<NaiveDate>,
+    ) -> PromiseLite<Result<BTreeMap<i32, BalanceRow>, balance::Error>> {
+        wrap!(self, |clone| clone.inner.create_balance(cutoff))
+    }
+    pub fn select_balance_lines(
+        &self,
+        balance_id: i32,
+    ) -> PromiseLite<Result<Vec<StatementLine>, Error>> {
+        wrap!(self, |clone| clone.inner.select_balance_lines(balance_id))
+    }
+    pub fn balance_preview(
+        &self,
+        cutoff: Option<NaiveDate>,
+    ) -> PromiseLite<Result<BalancePreview, Error>> {
+        wrap!(self, |clone| clone.inner.balance_preview(cutoff))
+    }
+    pub fn remove_balance(&self) -> PromiseLite<Result<BTreeMap<i32, BalanceRow>, balance::Error>> {
+        wrap!(self, |clone| async move {
+            Ok(clone.inner.remove_balance().await?)
+        })
+    }
+    pub fn show_percents(
+        &self,
+        grouping: Grouping,
+        rollup: Rollup,
+    ) -> PromiseLite<Result<Vec<PercentsBar>, Error>> {
+        wrap!(self, |clone| clone.inner.show_percents(grouping, rollup))
     }
     pub fn show_profit(&self) -> PromiseLite<Result<Vec<ProfitPoint>, Error>> {
         wrap!(self, |clone| clone.inner.show_profit())
     }
     pub fn show_dynamics(
         &self,
+        grouping: Grouping,
         articles: Vec<i32>,
+        tags: Vec<String>,
         start: NaiveDate,
         end: NaiveDate,
     ) -> PromiseLite<Result<Vec<DynamicsPoint>, Error>> {
         let (start, end) = (start.into(), end.into());
         wrap!(self, |clone| clone
             .inner
-            .show_dynamics(articles, start, end))
+            .show_dynamics(grouping, articles, tags, start, end))
+    }
+    pub fn report(&self, period: Period) -> PromiseLite<Result<Report, Error>> {
+        wrap!(self, |clone| clone.inner.report(period))
+    }
+    pub fn save_report(
+        &self,
+        period: Period,
+        path: PathBuf,
+    ) -> PromiseLite<Result<(), report::Error>> {
+        wrap!(self, |clone| async move {
+            let report = clone.inner.report(period).await?;
+            report.save(&path).await
+        })
+    }
+    pub fn dump(&self) -> PromiseLite<Result<Backup, Error>> {
+        wrap!(self, |clone| clone.inner.backup())
+    }
+    pub fn backup(&self, path: PathBuf) -> PromiseLite<Result<Summary, backup::Error>> {
+        wrap!(self, |clone| async move {
+            let backup = clone.inner.backup().await?;
+            backup.save(&path).await
+        })
+    }
+    pub fn restore(
+        &self,
+        path: PathBuf,
+        mode: RestoreMode,
+    ) -> PromiseLite<Result<Summary, backup::Error>> {
+        wrap!(self, |clone| async move {
+            let backup = Backup::load(&path).await?;
+            Ok(clone.inner.restore(backup, mode).await?)
+        })
     }
 }
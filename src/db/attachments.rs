@@ -0,0 +1,99 @@
+//! Файлы, прикреплённые к операциям: чеки, гарантийные талоны и тому подобное.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Больше этого в базу не кладём, чтобы выборки оставались быстрыми
+pub const MAX_SIZE: u64 = 20 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    Db(tokio_postgres::Error),
+    Io(std::io::Error),
+    TooLarge(u64),
+    NoName(PathBuf),
+}
+
+/// Прочитанный с диска файл, готовый к записи в базу
+pub struct File {
+    pub name: String,
+    pub mime: &'static str,
+    pub content: Vec<u8>,
+}
+
+impl File {
+    pub async fn read(path: &Path) -> Result<Self, Error> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or_else(|| Error::NoName(path.to_owned()))?;
+        let size = tokio::fs::metadata(path).await?.len();
+        if size > MAX_SIZE {
+            return Err(Error::TooLarge(size));
+        }
+        Ok(Self {
+            mime: mime(&name),
+            name,
+            content: tokio::fs::read(path).await?,
+        })
+    }
+}
+
+/// Тип содержимого по расширению файла
+pub fn mime(file_name: &str) -> &'static str {
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("pdf") => "application/pdf",
+        Some("txt") => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+/// Изображения, которые умеет показывать приложение
+pub fn can_preview(mime: &str) -> bool {
+    matches!(mime, "image/png" | "image/jpeg")
+}
+/// Размер в байтах, килобайтах или мегабайтах
+pub fn format_size(size: i32) -> String {
+    let size = f64::from(size);
+    if size < 1024.0 {
+        format!("{size} Б")
+    } else if size < 1024.0 * 1024.0 {
+        format!("{:.1} КБ", size / 1024.0)
+    } else {
+        format!("{:.1} МБ", size / 1024.0 / 1024.0)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Db(err) => write!(f, "Ошибка базы: {err}"),
+            Self::Io(err) => write!(f, "Ошибка файла: {err}"),
+            Self::TooLarge(size) => write!(
+                f,
+                "Файл слишком большой: {} МБ, можно не больше {} МБ",
+                size / 1024 / 1024,
+                MAX_SIZE / 1024 / 1024
+            ),
+            Self::NoName(path) => write!(f, "Не указано имя файла: {}", path.display()),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::Db(err)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -0,0 +1,202 @@
+use std::{fmt, path::Path};
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::scheme::{ArticlesRow, BalanceRow, OperationsRow, RecurringRow, Splits};
+
+const FORMAT: &str = "dbgui-backup";
+/// Версия формата. Повышается при любом несовместимом изменении схемы.
+/// Во второй версии добавлены таблицы, появившиеся после первой. В копиях
+/// первой версии их нет, и при загрузке они считаются пустыми.
+pub const VERSION: u32 = 2;
+
+/// Логическая копия всего бюджета вместе с id и связями `balance_id`.
+#[derive(Serialize, Deserialize)]
+pub struct Backup {
+    format: String,
+    version: u32,
+    pub create_date: NaiveDateTime,
+    pub articles: Vec<Entry<ArticlesRow>>,
+    pub balance: Vec<Entry<BalanceRow>>,
+    pub operations: Vec<Entry<OperationsRow>>,
+    /// Шаблоны вместе с датой следующего повторения
+    #[serde(default)]
+    pub recurring: Vec<Entry<RecurringRow>>,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    #[serde(default)]
+    pub splits: Splits,
+    #[serde(default)]
+    pub budgets: Vec<Budget>,
+}
+
+/// Прикреплённый файл вместе с содержимым. Свой id не нужен: на файлы никто не ссылается.
+#[derive(Serialize, Deserialize)]
+pub struct Attachment {
+    pub operation_id: i32,
+    pub file_name: String,
+    pub mime: String,
+    pub create_date: NaiveDateTime,
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    pub content: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry<T> {
+    pub id: i32,
+    #[serde(flatten)]
+    pub row: T,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestoreMode {
+    /// Добавить данные к существующим, выдав им новые id
+    #[default]
+    Merge,
+    /// Удалить всё и загрузить копию с исходными id
+    Replace,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct Summary {
+    pub articles: usize,
+    pub balance: usize,
+    pub operations: usize,
+    pub recurring: usize,
+    pub attachments: usize,
+    pub splits: usize,
+    pub budgets: usize,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Db(tokio_postgres::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Format(String),
+    Version(u32),
+}
+
+impl Backup {
+    pub fn new(
+        articles: Vec<Entry<ArticlesRow>>,
+        balance: Vec<Entry<BalanceRow>>,
+        operations: Vec<Entry<OperationsRow>>,
+        recurring: Vec<Entry<RecurringRow>>,
+        attachments: Vec<Attachment>,
+        splits: Splits,
+        budgets: Vec<Budget>,
+    ) -> Self {
+        Self {
+            format: FORMAT.into(),
+            version: VERSION,
+            create_date: Local::now().naive_local(),
+            articles,
+            balance,
+            operations,
+            recurring,
+            attachments,
+            splits,
+            budgets,
+        }
+    }
+    pub fn summary(&self) -> Summary {
+        Summary {
+            articles: self.articles.len(),
+            balance: self.balance.len(),
+            operations: self.operations.len(),
+            recurring: self.recurring.len(),
+            attachments: self.attachments.len(),
+            splits: self.splits.len(),
+            budgets: self.budgets.len(),
+        }
+    }
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let backup: Self = serde_json::from_str(json)?;
+        if backup.format != FORMAT {
+            return Err(Error::Format(backup.format));
+        }
+        if backup.version > VERSION {
+            return Err(Error::Version(backup.version));
+        }
+        Ok(backup)
+    }
+    pub async fn save(&self, path: &Path) -> Result<Summary, Error> {
+        tokio::fs::write(path, self.to_json()?).await?;
+        Ok(self.summary())
+    }
+    pub async fn load(path: &Path) -> Result<Self, Error> {
+        Self::from_json(&tokio::fs::read_to_string(path).await?)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "статей: {}, балансов: {}, операций: {}, шаблонов: {}, файлов: {}, разбивок: {}, \
+            бюджетов: {}",
+            self.articles,
+            self.balance,
+            self.operations,
+            self.recurring,
+            self.attachments,
+            self.splits,
+            self.budgets
+        )
+    }
+}
+
+/// Содержимое файлов хранится в base64, а не массивом чисел
+fn to_base64<S: Serializer>(content: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(content))
+}
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    STANDARD.decode(text).map_err(serde::de::Error::custom)
+}
+
+/// Бюджет статьи на месяц
+#[derive(Serialize, Deserialize)]
+pub struct Budget {
+    pub article_id: i32,
+    /// Первое число месяца
+    pub month: NaiveDate,
+    pub amount: i32,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Db(err) => write!(f, "Ошибка базы: {err}"),
+            Self::Io(err) => write!(f, "Ошибка файла: {err}"),
+            Self::Json(err) => write!(f, "Повреждённая копия: {err}"),
+            Self::Format(format) => write!(f, "Неизвестный формат файла: {format}"),
+            Self::Version(version) => {
+                write!(f, "Копия версии {version} новее поддерживаемой ({VERSION})")
+            }
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::Db(err)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
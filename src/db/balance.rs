@@ -0,0 +1,28 @@
+//! Сформированные балансы: свободные операции закрываются в баланс и больше не меняются.
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Db(tokio_postgres::Error),
+    /// Подходящих свободных операций нет, баланс не из чего сформировать
+    NothingToClose,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Db(err) => write!(f, "Ошибка базы: {err}"),
+            Self::NothingToClose => f.write_str(
+                "Нет свободных операций, которые вошли бы в баланс. Баланс не сформирован",
+            ),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::Db(err)
+    }
+}
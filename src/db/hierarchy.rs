@@ -0,0 +1,147 @@
+//! Вложенные статьи: группы вроде «Жильё → Аренда / Коммуналка».
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::scheme::ArticlesRow;
+
+/// Разделитель уровней в полном названии статьи
+pub const SEPARATOR: &str = " → ";
+
+/// До какого уровня сворачиваются статьи в процентах
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rollup {
+    /// Внутри какой статьи смотреть; `None` — все статьи
+    pub within: Option<i32>,
+    /// Сколько уровней ниже `within` показывать, с 1.
+    /// Более глубокие статьи складываются в своего предка на этом уровне.
+    pub level: i32,
+}
+
+impl Default for Rollup {
+    fn default() -> Self {
+        Self {
+            within: None,
+            level: 1,
+        }
+    }
+}
+
+/// Статьи, разложенные по родителям. Вложенные статьи идут по названию.
+pub struct Tree<'a> {
+    articles: &'a BTreeMap<i32, ArticlesRow>,
+    children: BTreeMap<Option<i32>, Vec<i32>>,
+}
+
+impl<'a> Tree<'a> {
+    pub fn new(articles: &'a BTreeMap<i32, ArticlesRow>) -> Self {
+        let mut children: BTreeMap<Option<i32>, Vec<i32>> = BTreeMap::new();
+        for id in articles.keys() {
+            children.entry(parent(*id, articles)).or_default().push(*id);
+        }
+        for ids in children.values_mut() {
+            ids.sort_by_key(|id| articles[id].name.clone().unwrap_or_default().to_lowercase());
+        }
+        Self { articles, children }
+    }
+    /// `None` — статьи верхнего уровня
+    pub fn children(&self, parent: Option<i32>) -> &[i32] {
+        self.children.get(&parent).map_or(&[], Vec::as_slice)
+    }
+    /// Все статьи сверху вниз вместе с глубиной вложенности
+    pub fn depth_first(&self) -> Vec<(i32, usize)> {
+        self.walk(true)
+    }
+    /// То же без архивных статей и всего, что в них вложено.
+    /// Из этого списка выбирают статью для новых операций.
+    pub fn active(&self) -> Vec<(i32, usize)> {
+        self.walk(false)
+    }
+    /// Статья в архиве сама или вложена в архивную
+    pub fn is_archived(&self, id: i32) -> bool {
+        let mut current = Some(id);
+        for _ in 0..=self.articles.len() {
+            let Some((id, article)) = current.and_then(|id| self.articles.get_key_value(&id))
+            else {
+                return false;
+            };
+            if article.archived {
+                return true;
+            }
+            current = parent(*id, self.articles);
+        }
+        false
+    }
+    fn walk(&self, archived: bool) -> Vec<(i32, usize)> {
+        let mut result = Vec::with_capacity(self.articles.len());
+        let mut stack: Vec<(i32, usize)> = self
+            .children(None)
+            .iter()
+            .rev()
+            .map(|id| (*id, 0))
+            .collect();
+        while let Some((id, depth)) = stack.pop() {
+            if !archived && self.articles.get(&id).is_some_and(|a| a.archived) {
+                continue;
+            }
+            result.push((id, depth));
+            stack.extend(
+                self.children(Some(id))
+                    .iter()
+                    .rev()
+                    .map(|c| (*c, depth + 1)),
+            );
+        }
+        result
+    }
+    /// Название с отступом по глубине, для списков
+    pub fn indented(&self, id: i32, depth: usize) -> String {
+        let name = self.articles.get(&id).and_then(|a| a.name.as_deref());
+        format!("{}{}", "    ".repeat(depth), name.unwrap_or_default())
+    }
+    /// Сама статья и всё, что в неё вложено
+    pub fn descendants(&self, id: i32) -> BTreeSet<i32> {
+        let mut result = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if result.insert(id) {
+                stack.extend_from_slice(self.children(Some(id)));
+            }
+        }
+        result
+    }
+}
+
+/// Родитель статьи. Если родителя нет в таблице или статьи зациклены,
+/// статья считается статьёй верхнего уровня.
+fn parent(id: i32, articles: &BTreeMap<i32, ArticlesRow>) -> Option<i32> {
+    let parent = articles
+        .get(&id)?
+        .parent_id
+        .filter(|p| articles.contains_key(p))?;
+    let mut current = Some(parent);
+    for _ in 0..articles.len() {
+        match current {
+            Some(ancestor) if ancestor == id => return None,
+            Some(ancestor) => current = articles.get(&ancestor).and_then(|a| a.parent_id),
+            None => return Some(parent),
+        }
+    }
+    None
+}
+
+/// Названия от статьи верхнего уровня до этой, например «Жильё → Аренда»
+pub fn path(id: i32, articles: &BTreeMap<i32, ArticlesRow>) -> String {
+    let mut names = Vec::new();
+    let mut current = Some(id);
+    while let Some(id) = current
+        && names.len() <= articles.len()
+    {
+        let Some(article) = articles.get(&id) else {
+            break;
+        };
+        names.push(article.name.clone().unwrap_or_default());
+        current = parent(id, articles);
+    }
+    names.reverse();
+    names.join(SEPARATOR)
+}
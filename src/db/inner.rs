@@ -1,17 +1,36 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::db::scheme::{ArticlesRow, BalanceRow, DynamicsPoint, PercentsBar, ProfitPoint};
+use crate::{
+    db::{
+        Bulk, Grouping, Orphans,
+        attachments::{self, File},
+        backup::{Attachment, Backup, Budget, Entry, RestoreMode, Summary},
+        balance,
+        hierarchy::Rollup,
+        recurring::Posted,
+        scheme::{
+            ArticleTotals, ArticleUsage, ArticlesRow, AttachmentRow, BalancePreview, BalanceRow,
+            BudgetLine, DynamicsPoint, Inserted, PercentsBar, ProfitPoint, RecurringRow, SplitRow,
+            Splits, StatementLine, TopOperation, Totals,
+        },
+        validation::{self, Rules},
+    },
+    report::{Period, PeriodData, Report, TOP_OPERATIONS},
+};
 
 use super::scheme::OperationsRow;
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use futures_util::{StreamExt, TryStreamExt};
+use tokio::sync::{RwLock, RwLockReadGuard};
 use tokio_postgres::{
-    Client, Config, Error, NoTls, Statement,
+    Client, Config, Error, GenericClient, IsolationLevel, NoTls, Statement, Transaction,
     types::{ToSql, Type},
 };
 pub struct Inner {
     user: String,
-    client: Client,
+    // Обычные запросы идут параллельно через общий доступ,
+    // транзакции забирают клиента целиком.
+    client: RwLock<Client>,
 
     select_from_operations: Statement,
     select_from_articles: Statement,
@@ -19,6 +38,8 @@ pub struct Inner {
 
     insert_to_operations: Statement,
     insert_to_articles: Statement,
+    reinsert_to_operations: Statement,
+    reinsert_to_articles: Statement,
 
     update_in_operations: Statement,
     update_in_articles: Statement,
@@ -28,29 +49,36 @@ pub struct Inner {
     create_balance: Statement,
     remove_balance: Statement,
     show_percents: Statement,
+    show_percents_by_tag: Statement,
     show_dynamics: Statement,
+    show_dynamics_by_tag: Statement,
     show_profit: Statement,
+
+    report_totals: Statement,
+    report_articles: Statement,
+    report_profit: Statement,
+    report_top: Statement,
+    previous_balance: Statement,
+
+    select_from_recurring: Statement,
+    insert_to_recurring: Statement,
+    update_in_recurring: Statement,
+    delete_from_recurring: Statement,
+
+    select_from_attachments: Statement,
+    delete_from_attachments: Statement,
 }
 impl Inner {
     pub async fn new(user: String, password: String) -> Result<Self, Error> {
-        let (client, connection) = Config::new()
-            .host("/var/run/postgresql/")
-            .dbname("budget")
-            .user(&user)
-            .password(password)
-            .connect(NoTls)
-            .await?;
-        tokio::spawn(async move {
-            if let Err(err) = connection.await {
-                log::error!("Ошибка подключения к базе: {err}");
-            }
-        });
+        let client = Self::connect(&user, password).await?;
         let (
             select_from_operations,
             select_from_articles,
             select_from_balance,
             insert_to_operations,
             insert_to_articles,
+            reinsert_to_operations,
+            reinsert_to_articles,
             update_in_operations,
             update_in_articles,
             delete_from_operations,
@@ -58,14 +86,29 @@ impl Inner {
             create_balance,
             remove_balance,
             show_percents,
+            show_percents_by_tag,
             show_dynamics,
+            show_dynamics_by_tag,
             show_profit,
+            report_totals,
+            report_articles,
+            report_profit,
+            report_top,
+            previous_balance,
+            select_from_recurring,
+            insert_to_recurring,
+            update_in_recurring,
+            delete_from_recurring,
+            select_from_attachments,
+            delete_from_attachments,
         ) = tokio::try_join!(
             Self::prepare_select_from_operations(&client),
             Self::prepare_select_from_articles(&client),
             Self::prepare_select_from_balance(&client),
             Self::prepare_insert_to_operations(&client),
             Self::prepare_insert_to_articles(&client),
+            Self::prepare_reinsert_to_operations(&client),
+            Self::prepare_reinsert_to_articles(&client),
             Self::prepare_update_in_operations(&client),
             Self::prepare_update_in_articles(&client),
             Self::prepare_delete_from_operations(&client),
@@ -73,17 +116,32 @@ impl Inner {
             Self::prepare_create_balance(&client),
             Self::prepare_remove_balance(&client),
             Self::prepare_show_percents(&client),
+            Self::prepare_show_percents_by_tag(&client),
             Self::prepare_show_dynamics(&client),
+            Self::prepare_show_dynamics_by_tag(&client),
             Self::prepare_show_profit(&client),
+            Self::prepare_report_totals(&client),
+            Self::prepare_report_articles(&client),
+            Self::prepare_report_profit(&client),
+            Self::prepare_report_top(&client),
+            Self::prepare_previous_balance(&client),
+            Self::prepare_select_from_recurring(&client),
+            Self::prepare_insert_to_recurring(&client),
+            Self::prepare_update_in_recurring(&client),
+            Self::prepare_delete_from_recurring(&client),
+            Self::prepare_select_from_attachments(&client),
+            Self::prepare_delete_from_attachments(&client),
         )?;
         Ok(Self {
             user,
-            client,
+            client: RwLock::new(client),
             select_from_operations,
             select_from_articles,
             select_from_balance,
             insert_to_operations,
             insert_to_articles,
+            reinsert_to_operations,
+            reinsert_to_articles,
             update_in_operations,
             update_in_articles,
             delete_from_operations,
@@ -91,15 +149,97 @@ impl Inner {
             create_balance,
             remove_balance,
             show_percents,
+            show_percents_by_tag,
             show_dynamics,
+            show_dynamics_by_tag,
             show_profit,
+            report_totals,
+            report_articles,
+            report_profit,
+            report_top,
+            previous_balance,
+            select_from_recurring,
+            insert_to_recurring,
+            update_in_recurring,
+            delete_from_recurring,
+            select_from_attachments,
+            delete_from_attachments,
         })
     }
+    /// Подключается к базе и доводит её схему до текущей
+    async fn connect(user: &str, password: String) -> Result<Client, Error> {
+        let (mut client, connection) = Config::new()
+            .host("/var/run/postgresql/")
+            .dbname("budget")
+            .user(user)
+            .password(password)
+            .connect(NoTls)
+            .await?;
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                log::error!("Ошибка подключения к базе: {err}");
+            }
+        });
+        Self::migrate(&mut client).await?;
+        Ok(client)
+    }
+    /// Доводит схему до последней версии. Если база не отстаёт, DDL не выполняется,
+    /// и войти может пользователь без прав на изменение схемы.
+    async fn migrate(client: &mut Client) -> Result<(), Error> {
+        if Self::schema_version(client).await? >= MIGRATIONS.len() {
+            return Ok(());
+        }
+        let tx = client.transaction().await?;
+        tx.batch_execute(
+            "CREATE TABLE IF NOT EXISTS public.schema_version (version INT4 NOT NULL); \
+            GRANT SELECT ON public.schema_version TO PUBLIC; \
+            LOCK TABLE public.schema_version IN EXCLUSIVE MODE;",
+        )
+        .await?;
+        // Пока ждали блокировку, схему мог обновить другой клиент
+        let current = Self::schema_version(&tx).await?;
+        for step in MIGRATIONS.iter().skip(current) {
+            tx.batch_execute(step).await?;
+        }
+        let latest = i32::try_from(MIGRATIONS.len()).unwrap_or(i32::MAX);
+        tx.execute("DELETE FROM public.schema_version", &[]).await?;
+        tx.execute(
+            "INSERT INTO public.schema_version(version) VALUES ($1)",
+            &[&latest],
+        )
+        .await?;
+        tx.commit().await
+    }
+    /// Сколько шагов `MIGRATIONS` уже выполнено; 0 — версия ещё не записывалась
+    async fn schema_version(client: &impl GenericClient) -> Result<usize, Error> {
+        let exists: bool = client
+            .query_one(
+                "SELECT to_regclass('public.schema_version') IS NOT NULL",
+                &[],
+            )
+            .await?
+            .try_get(0)?;
+        if !exists {
+            return Ok(0);
+        }
+        let version: i32 = client
+            .query_one(
+                "SELECT COALESCE(MAX(version), 0) FROM public.schema_version",
+                &[],
+            )
+            .await?
+            .try_get(0)?;
+        Ok(usize::try_from(version).unwrap_or_default())
+    }
     pub fn user(&self) -> &str {
         &self.user
     }
+    async fn client(&self) -> RwLockReadGuard<'_, Client> {
+        self.client.read().await
+    }
     pub async fn select_from_operations(&self) -> Result<BTreeMap<i32, OperationsRow>, Error> {
-        self.client
+        self.client()
+            .await
             .query_raw(&self.select_from_operations, NO_PARAMS)
             .await?
             .map_ok(|r| OperationsRow::new(r))
@@ -107,24 +247,77 @@ impl Inner {
             .try_collect()
             .await
     }
+    /// Возвращает id новой строки вместе со всеми строками
     pub async fn insert_to_operations(
         &self,
         row: OperationsRow,
-    ) -> Result<BTreeMap<i32, OperationsRow>, Error> {
-        self.client
-            .execute(
+        rules: Rules,
+    ) -> Result<Inserted<OperationsRow>, validation::Error> {
+        rules.check(&row)?;
+        let inserted = self
+            .client()
+            .await
+            .query_one(
                 &self.insert_to_operations,
-                &[&row.article_id, &row.debit, &row.credit, &row.create_date],
+                &[
+                    &row.article_id,
+                    &row.debit,
+                    &row.credit,
+                    &row.create_date,
+                    &row.description,
+                    &row.tags,
+                ],
             )
             .await?;
+        Ok((
+            inserted.try_get("id")?,
+            self.select_from_operations().await?,
+        ))
+    }
+    /// Возвращает удалённую строку под прежним id вместе с её разбивкой. Если строка
+    /// входила в баланс, баланс пересчитывается, а изменение записывается в журнал.
+    pub async fn reinsert_to_operations(
+        &self,
+        id: i32,
+        row: OperationsRow,
+        splits: Vec<SplitRow>,
+    ) -> Result<BTreeMap<i32, OperationsRow>, Error> {
+        {
+            let mut client = self.client.write().await;
+            let tx = client.transaction().await?;
+            tx.execute(
+                &self.reinsert_to_operations,
+                &[
+                    &id,
+                    &row.article_id,
+                    &row.debit,
+                    &row.credit,
+                    &row.create_date,
+                    &row.balance_id,
+                    &row.description,
+                    &row.tags,
+                ],
+            )
+            .await?;
+            Self::insert_splits(&tx, id, &splits).await?;
+            if let Some(balance_id) = row.balance_id {
+                Self::recalculate_balances(&tx, &[balance_id]).await?;
+                Self::record_override(&tx, id, Some(balance_id), None, Some(&row)).await?;
+            }
+            tx.commit().await?;
+        }
         self.select_from_operations().await
     }
     pub async fn update_in_operations(
         &self,
         id: i32,
         row: OperationsRow,
-    ) -> Result<BTreeMap<i32, OperationsRow>, Error> {
-        self.client
+        rules: Rules,
+    ) -> Result<BTreeMap<i32, OperationsRow>, validation::Error> {
+        rules.check(&row)?;
+        let client = self.client().await;
+        Self::check_splits(&*client, id, &row).await?;
+        let updated = client
             .execute(
                 &self.update_in_operations,
                 &[
@@ -133,22 +326,245 @@ impl Inner {
                     &row.debit,
                     &row.credit,
                     &row.create_date,
+                    &row.description,
+                    &row.tags,
                 ],
             )
             .await?;
-        self.select_from_operations().await
+        if updated == 0 {
+            Self::check_unlocked(&client, id).await?;
+        }
+        drop(client);
+        Ok(self.select_from_operations().await?)
     }
     pub async fn delete_from_operations(
         &self,
         id: i32,
-    ) -> Result<BTreeMap<i32, OperationsRow>, Error> {
-        self.client
-            .execute(&self.delete_from_operations, &[&id])
+    ) -> Result<BTreeMap<i32, OperationsRow>, validation::Error> {
+        let client = self.client().await;
+        let deleted = client.execute(&self.delete_from_operations, &[&id]).await?;
+        if deleted == 0 {
+            Self::check_unlocked(&client, id).await?;
+        }
+        drop(client);
+        Ok(self.select_from_operations().await?)
+    }
+    /// Изменение или удаление (`row` — `None`) операции из баланса в обход блокировки.
+    /// В одной транзакции пересчитывает баланс и записывает изменение в журнал.
+    pub async fn override_operation(
+        &self,
+        id: i32,
+        row: Option<OperationsRow>,
+        rules: Rules,
+    ) -> Result<BTreeMap<i32, OperationsRow>, validation::Error> {
+        if let Some(row) = &row {
+            rules.check(row)?;
+        }
+        {
+            let mut client = self.client.write().await;
+            let tx = client.transaction().await?;
+            let before = tx
+                .query_opt(
+                    "SELECT * FROM public.operations WHERE id = $1 FOR UPDATE",
+                    &[&id],
+                )
+                .await?
+                .map(OperationsRow::new)
+                .transpose()?
+                .map(|(_, before)| before);
+            let balance_id = before.as_ref().and_then(|b| b.balance_id);
+            match &row {
+                Some(row) => {
+                    Self::check_splits(&tx, id, row).await?;
+                    tx.execute(
+                        "UPDATE public.operations \
+                        SET article_id=$2, debit=$3, credit=$4, create_date=$5, \
+                        description=$6, tags=$7 \
+                        WHERE id=$1",
+                        &[
+                            &id,
+                            &row.article_id,
+                            &row.debit,
+                            &row.credit,
+                            &row.create_date,
+                            &row.description,
+                            &row.tags,
+                        ],
+                    )
+                    .await?
+                }
+                None => {
+                    tx.execute("DELETE FROM public.operations WHERE id = $1", &[&id])
+                        .await?
+                }
+            };
+            let after = row.map(|row| OperationsRow { balance_id, ..row });
+            Self::recalculate_balances(&tx, &Vec::from_iter(balance_id)).await?;
+            Self::record_override(&tx, id, balance_id, before.as_ref(), after.as_ref()).await?;
+            tx.commit().await?;
+        }
+        Ok(self.select_from_operations().await?)
+    }
+    /// Ошибка, если операция входит в сформированный баланс
+    async fn check_unlocked(client: &Client, id: i32) -> Result<(), validation::Error> {
+        let balance: Option<Option<i32>> = client
+            .query_opt(
+                "SELECT balance_id FROM public.operations WHERE id = $1",
+                &[&id],
+            )
+            .await?
+            .map(|row| row.try_get(0))
+            .transpose()?;
+        match balance.flatten() {
+            Some(balance) => Err(validation::Error::Locked {
+                operation: id,
+                balance,
+            }),
+            None => Ok(()),
+        }
+    }
+    /// Ошибка, если у операции есть разбивка и новая сумма с ней не сходится
+    async fn check_splits(
+        client: &impl GenericClient,
+        id: i32,
+        row: &OperationsRow,
+    ) -> Result<(), validation::Error> {
+        let sums = client
+            .query_one(
+                "SELECT COUNT(*) AS count, \
+                CAST(COALESCE(SUM(debit), 0) AS BIGINT) AS debit, \
+                CAST(COALESCE(SUM(credit), 0) AS BIGINT) AS credit \
+                FROM public.operation_splits WHERE operation_id = $1",
+                &[&id],
+            )
             .await?;
-        self.select_from_operations().await
+        let (count, debit, credit): (i64, i64, i64) = (
+            sums.try_get("count")?,
+            sums.try_get("debit")?,
+            sums.try_get("credit")?,
+        );
+        let matches = debit == i64::from(row.debit.unwrap_or(0))
+            && credit == i64::from(row.credit.unwrap_or(0));
+        if count == 0 || matches {
+            Ok(())
+        } else {
+            Err(validation::Error::Split {
+                operation: id,
+                debit,
+                credit,
+            })
+        }
+    }
+    async fn recalculate_balances(tx: &Transaction<'_>, balances: &[i32]) -> Result<(), Error> {
+        tx.execute(RECALCULATE_BALANCES, &[&balances]).await?;
+        Ok(())
+    }
+    async fn record_override(
+        tx: &Transaction<'_>,
+        id: i32,
+        balance_id: Option<i32>,
+        before: Option<&OperationsRow>,
+        after: Option<&OperationsRow>,
+    ) -> Result<(), Error> {
+        let json = |row: Option<&OperationsRow>| serde_json::to_string(row?).ok();
+        tx.execute(
+            "INSERT INTO public.balance_overrides(operation_id, balance_id, before, after) \
+            VALUES ($1, $2, $3::TEXT::JSONB, $4::TEXT::JSONB)",
+            &[&id, &balance_id, &json(before), &json(after)],
+        )
+        .await?;
+        Ok(())
+    }
+    /// Всё действие — одна транзакция. Итоги затронутых балансов пересчитываются.
+    pub async fn bulk_operations(
+        &self,
+        ids: Vec<i32>,
+        action: Bulk,
+    ) -> Result<BTreeMap<i32, OperationsRow>, validation::Error> {
+        {
+            let mut client = self.client.write().await;
+            let tx = client.transaction().await?;
+            // Состав баланса меняется только явной привязкой и отвязкой
+            if !matches!(action, Bulk::SetBalance(_))
+                && let Some(row) = tx
+                    .query_opt(
+                        "SELECT id, balance_id FROM public.operations \
+                        WHERE id = ANY($1) AND balance_id IS NOT NULL LIMIT 1",
+                        &[&ids],
+                    )
+                    .await?
+            {
+                return Err(validation::Error::Locked {
+                    operation: row.try_get(0)?,
+                    balance: row.try_get(1)?,
+                });
+            }
+            let balances: Vec<Option<i32>> = tx
+                .query(
+                    "SELECT DISTINCT balance_id FROM public.operations WHERE id = ANY($1)",
+                    &[&ids],
+                )
+                .await?
+                .iter()
+                .map(|row| row.try_get(0))
+                .collect::<Result<_, _>>()?;
+            let mut balances: Vec<i32> = balances.into_iter().flatten().collect();
+            match action {
+                Bulk::Delete => {
+                    tx.execute("DELETE FROM public.operations WHERE id = ANY($1)", &[&ids])
+                        .await?
+                }
+                Bulk::SetArticle(article_id) => {
+                    tx.execute(
+                        "UPDATE public.operations SET article_id = $2 WHERE id = ANY($1)",
+                        &[&ids, &article_id],
+                    )
+                    .await?
+                }
+                Bulk::ShiftDate(days) => {
+                    tx.execute(
+                        "UPDATE public.operations \
+                        SET create_date = create_date + make_interval(days => $2) \
+                        WHERE id = ANY($1)",
+                        &[&ids, &days],
+                    )
+                    .await?
+                }
+                Bulk::SetBalance(balance_id) => {
+                    balances.extend(balance_id);
+                    // Каждая операция, сменившая баланс, попадает в журнал правок
+                    let moved = tx
+                        .query(
+                            "SELECT * FROM public.operations \
+                            WHERE id = ANY($1) AND balance_id IS DISTINCT FROM $2 FOR UPDATE",
+                            &[&ids, &balance_id],
+                        )
+                        .await?;
+                    for row in moved {
+                        let (id, before) = OperationsRow::new(row)?;
+                        let after = OperationsRow {
+                            balance_id,
+                            ..before.clone()
+                        };
+                        let affected = before.balance_id.or(balance_id);
+                        Self::record_override(&tx, id, affected, Some(&before), Some(&after))
+                            .await?;
+                    }
+                    tx.execute(
+                        "UPDATE public.operations SET balance_id = $2 WHERE id = ANY($1)",
+                        &[&ids, &balance_id],
+                    )
+                    .await?
+                }
+            };
+            Self::recalculate_balances(&tx, &balances).await?;
+            tx.commit().await?;
+        }
+        Ok(self.select_from_operations().await?)
     }
     pub async fn select_from_articles(&self) -> Result<BTreeMap<i32, ArticlesRow>, Error> {
-        self.client
+        self.client()
+            .await
             .query_raw(&self.select_from_articles, NO_PARAMS)
             .await?
             .map_ok(|r| ArticlesRow::new(r))
@@ -156,12 +572,45 @@ impl Inner {
             .try_collect()
             .await
     }
+    /// Возвращает id новой строки вместе со всеми строками
     pub async fn insert_to_articles(
         &self,
         row: ArticlesRow,
+    ) -> Result<Inserted<ArticlesRow>, Error> {
+        let inserted = self
+            .client()
+            .await
+            .query_one(
+                &self.insert_to_articles,
+                &[
+                    &row.name,
+                    &row.parent_id,
+                    &row.color,
+                    &row.icon,
+                    &row.archived,
+                ],
+            )
+            .await?;
+        Ok((inserted.try_get("id")?, self.select_from_articles().await?))
+    }
+    pub async fn reinsert_to_articles(
+        &self,
+        id: i32,
+        row: ArticlesRow,
     ) -> Result<BTreeMap<i32, ArticlesRow>, Error> {
-        self.client
-            .execute(&self.insert_to_articles, &[&row.name])
+        self.client()
+            .await
+            .execute(
+                &self.reinsert_to_articles,
+                &[
+                    &id,
+                    &row.name,
+                    &row.parent_id,
+                    &row.color,
+                    &row.icon,
+                    &row.archived,
+                ],
+            )
             .await?;
         self.select_from_articles().await
     }
@@ -170,19 +619,477 @@ impl Inner {
         id: i32,
         row: ArticlesRow,
     ) -> Result<BTreeMap<i32, ArticlesRow>, Error> {
-        self.client
-            .execute(&self.update_in_articles, &[&id, &row.name])
+        self.client()
+            .await
+            .execute(
+                &self.update_in_articles,
+                &[
+                    &id,
+                    &row.name,
+                    &row.parent_id,
+                    &row.color,
+                    &row.icon,
+                    &row.archived,
+                ],
+            )
             .await?;
         self.select_from_articles().await
     }
     pub async fn delete_from_articles(&self, id: i32) -> Result<BTreeMap<i32, ArticlesRow>, Error> {
-        self.client
+        self.client()
+            .await
             .execute(&self.delete_from_articles, &[&id])
             .await?;
         self.select_from_articles().await
     }
+    /// Сколько операций ссылается на каждую статью и на какую сумму
+    pub async fn select_article_usage(&self) -> Result<BTreeMap<i32, ArticleUsage>, Error> {
+        let rows = self
+            .client()
+            .await
+            .query(
+                "SELECT article_id AS id, COUNT(*) AS operations, \
+                COUNT(balance_id) AS locked, \
+                COALESCE(SUM(debit), 0) AS debit, \
+                COALESCE(SUM(credit), 0) AS credit \
+                FROM public.operations WHERE article_id IS NOT NULL \
+                GROUP BY article_id",
+                &[],
+            )
+            .await?;
+        rows.into_iter().map(ArticleUsage::new).collect()
+    }
+    /// Удаляет статью вместе с тем, что на неё ссылается, в одной транзакции.
+    /// При переносе в другую статью туда же уходят строки разбивки, шаблоны,
+    /// бюджеты (складываясь с бюджетами на тот же месяц) и вложенные статьи,
+    /// иначе они остаются без статьи. Операции из
+    /// сформированных балансов переносятся с записью в журнал.
+    pub async fn delete_article(
+        &self,
+        id: i32,
+        orphans: Orphans,
+    ) -> Result<BTreeMap<i32, ArticlesRow>, validation::Error> {
+        {
+            let mut client = self.client.write().await;
+            let tx = client.transaction().await?;
+            match orphans {
+                Orphans::Reassign(target) => {
+                    Self::move_operations(&tx, id, Some(target)).await?;
+                    for query in [
+                        "UPDATE public.operation_splits SET article_id = $2 WHERE article_id = $1",
+                        "UPDATE public.recurring SET article_id = $2 WHERE article_id = $1",
+                        "UPDATE public.articles SET parent_id = $2 WHERE parent_id = $1",
+                        "INSERT INTO public.budgets(article_id, month, amount) \
+                        SELECT $2, month, amount FROM public.budgets WHERE article_id = $1 \
+                        ON CONFLICT (article_id, month) \
+                        DO UPDATE SET amount = budgets.amount + EXCLUDED.amount",
+                    ] {
+                        tx.execute(query, &[&id, &target]).await?;
+                    }
+                }
+                Orphans::Unlink => {
+                    Self::move_operations(&tx, id, None).await?;
+                }
+                // Операции из балансов не удаляются. Внешнего ключа на статью в схеме
+                // может не быть, поэтому такие операции проверяются здесь же.
+                Orphans::Delete => {
+                    let locked = tx
+                        .query_opt(
+                            "SELECT id, balance_id FROM public.operations \
+                            WHERE article_id = $1 AND balance_id IS NOT NULL \
+                            LIMIT 1 FOR UPDATE",
+                            &[&id],
+                        )
+                        .await?;
+                    if let Some(locked) = locked {
+                        return Err(validation::Error::Locked {
+                            operation: locked.try_get("id")?,
+                            balance: locked.try_get("balance_id")?,
+                        });
+                    }
+                    tx.execute(
+                        "DELETE FROM public.operations \
+                        WHERE article_id = $1 AND balance_id IS NULL",
+                        &[&id],
+                    )
+                    .await?;
+                }
+            }
+            tx.execute("DELETE FROM public.articles WHERE id = $1", &[&id])
+                .await?;
+            tx.commit().await?;
+        }
+        Ok(self.select_from_articles().await?)
+    }
+    /// Переносит операции статьи `id` в `target` или оставляет без статьи. Суммы балансов от этого не меняются,
+    /// но операции из них меняются в обход блокировки и попадают в журнал.
+    async fn move_operations(
+        tx: &Transaction<'_>,
+        id: i32,
+        target: Option<i32>,
+    ) -> Result<(), Error> {
+        let locked = tx
+            .query(
+                "SELECT * FROM public.operations \
+                WHERE article_id = $1 AND balance_id IS NOT NULL FOR UPDATE",
+                &[&id],
+            )
+            .await?;
+        tx.execute(
+            "UPDATE public.operations SET article_id = $2 WHERE article_id = $1",
+            &[&id, &target],
+        )
+        .await?;
+        for row in locked {
+            let (operation_id, before) = OperationsRow::new(row)?;
+            let after = OperationsRow {
+                article_id: target,
+                ..before.clone()
+            };
+            Self::record_override(
+                tx,
+                operation_id,
+                before.balance_id,
+                Some(&before),
+                Some(&after),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    /// План и факт за месяц по статьям, у которых есть бюджет или расходы.
+    /// Факт статьи включает расходы вложенных в неё статей.
+    pub async fn select_budget(
+        &self,
+        month: NaiveDate,
+    ) -> Result<BTreeMap<i32, BudgetLine>, Error> {
+        let rows = self
+            .client()
+            .await
+            .query(
+                "WITH RECURSIVE subtree AS ( \
+                	SELECT id AS root, id FROM public.articles \
+                	UNION ALL \
+                	SELECT subtree.root, art.id FROM public.articles art \
+                	JOIN subtree ON art.parent_id = subtree.id \
+                ), spent AS ( \
+                	SELECT subtree.root AS article_id, \
+                	CAST(COALESCE(SUM(ops.credit), 0) AS BIGINT) AS actual \
+                	FROM public.operation_lines ops \
+                	JOIN subtree ON subtree.id = ops.article_id \
+                	WHERE ops.create_date >= CAST($1 AS DATE) \
+                	AND ops.create_date < CAST($1 AS DATE) + INTERVAL '1 month' \
+                	GROUP BY subtree.root \
+                ), planned AS ( \
+                	SELECT article_id, amount FROM public.budgets WHERE month = CAST($1 AS DATE) \
+                ) \
+                SELECT COALESCE(planned.article_id, spent.article_id) AS article_id, \
+                planned.amount AS planned, \
+                COALESCE(spent.actual, 0) AS actual \
+                FROM planned FULL JOIN spent ON spent.article_id = planned.article_id",
+                &[&month],
+            )
+            .await?;
+        rows.into_iter().map(BudgetLine::new).collect()
+    }
+    /// Задаёт бюджет статьи на месяц, `None` убирает его
+    pub async fn set_budget(
+        &self,
+        month: NaiveDate,
+        article_id: i32,
+        amount: Option<i32>,
+    ) -> Result<BTreeMap<i32, BudgetLine>, Error> {
+        let client = self.client().await;
+        match amount {
+            Some(amount) => {
+                client
+                    .execute(
+                        "INSERT INTO public.budgets(article_id, month, amount) \
+                        VALUES ($1, $2, $3) \
+                        ON CONFLICT (article_id, month) DO UPDATE SET amount = EXCLUDED.amount",
+                        &[&article_id, &month, &amount],
+                    )
+                    .await?;
+            }
+            None => {
+                client
+                    .execute(
+                        "DELETE FROM public.budgets WHERE article_id = $1 AND month = $2",
+                        &[&article_id, &month],
+                    )
+                    .await?;
+            }
+        }
+        drop(client);
+        self.select_budget(month).await
+    }
+    pub async fn select_from_recurring(&self) -> Result<BTreeMap<i32, RecurringRow>, Error> {
+        self.client()
+            .await
+            .query_raw(&self.select_from_recurring, NO_PARAMS)
+            .await?
+            .map_ok(|r| RecurringRow::new(r))
+            .map(|r| r.flatten())
+            .try_collect()
+            .await
+    }
+    pub async fn insert_to_recurring(
+        &self,
+        row: RecurringRow,
+    ) -> Result<BTreeMap<i32, RecurringRow>, Error> {
+        self.write_recurring(&self.insert_to_recurring, None, &row)
+            .await
+    }
+    pub async fn update_in_recurring(
+        &self,
+        id: i32,
+        row: RecurringRow,
+    ) -> Result<BTreeMap<i32, RecurringRow>, Error> {
+        self.write_recurring(&self.update_in_recurring, Some(id), &row)
+            .await
+    }
+    /// Вставка и изменение отличаются только последним параметром — id
+    async fn write_recurring(
+        &self,
+        statement: &Statement,
+        id: Option<i32>,
+        row: &RecurringRow,
+    ) -> Result<BTreeMap<i32, RecurringRow>, Error> {
+        let schedule = &row.schedule;
+        let frequency = schedule.frequency.code();
+        let nth = schedule.nth_weekday.map(|nth| nth.n);
+        let weekday = schedule
+            .nth_weekday
+            .map(|nth| nth.weekday.num_days_from_monday().cast_signed());
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![
+            &row.article_id,
+            &row.debit,
+            &row.credit,
+            &frequency,
+            &schedule.every,
+            &nth,
+            &weekday,
+            &schedule.start,
+            &schedule.end,
+            &row.next_due,
+            &row.confirm,
+        ];
+        if let Some(id) = &id {
+            params.push(id);
+        }
+        self.client().await.execute(statement, &params).await?;
+        self.select_from_recurring().await
+    }
+    pub async fn select_from_attachments(&self) -> Result<BTreeMap<i32, AttachmentRow>, Error> {
+        self.client()
+            .await
+            .query_raw(&self.select_from_attachments, NO_PARAMS)
+            .await?
+            .map_ok(|r| AttachmentRow::new(r))
+            .map(|r| r.flatten())
+            .try_collect()
+            .await
+    }
+    pub async fn insert_to_attachments(
+        &self,
+        operation_id: i32,
+        file: File,
+    ) -> Result<BTreeMap<i32, AttachmentRow>, attachments::Error> {
+        self.client()
+            .await
+            .execute(
+                "INSERT INTO public.attachments(operation_id, file_name, mime, content) \
+                VALUES ($1, $2, $3, $4)",
+                &[&operation_id, &file.name, &file.mime, &file.content],
+            )
+            .await?;
+        Ok(self.select_from_attachments().await?)
+    }
+    /// Содержимое файла. Не загружается вместе со списком, чтобы не тянуть все чеки разом.
+    pub async fn attachment_content(&self, id: i32) -> Result<Vec<u8>, Error> {
+        self.client()
+            .await
+            .query_one(
+                "SELECT content FROM public.attachments WHERE id = $1",
+                &[&id],
+            )
+            .await?
+            .try_get("content")
+    }
+    pub async fn delete_from_attachments(
+        &self,
+        id: i32,
+    ) -> Result<BTreeMap<i32, AttachmentRow>, Error> {
+        self.client()
+            .await
+            .execute(&self.delete_from_attachments, &[&id])
+            .await?;
+        self.select_from_attachments().await
+    }
+    /// Строки разбивки, сгруппированные по операциям
+    pub async fn select_from_splits(&self) -> Result<Splits, Error> {
+        Self::select_splits(&*self.client().await).await
+    }
+    async fn select_splits(client: &impl GenericClient) -> Result<Splits, Error> {
+        let rows = client
+            .query(
+                "SELECT operation_id, article_id, debit, credit \
+                FROM public.operation_splits ORDER BY operation_id, id",
+                &[],
+            )
+            .await?;
+        let mut splits = Splits::new();
+        for row in &rows {
+            let (operation_id, split) = SplitRow::new(row)?;
+            splits.entry(operation_id).or_default().push(split);
+        }
+        Ok(splits)
+    }
+    /// Заменяет разбивку операции. Пустой список убирает разбивку.
+    pub async fn set_splits(
+        &self,
+        operation_id: i32,
+        lines: Vec<SplitRow>,
+    ) -> Result<Splits, validation::Error> {
+        {
+            let mut client = self.client.write().await;
+            let tx = client.transaction().await?;
+            let parent = tx
+                .query_one(
+                    "SELECT debit, credit, balance_id FROM public.operations \
+                    WHERE id = $1 FOR UPDATE",
+                    &[&operation_id],
+                )
+                .await?;
+            // Разбивка меняет суммы по статьям, а они уже вошли в баланс
+            if let Some(balance) = parent.try_get("balance_id")? {
+                return Err(validation::Error::Locked {
+                    operation: operation_id,
+                    balance,
+                });
+            }
+            let (debit, credit): (Option<i32>, Option<i32>) =
+                (parent.try_get("debit")?, parent.try_get("credit")?);
+            let lines_debit: i64 = lines.iter().map(|line| i64::from(line.debit)).sum();
+            let lines_credit: i64 = lines.iter().map(|line| i64::from(line.credit)).sum();
+            if !lines.is_empty()
+                && (lines_debit != i64::from(debit.unwrap_or(0))
+                    || lines_credit != i64::from(credit.unwrap_or(0)))
+            {
+                return Err(validation::Error::Split {
+                    operation: operation_id,
+                    debit: lines_debit,
+                    credit: lines_credit,
+                });
+            }
+            tx.execute(
+                "DELETE FROM public.operation_splits WHERE operation_id = $1",
+                &[&operation_id],
+            )
+            .await?;
+            Self::insert_splits(&tx, operation_id, &lines).await?;
+            tx.commit().await?;
+        }
+        Ok(self.select_from_splits().await?)
+    }
+    async fn insert_splits(
+        tx: &Transaction<'_>,
+        operation_id: i32,
+        lines: &[SplitRow],
+    ) -> Result<(), Error> {
+        for line in lines {
+            tx.execute(
+                "INSERT INTO public.operation_splits(operation_id, article_id, debit, credit) \
+                VALUES ($1, $2, $3, $4)",
+                &[&operation_id, &line.article_id, &line.debit, &line.credit],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    pub async fn delete_from_recurring(
+        &self,
+        id: i32,
+    ) -> Result<BTreeMap<i32, RecurringRow>, Error> {
+        self.client()
+            .await
+            .execute(&self.delete_from_recurring, &[&id])
+            .await?;
+        self.select_from_recurring().await
+    }
+    /// Создаёт операции по наступившим повторениям и сдвигает дату следующего.
+    /// `ids` — шаблоны, выбранные пользователем, `None` — все, что не требуют
+    /// подтверждения. Без `create` повторения пропускаются без создания операций.
+    pub async fn post_recurring(
+        &self,
+        ids: Option<Vec<i32>>,
+        today: NaiveDate,
+        create: bool,
+        rules: Rules,
+    ) -> Result<Posted, Error> {
+        let mut rejected = Vec::new();
+        let created = {
+            let mut client = self.client.write().await;
+            let tx = client.transaction().await?;
+            let templates: Vec<(i32, RecurringRow)> = tx
+                .query(
+                    "SELECT * FROM public.recurring \
+                    WHERE next_due <= $1 \
+                    AND CASE WHEN $2::INT4[] IS NULL THEN NOT confirm ELSE id = ANY($2) END \
+                    FOR UPDATE",
+                    &[&today, &ids],
+                )
+                .await?
+                .into_iter()
+                .map(RecurringRow::new)
+                .collect::<Result<_, _>>()?;
+            let mut created = 0;
+            for (id, template) in &templates {
+                let dates = template.schedule.due(template.next_due, today);
+                let Some(last) = dates.last() else {
+                    continue;
+                };
+                if create {
+                    let rows: Vec<_> = dates.iter().map(|date| template.operation(*date)).collect();
+                    // Правила могли ужесточить уже после того, как шаблон сохранили
+                    if rows.iter().any(|row| rules.check(row).is_err()) {
+                        rejected.push(*id);
+                        continue;
+                    }
+                    for row in &rows {
+                        tx.execute(
+                            &self.insert_to_operations,
+                            &[
+                                &row.article_id,
+                                &row.debit,
+                                &row.credit,
+                                &row.create_date,
+                                &row.description,
+                                &row.tags,
+                            ],
+                        )
+                        .await?;
+                    }
+                    created += dates.len();
+                }
+                tx.execute(
+                    "UPDATE public.recurring SET next_due = $2 WHERE id = $1",
+                    &[id, &template.schedule.next_after(*last)],
+                )
+                .await?;
+            }
+            tx.commit().await?;
+            created
+        };
+        Ok(Posted {
+            templates: self.select_from_recurring().await?,
+            created,
+            rejected,
+        })
+    }
     pub async fn select_from_balance(&self) -> Result<BTreeMap<i32, BalanceRow>, Error> {
-        self.client
+        self.client()
+            .await
             .query_raw(&self.select_from_balance, NO_PARAMS)
             .await?
             .map_ok(|r| BalanceRow::new(r))
@@ -190,17 +1097,83 @@ impl Inner {
             .try_collect()
             .await
     }
-    pub async fn create_balance(&self) -> Result<BTreeMap<i32, BalanceRow>, Error> {
-        self.client.execute(&self.create_balance, &[]).await?;
-        self.select_from_balance().await
+    /// Закрывает в баланс свободные операции не позже `cutoff`, а без него — все
+    pub async fn create_balance(
+        &self,
+        cutoff: Option<NaiveDate>,
+    ) -> Result<BTreeMap<i32, BalanceRow>, balance::Error> {
+        let closed = self
+            .client()
+            .await
+            .execute(&self.create_balance, &[&cutoff])
+            .await?;
+        if closed == 0 {
+            return Err(balance::Error::NothingToClose);
+        }
+        Ok(self.select_from_balance().await?)
+    }
+    /// Операции баланса по статьям: разбитая операция даёт строку на каждую часть
+    pub async fn select_balance_lines(&self, balance_id: i32) -> Result<Vec<StatementLine>, Error> {
+        let rows = self
+            .client()
+            .await
+            .query(
+                "SELECT lines.id, lines.article_id, lines.debit, lines.credit, \
+                lines.create_date, ops.description \
+                FROM public.operation_lines lines \
+                JOIN public.operations ops ON ops.id = lines.id \
+                WHERE lines.balance_id = $1 \
+                ORDER BY lines.create_date, lines.id",
+                &[&balance_id],
+            )
+            .await?;
+        rows.into_iter().map(StatementLine::new).collect()
+    }
+    /// Итоги операций, которые войдут в баланс при таком `cutoff`, и сколько останется вне его
+    pub async fn balance_preview(
+        &self,
+        cutoff: Option<NaiveDate>,
+    ) -> Result<BalancePreview, Error> {
+        let row = self
+            .client()
+            .await
+            .query_one(
+                "SELECT CAST(COALESCE(SUM(ops.debit) FILTER (WHERE included), 0) AS BIGINT) AS debit, \
+                CAST(COALESCE(SUM(ops.credit) FILTER (WHERE included), 0) AS BIGINT) AS credit, \
+                COUNT(*) FILTER (WHERE included) AS count, \
+                MIN(ops.create_date) FILTER (WHERE included) AS first, \
+                MAX(ops.create_date) FILTER (WHERE included) AS last, \
+                COUNT(*) FILTER (WHERE NOT included) AS left_out \
+                FROM ( \
+                	SELECT *, (CAST($1 AS DATE) IS NULL \
+                	OR create_date < CAST($1 AS DATE) + 1) AS included \
+                	FROM public.operations WHERE balance_id IS NULL \
+                ) ops",
+                &[&cutoff],
+            )
+            .await?;
+        BalancePreview::new(row)
     }
     pub async fn remove_balance(&self) -> Result<BTreeMap<i32, BalanceRow>, Error> {
-        self.client.execute(&self.remove_balance, &[]).await?;
+        self.client()
+            .await
+            .execute(&self.remove_balance, &[])
+            .await?;
         self.select_from_balance().await
     }
-    pub async fn show_percents(&self) -> Result<Vec<PercentsBar>, Error> {
-        self.client
-            .query_raw(&self.show_percents, NO_PARAMS)
+    /// По меткам статьи не сворачиваются, `rollup` не учитывается
+    pub async fn show_percents(
+        &self,
+        grouping: Grouping,
+        rollup: Rollup,
+    ) -> Result<Vec<PercentsBar>, Error> {
+        let (statement, params): (_, &[&(dyn ToSql + Sync)]) = match grouping {
+            Grouping::Articles => (&self.show_percents, &[&rollup.within, &rollup.level]),
+            Grouping::Tags => (&self.show_percents_by_tag, &[]),
+        };
+        self.client()
+            .await
+            .query_raw(statement, params.iter().copied())
             .await?
             .map_ok(|r| PercentsBar::new(r))
             .map(|r| r.flatten())
@@ -208,7 +1181,8 @@ impl Inner {
             .await
     }
     pub async fn show_profit(&self) -> Result<Vec<ProfitPoint>, Error> {
-        self.client
+        self.client()
+            .await
             .query_raw(&self.show_profit, NO_PARAMS)
             .await?
             .map_ok(|r| ProfitPoint::new(r))
@@ -216,27 +1190,554 @@ impl Inner {
             .try_collect()
             .await
     }
+    /// Выбранные статьи или метки, смотря по группировке
     pub async fn show_dynamics(
         &self,
+        grouping: Grouping,
         articles: Vec<i32>,
+        tags: Vec<String>,
         start: NaiveDateTime,
         end: NaiveDateTime,
     ) -> Result<Vec<DynamicsPoint>, Error> {
-        let params: [&(dyn ToSql + Sync); _] = [&articles, &start, &end];
-        self.client
-            .query_raw(&self.show_dynamics, params)
+        let (statement, params): (_, [&(dyn ToSql + Sync); _]) = match grouping {
+            Grouping::Articles => (&self.show_dynamics, [&articles, &start, &end]),
+            Grouping::Tags => (&self.show_dynamics_by_tag, [&tags, &start, &end]),
+        };
+        self.client()
+            .await
+            .query_raw(statement, params)
             .await?
             .map_ok(|r| DynamicsPoint::new(r))
             .map(|r| r.flatten())
             .try_collect()
             .await
     }
+    pub async fn report(&self, period: Period) -> Result<Report, Error> {
+        let previous = match period {
+            Period::Dates { .. } => period.previous_dates(),
+            Period::Balance(id) => self
+                .client()
+                .await
+                .query_opt(&self.previous_balance, &[&id])
+                .await?
+                .map(|r| r.try_get("id").map(Period::Balance))
+                .transpose()?,
+        };
+        let current = self.period_data(period).await?;
+        let previous = match previous {
+            Some(previous) => Some((previous, self.period_data(previous).await?)),
+            None => None,
+        };
+        Ok(Report {
+            period,
+            current,
+            previous,
+        })
+    }
+    async fn period_data(&self, period: Period) -> Result<PeriodData, Error> {
+        let (balance_id, start, end) = period.params();
+        let params: [&(dyn ToSql + Sync); _] = [&balance_id, &start, &end];
+        let client = self.client().await;
+        let totals = async { Totals::new(client.query_one(&self.report_totals, &params).await?) };
+        let articles = async {
+            client
+                .query_raw(&self.report_articles, params)
+                .await?
+                .map_ok(ArticleTotals::new)
+                .map(|r| r.flatten())
+                .try_collect()
+                .await
+        };
+        let profit = async {
+            client
+                .query_raw(&self.report_profit, params)
+                .await?
+                .map_ok(ProfitPoint::new)
+                .map(|r| r.flatten())
+                .try_collect()
+                .await
+        };
+        let top = async {
+            let params: [&(dyn ToSql + Sync); _] = [&balance_id, &start, &end, &TOP_OPERATIONS];
+            client
+                .query_raw(&self.report_top, params)
+                .await?
+                .map_ok(TopOperation::new)
+                .map(|r| r.flatten())
+                .try_collect()
+                .await
+        };
+        let (totals, articles, profit, top) = tokio::try_join!(totals, articles, profit, top)?;
+        Ok(PeriodData {
+            totals,
+            articles,
+            profit,
+            top,
+        })
+    }
+    pub async fn backup(&self) -> Result<Backup, Error> {
+        let mut client = self.client.write().await;
+        let tx = client
+            .build_transaction()
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start()
+            .await?;
+        let articles = Self::select_entries(&tx, &self.select_from_articles, ArticlesRow::new);
+        let balance = Self::select_entries(&tx, &self.select_from_balance, BalanceRow::new);
+        let operations =
+            Self::select_entries(&tx, &self.select_from_operations, OperationsRow::new);
+        let recurring = Self::select_entries(&tx, &self.select_from_recurring, RecurringRow::new);
+        let attachments = async {
+            tx.query(
+                "SELECT operation_id, file_name, mime, create_date, content \
+                FROM public.attachments ORDER BY id",
+                &[],
+            )
+            .await?
+            .into_iter()
+            .map(|row| {
+                Ok(Attachment {
+                    operation_id: row.try_get("operation_id")?,
+                    file_name: row.try_get("file_name")?,
+                    mime: row.try_get("mime")?,
+                    create_date: row.try_get("create_date")?,
+                    content: row.try_get("content")?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()
+        };
+        let splits = Self::select_splits(&tx);
+        let budgets = async {
+            tx.query(
+                "SELECT article_id, month, amount FROM public.budgets ORDER BY id",
+                &[],
+            )
+            .await?
+            .into_iter()
+            .map(|row| {
+                Ok(Budget {
+                    article_id: row.try_get("article_id")?,
+                    month: row.try_get("month")?,
+                    amount: row.try_get("amount")?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()
+        };
+        let (articles, balance, operations, recurring, attachments, splits, budgets) = tokio::try_join!(
+            articles,
+            balance,
+            operations,
+            recurring,
+            attachments,
+            splits,
+            budgets
+        )?;
+        tx.commit().await?;
+        Ok(Backup::new(
+            articles,
+            balance,
+            operations,
+            recurring,
+            attachments,
+            splits,
+            budgets,
+        ))
+    }
+    pub async fn restore(&self, backup: Backup, mode: RestoreMode) -> Result<Summary, Error> {
+        let mut client = self.client.write().await;
+        let tx = client.transaction().await?;
+        let keep_ids = mode == RestoreMode::Replace;
+        if keep_ids {
+            tx.batch_execute(
+                "DELETE FROM public.recurring; \
+                DELETE FROM public.attachments; \
+                DELETE FROM public.operation_splits; \
+                DELETE FROM public.budgets; \
+                DELETE FROM public.operations; \
+                DELETE FROM public.balance; \
+                DELETE FROM public.articles;",
+            )
+            .await?;
+        }
+        let articles = Self::restore_articles(&tx, &backup.articles, keep_ids).await?;
+        let balance = Self::restore_balance(&tx, &backup.balance, keep_ids).await?;
+        let operations =
+            Self::restore_operations(&tx, &backup.operations, keep_ids, &articles, &balance)
+                .await?;
+        Self::restore_recurring(&tx, &backup.recurring, keep_ids, &articles).await?;
+        Self::restore_attachments(&tx, &backup.attachments, &operations).await?;
+        Self::restore_splits(&tx, &backup.splits, &operations, &articles).await?;
+        Self::restore_budgets(&tx, &backup.budgets, &articles).await?;
+        if keep_ids {
+            tx.batch_execute(
+                "SELECT setval(pg_get_serial_sequence('public.articles', 'id'), \
+                	COALESCE(MAX(id), 1), MAX(id) IS NOT NULL) FROM public.articles; \
+                SELECT setval(pg_get_serial_sequence('public.balance', 'id'), \
+                	COALESCE(MAX(id), 1), MAX(id) IS NOT NULL) FROM public.balance; \
+                SELECT setval(pg_get_serial_sequence('public.operations', 'id'), \
+                	COALESCE(MAX(id), 1), MAX(id) IS NOT NULL) FROM public.operations; \
+                SELECT setval(pg_get_serial_sequence('public.recurring', 'id'), \
+                	COALESCE(MAX(id), 1), MAX(id) IS NOT NULL) FROM public.recurring;",
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(backup.summary())
+    }
+    async fn select_entries<T>(
+        tx: &Transaction<'_>,
+        statement: &Statement,
+        new: impl Fn(tokio_postgres::Row) -> Result<(i32, T), Error>,
+    ) -> Result<Vec<Entry<T>>, Error> {
+        tx.query_raw(statement, NO_PARAMS)
+            .await?
+            .map_ok(|r| new(r).map(|(id, row)| Entry { id, row }))
+            .map(|r| r.flatten())
+            .try_collect()
+            .await
+    }
+    /// Восстанавливает статьи и возвращает отображение старых id в новые.
+    /// При слиянии статьи с уже существующим именем переиспользуются
+    /// вместе с их местом в иерархии.
+    async fn restore_articles(
+        tx: &Transaction<'_>,
+        articles: &[Entry<ArticlesRow>],
+        keep_ids: bool,
+    ) -> Result<HashMap<i32, i32>, Error> {
+        let insert = tx
+            .prepare_typed(
+                "INSERT INTO public.articles(id, name, color, icon, archived) \
+                OVERRIDING SYSTEM VALUE \
+                VALUES ( \
+                	COALESCE($1, nextval(pg_get_serial_sequence('public.articles', 'id'))), \
+                	$2, $3, $4, $5 \
+                ) \
+                RETURNING id",
+                &[
+                    Type::INT4,
+                    Type::VARCHAR,
+                    Type::TEXT,
+                    Type::TEXT,
+                    Type::BOOL,
+                ],
+            )
+            .await?;
+        let existing = tx
+            .prepare_typed(
+                "SELECT id FROM public.articles WHERE name = $1 LIMIT 1",
+                &[Type::VARCHAR],
+            )
+            .await?;
+        let mut ids: HashMap<i32, i32> = HashMap::new();
+        let mut inserted_ids = HashSet::new();
+        for Entry { id, row } in articles {
+            if !keep_ids && let Some(found) = tx.query_opt(&existing, &[&row.name]).await? {
+                ids.insert(*id, found.try_get("id")?);
+                continue;
+            }
+            let new_id = keep_ids.then_some(*id);
+            let inserted = tx
+                .query_one(
+                    &insert,
+                    &[&new_id, &row.name, &row.color, &row.icon, &row.archived],
+                )
+                .await?;
+            ids.insert(*id, inserted.try_get("id")?);
+            inserted_ids.insert(*id);
+        }
+        // Родители могут идти в копии после вложенных статей
+        let set_parent = tx
+            .prepare_typed(
+                "UPDATE public.articles SET parent_id = $2 WHERE id = $1",
+                &[Type::INT4, Type::INT4],
+            )
+            .await?;
+        for Entry { id, row } in articles.iter().filter(|e| inserted_ids.contains(&e.id)) {
+            let parent = row.parent_id.and_then(|parent| ids.get(&parent));
+            if let (Some(new_id), Some(parent)) = (ids.get(id), parent) {
+                tx.execute(&set_parent, &[new_id, parent]).await?;
+            }
+        }
+        Ok(ids)
+    }
+    async fn restore_balance(
+        tx: &Transaction<'_>,
+        balance: &[Entry<BalanceRow>],
+        keep_ids: bool,
+    ) -> Result<HashMap<i32, i32>, Error> {
+        let insert = tx
+            .prepare_typed(
+                "INSERT INTO public.balance( \
+                	id, create_date, debit, credit, amount) OVERRIDING SYSTEM VALUE \
+                VALUES (COALESCE($1, nextval(pg_get_serial_sequence('public.balance', 'id'))), \
+                	$2, $3, $4, $5) \
+                RETURNING id",
+                &[
+                    Type::INT4,
+                    Type::TIMESTAMP,
+                    Type::INT4,
+                    Type::INT4,
+                    Type::INT4,
+                ],
+            )
+            .await?;
+        let mut ids = HashMap::new();
+        for Entry { id, row } in balance {
+            let new_id = keep_ids.then_some(*id);
+            let inserted = tx
+                .query_one(
+                    &insert,
+                    &[
+                        &new_id,
+                        &row.create_date,
+                        &row.debit,
+                        &row.credit,
+                        &row.amount,
+                    ],
+                )
+                .await?;
+            ids.insert(*id, inserted.try_get("id")?);
+        }
+        Ok(ids)
+    }
+    async fn restore_operations(
+        tx: &Transaction<'_>,
+        operations: &[Entry<OperationsRow>],
+        keep_ids: bool,
+        articles: &HashMap<i32, i32>,
+        balance: &HashMap<i32, i32>,
+    ) -> Result<HashMap<i32, i32>, Error> {
+        let insert = tx
+            .prepare_typed(
+                "INSERT INTO public.operations( \
+                	id, article_id, balance_id, debit, credit, create_date, description, tags) \
+                OVERRIDING SYSTEM VALUE \
+                VALUES (COALESCE($1, nextval(pg_get_serial_sequence('public.operations', 'id'))), \
+                	$2, $3, $4, $5, $6, $7, $8) \
+                RETURNING id",
+                &[
+                    Type::INT4,
+                    Type::INT4,
+                    Type::INT4,
+                    Type::INT4,
+                    Type::INT4,
+                    Type::TIMESTAMP,
+                    Type::TEXT,
+                    Type::TEXT_ARRAY,
+                ],
+            )
+            .await?;
+        let mut ids = HashMap::new();
+        for Entry { id, row } in operations {
+            let new_id = keep_ids.then_some(*id);
+            let article_id = row.article_id.and_then(|a| articles.get(&a).copied());
+            let balance_id = row.balance_id.and_then(|b| balance.get(&b).copied());
+            let inserted = tx
+                .query_one(
+                    &insert,
+                    &[
+                        &new_id,
+                        &article_id,
+                        &balance_id,
+                        &row.debit,
+                        &row.credit,
+                        &row.create_date,
+                        &row.description,
+                        &row.tags,
+                    ],
+                )
+                .await?;
+            ids.insert(*id, inserted.try_get("id")?);
+        }
+        Ok(ids)
+    }
+    /// При слиянии уже заданный бюджет статьи на месяц не меняется
+    async fn restore_budgets(
+        tx: &Transaction<'_>,
+        budgets: &[Budget],
+        articles: &HashMap<i32, i32>,
+    ) -> Result<(), Error> {
+        let insert = tx
+            .prepare_typed(
+                "INSERT INTO public.budgets(article_id, month, amount) VALUES ($1, $2, $3) \
+                ON CONFLICT (article_id, month) DO NOTHING",
+                &[Type::INT4, Type::DATE, Type::INT4],
+            )
+            .await?;
+        for budget in budgets {
+            let Some(article_id) = articles.get(&budget.article_id) else {
+                continue;
+            };
+            tx.execute(&insert, &[article_id, &budget.month, &budget.amount])
+                .await?;
+        }
+        Ok(())
+    }
+    /// Разбивка операций, которых нет в копии, пропускается
+    async fn restore_splits(
+        tx: &Transaction<'_>,
+        splits: &Splits,
+        operations: &HashMap<i32, i32>,
+        articles: &HashMap<i32, i32>,
+    ) -> Result<(), Error> {
+        for (operation_id, lines) in splits {
+            let Some(operation_id) = operations.get(operation_id) else {
+                continue;
+            };
+            let lines: Vec<_> = lines
+                .iter()
+                .map(|line| SplitRow {
+                    article_id: line.article_id.and_then(|a| articles.get(&a).copied()),
+                    ..line.clone()
+                })
+                .collect();
+            Self::insert_splits(tx, *operation_id, &lines).await?;
+        }
+        Ok(())
+    }
+    /// Файлы операций, которых нет в копии, пропускаются
+    async fn restore_attachments(
+        tx: &Transaction<'_>,
+        attachments: &[Attachment],
+        operations: &HashMap<i32, i32>,
+    ) -> Result<(), Error> {
+        let insert = tx
+            .prepare_typed(
+                "INSERT INTO public.attachments(operation_id, file_name, mime, create_date, content) \
+                VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    Type::INT4,
+                    Type::TEXT,
+                    Type::TEXT,
+                    Type::TIMESTAMP,
+                    Type::BYTEA,
+                ],
+            )
+            .await?;
+        for attachment in attachments {
+            let Some(operation_id) = operations.get(&attachment.operation_id) else {
+                continue;
+            };
+            tx.execute(
+                &insert,
+                &[
+                    operation_id,
+                    &attachment.file_name,
+                    &attachment.mime,
+                    &attachment.create_date,
+                    &attachment.content,
+                ],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    /// Шаблоны восстанавливаются со своей датой следующего повторения,
+    /// чтобы не создать уже созданные операции ещё раз
+    async fn restore_recurring(
+        tx: &Transaction<'_>,
+        recurring: &[Entry<RecurringRow>],
+        keep_ids: bool,
+        articles: &HashMap<i32, i32>,
+    ) -> Result<(), Error> {
+        let mut types = vec![Type::INT4];
+        types.extend_from_slice(RECURRING_TYPES);
+        let insert = tx
+            .prepare_typed(
+                "INSERT INTO public.recurring( \
+                	id, article_id, debit, credit, frequency, every, nth, weekday, \
+                	start_date, end_date, next_due, confirm) \
+                OVERRIDING SYSTEM VALUE \
+                VALUES (COALESCE($1, nextval(pg_get_serial_sequence('public.recurring', 'id'))), \
+                	$2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+                &types,
+            )
+            .await?;
+        for Entry { id, row } in recurring {
+            let new_id = keep_ids.then_some(*id);
+            let article_id = row.article_id.and_then(|a| articles.get(&a).copied());
+            let schedule = &row.schedule;
+            let nth = schedule.nth_weekday.map(|nth| nth.n);
+            let weekday = schedule
+                .nth_weekday
+                .map(|nth| nth.weekday.num_days_from_monday().cast_signed());
+            tx.execute(
+                &insert,
+                &[
+                    &new_id,
+                    &article_id,
+                    &row.debit,
+                    &row.credit,
+                    &schedule.frequency.code(),
+                    &schedule.every,
+                    &nth,
+                    &weekday,
+                    &schedule.start,
+                    &schedule.end,
+                    &row.next_due,
+                    &row.confirm,
+                ],
+            )
+            .await?;
+        }
+        Ok(())
+    }
     async fn prepare_select_from_operations(client: &Client) -> Result<Statement, Error> {
         client.prepare("SELECT * FROM public.operations").await
     }
     async fn prepare_select_from_articles(client: &Client) -> Result<Statement, Error> {
         client.prepare("SELECT * FROM public.articles").await
     }
+    async fn prepare_select_from_recurring(client: &Client) -> Result<Statement, Error> {
+        client.prepare("SELECT * FROM public.recurring").await
+    }
+    async fn prepare_insert_to_recurring(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                "INSERT INTO public.recurring( \
+            	article_id, debit, credit, frequency, every, nth, weekday, \
+            	start_date, end_date, next_due, confirm) \
+            	VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+                RECURRING_TYPES,
+            )
+            .await
+    }
+    async fn prepare_update_in_recurring(client: &Client) -> Result<Statement, Error> {
+        let mut types = RECURRING_TYPES.to_vec();
+        types.push(Type::INT4);
+        client
+            .prepare_typed(
+                "UPDATE public.recurring \
+            	SET article_id=$1, debit=$2, credit=$3, frequency=$4, every=$5, nth=$6, \
+            	weekday=$7, start_date=$8, end_date=$9, next_due=$10, confirm=$11 \
+            	WHERE id=$12",
+                &types,
+            )
+            .await
+    }
+    async fn prepare_delete_from_recurring(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed("DELETE FROM public.recurring WHERE id = $1", &[Type::INT4])
+            .await
+    }
+    async fn prepare_select_from_attachments(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare(
+                "SELECT id, operation_id, file_name, mime, \
+                octet_length(content) AS size, create_date \
+                FROM public.attachments",
+            )
+            .await
+    }
+    async fn prepare_delete_from_attachments(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                "DELETE FROM public.attachments WHERE id = $1",
+                &[Type::INT4],
+            )
+            .await
+    }
     async fn prepare_select_from_balance(client: &Client) -> Result<Statement, Error> {
         client.prepare("SELECT * FROM public.balance").await
     }
@@ -244,17 +1745,70 @@ impl Inner {
         client
             .prepare_typed(
                 "INSERT INTO public.operations( \
-            	article_id, debit, credit, create_date)\
-            	VALUES ($1, $2, $3, $4)",
-                &[Type::INT4, Type::INT4, Type::INT4, Type::TIMESTAMP],
+            	article_id, debit, credit, create_date, description, tags)\
+            	VALUES ($1, $2, $3, $4, $5, $6) \
+            	RETURNING id",
+                &[
+                    Type::INT4,
+                    Type::INT4,
+                    Type::INT4,
+                    Type::TIMESTAMP,
+                    Type::TEXT,
+                    Type::TEXT_ARRAY,
+                ],
             )
             .await
     }
     async fn prepare_insert_to_articles(client: &Client) -> Result<Statement, Error> {
         client
             .prepare_typed(
-                "INSERT INTO public.articles(name) VALUES ($1)",
-                &[Type::VARCHAR],
+                "INSERT INTO public.articles(name, parent_id, color, icon, archived) \
+                VALUES ($1, $2, $3, $4, $5) \
+                RETURNING id",
+                &[
+                    Type::VARCHAR,
+                    Type::INT4,
+                    Type::TEXT,
+                    Type::TEXT,
+                    Type::BOOL,
+                ],
+            )
+            .await
+    }
+    async fn prepare_reinsert_to_operations(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                "INSERT INTO public.operations( \
+            	id, article_id, debit, credit, create_date, balance_id, description, tags) \
+            	OVERRIDING SYSTEM VALUE \
+            	VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[
+                    Type::INT4,
+                    Type::INT4,
+                    Type::INT4,
+                    Type::INT4,
+                    Type::TIMESTAMP,
+                    Type::INT4,
+                    Type::TEXT,
+                    Type::TEXT_ARRAY,
+                ],
+            )
+            .await
+    }
+    async fn prepare_reinsert_to_articles(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                "INSERT INTO public.articles(id, name, parent_id, color, icon, archived) \
+                OVERRIDING SYSTEM VALUE \
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    Type::INT4,
+                    Type::VARCHAR,
+                    Type::INT4,
+                    Type::TEXT,
+                    Type::TEXT,
+                    Type::BOOL,
+                ],
             )
             .await
     }
@@ -262,14 +1816,16 @@ impl Inner {
         client
             .prepare_typed(
                 "UPDATE public.operations \
-            	SET article_id=$2, debit=$3, credit=$4, create_date=$5 \
-            	WHERE id=$1",
+            	SET article_id=$2, debit=$3, credit=$4, create_date=$5, description=$6, tags=$7 \
+            	WHERE id=$1 AND balance_id IS NULL",
                 &[
                     Type::INT4,
                     Type::INT4,
                     Type::INT4,
                     Type::INT4,
                     Type::TIMESTAMP,
+                    Type::TEXT,
+                    Type::TEXT_ARRAY,
                 ],
             )
             .await
@@ -278,16 +1834,26 @@ impl Inner {
         client
             .prepare_typed(
                 "UPDATE public.articles \
-            	SET name=$2 \
+            	SET name=$2, parent_id=$3, color=$4, icon=$5, archived=$6 \
             	WHERE id=$1",
-                &[Type::INT4, Type::VARCHAR],
+                &[
+                    Type::INT4,
+                    Type::VARCHAR,
+                    Type::INT4,
+                    Type::TEXT,
+                    Type::TEXT,
+                    Type::BOOL,
+                ],
             )
             .await
     }
 
     async fn prepare_delete_from_operations(client: &Client) -> Result<Statement, Error> {
         client
-            .prepare_typed("DELETE FROM public.operations WHERE id = $1", &[Type::INT4])
+            .prepare_typed(
+                "DELETE FROM public.operations WHERE id = $1 AND balance_id IS NULL",
+                &[Type::INT4],
+            )
             .await
     }
     async fn prepare_delete_from_articles(client: &Client) -> Result<Statement, Error> {
@@ -305,12 +1871,15 @@ impl Inner {
                 	SUM(ops.debit), SUM(ops.credit), \
                 	SUM(ops.debit) - SUM(ops.credit) \
                 	FROM public.operations ops WHERE ops.balance_id is NULL \
+                	AND (CAST($1 AS DATE) IS NULL OR ops.create_date < CAST($1 AS DATE) + 1) \
+                	HAVING COUNT(*) > 0 \
                 	RETURNING id \
                 ) \
                 UPDATE public.operations \
                 SET balance_id=(SELECT * FROM new_balance) \
-                WHERE balance_id IS NULL",
-                &[],
+                WHERE balance_id IS NULL \
+                AND (CAST($1 AS DATE) IS NULL OR create_date < CAST($1 AS DATE) + 1)",
+                &[Type::DATE],
             )
             .await
     }
@@ -336,15 +1905,33 @@ impl Inner {
             )
             .await
     }
+    /// Статья каждой строки заменяется предком на уровне `$2` под статьёй `$1`.
+    /// Внутри статьи проценты считаются от её суммы, иначе — от суммы всех операций.
     async fn prepare_show_percents(client: &Client) -> Result<Statement, Error> {
         client
             .prepare_typed(
-                "WITH totals AS ( \
+                "WITH RECURSIVE paths AS ( \
+                	SELECT id, ARRAY[id] AS path FROM public.articles WHERE parent_id IS NULL \
+                	UNION ALL \
+                	SELECT art.id, paths.path || art.id \
+                	FROM public.articles art JOIN paths ON art.parent_id = paths.id \
+                ), groups AS ( \
+                	SELECT id, path[LEAST( \
+                		COALESCE(array_position(path, $1), 0) + $2, cardinality(path) \
+                	)] AS group_id \
+                	FROM paths \
+                	WHERE $1 IS NULL OR $1 = ANY(path) \
+                ), lines AS ( \
+                	SELECT groups.group_id, ops.debit, ops.credit \
+                	FROM public.operation_lines ops \
+                	LEFT JOIN groups ON groups.id = ops.article_id \
+                	WHERE $1 IS NULL OR groups.id IS NOT NULL \
+                ), totals AS ( \
                 	SELECT SUM(ops.debit) AS debit, \
                 	SUM(ops.credit) AS credit \
-                	FROM public.operations ops \
+                	FROM lines ops \
                 ) \
-                SELECT art.name AS article_name, \
+                SELECT art.id AS article_id, art.name AS article_name, \
                 	CAST( \
                 	    100.0 * SUM(ops.debit) / \
                 		NULLIF((SELECT debit FROM totals), 0) \
@@ -355,28 +1942,85 @@ impl Inner {
                 		NULLIF((SELECT credit FROM totals), 0) \
                 		AS DOUBLE PRECISION \
                 	) AS credit \
-                FROM public.operations ops \
+                FROM lines ops \
                 RIGHT JOIN public.articles art \
-                ON art.id = ops.article_id \
+                ON art.id = ops.group_id \
+                WHERE art.id IN (SELECT group_id FROM groups) \
                 GROUP BY art.id \
                 ORDER BY art.id ASC",
-                &[],
+                &[Type::INT4, Type::INT4],
             )
             .await
     }
+    /// Отдельная линия на каждую выбранную статью.
+    /// Выбранная статья учитывается вместе со всеми вложенными.
     async fn prepare_show_dynamics(client: &Client) -> Result<Statement, Error> {
         client
             .prepare_typed(
-                "SELECT ops.create_date AS create_date, \
-                SUM(ops.debit) AS debit, \
-                SUM(ops.credit) AS credit \
+                "WITH RECURSIVE chosen AS ( \
+                	SELECT id, id AS root FROM public.articles WHERE id = ANY($1) \
+                	UNION \
+                	SELECT art.id, chosen.root FROM public.articles art \
+                	JOIN chosen ON art.parent_id = chosen.id \
+                ) \
+                SELECT chosen.root AS article_id, CAST(NULL AS TEXT) AS tag, \
+                ops.create_date AS create_date, \
+                COALESCE(SUM(ops.debit), 0) AS debit, \
+                COALESCE(SUM(ops.credit), 0) AS credit \
+                FROM public.operation_lines ops \
+                JOIN chosen ON chosen.id = ops.article_id \
+                WHERE ops.create_date \
+                BETWEEN $2 AND $3 \
+                GROUP BY chosen.root, ops.create_date \
+                ORDER BY chosen.root, ops.create_date ASC",
+                &[Type::INT4_ARRAY, Type::TIMESTAMP, Type::TIMESTAMP],
+            )
+            .await
+    }
+    /// Операция с несколькими метками учитывается в каждой из них
+    async fn prepare_show_percents_by_tag(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                "WITH totals AS ( \
+                	SELECT SUM(ops.debit) AS debit, \
+                	SUM(ops.credit) AS credit \
+                	FROM public.operations ops \
+                ) \
+                SELECT CAST(NULL AS INT4) AS article_id, tag AS article_name, \
+                	CAST( \
+                	    100.0 * SUM(ops.debit) / \
+                		NULLIF((SELECT debit FROM totals), 0) \
+                		AS DOUBLE PRECISION \
+                	) AS debit, \
+                	CAST( \
+                	    100.0 * SUM(ops.credit) / \
+                		NULLIF((SELECT credit FROM totals), 0) \
+                		AS DOUBLE PRECISION \
+                	) AS credit \
                 FROM public.operations ops \
-                WHERE ops.article_id = ANY($1) \
+                CROSS JOIN LATERAL unnest(ops.tags) AS tag \
+                GROUP BY tag \
+                ORDER BY tag ASC",
+                &[],
+            )
+            .await
+    }
+    /// Отдельная линия на каждую выбранную метку
+    async fn prepare_show_dynamics_by_tag(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                "SELECT CAST(NULL AS INT4) AS article_id, tag, \
+                ops.create_date AS create_date, \
+                COALESCE(SUM(ops.debit), 0) AS debit, \
+                COALESCE(SUM(ops.credit), 0) AS credit \
+                FROM public.operations ops \
+                CROSS JOIN LATERAL unnest(ops.tags) AS tag \
+                WHERE tag = ANY($1) \
                 AND ops.create_date \
                 BETWEEN $2 AND $3 \
-                GROUP BY ops.create_date \
-                ORDER BY ops.create_date ASC",
-                &[Type::INT4_ARRAY, Type::TIMESTAMP, Type::TIMESTAMP],
+                GROUP BY tag, ops.create_date \
+                ORDER BY tag, ops.create_date ASC",
+                &[Type::TEXT_ARRAY, Type::TIMESTAMP, Type::TIMESTAMP],
             )
             .await
     }
@@ -395,7 +2039,234 @@ impl Inner {
             )
             .await
     }
+    async fn prepare_report_totals(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                &format!(
+                    "SELECT CAST(COALESCE(SUM(ops.debit), 0) AS BIGINT) AS debit, \
+                    CAST(COALESCE(SUM(ops.credit), 0) AS BIGINT) AS credit, \
+                    COUNT(*) AS count, \
+                    MIN(ops.create_date) AS first, \
+                    MAX(ops.create_date) AS last \
+                    FROM public.operations ops \
+                    WHERE {PERIOD_FILTER}"
+                ),
+                PERIOD_TYPES,
+            )
+            .await
+    }
+    async fn prepare_report_articles(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                &format!(
+                    "SELECT art.name AS article_name, \
+                    CAST(COALESCE(SUM(ops.debit), 0) AS BIGINT) AS debit, \
+                    CAST(COALESCE(SUM(ops.credit), 0) AS BIGINT) AS credit \
+                    FROM public.operation_lines ops \
+                    LEFT JOIN public.articles art \
+                    ON art.id = ops.article_id \
+                    WHERE {PERIOD_FILTER} \
+                    GROUP BY art.id, art.name \
+                    ORDER BY art.id ASC"
+                ),
+                PERIOD_TYPES,
+            )
+            .await
+    }
+    async fn prepare_report_profit(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                &format!(
+                    "SELECT ops.create_date AS create_date, \
+                    CAST( \
+                        SUM(SUM(COALESCE(ops.debit, 0)) - SUM(COALESCE(ops.credit, 0))) \
+                        OVER (ORDER BY ops.create_date) \
+                        AS DOUBLE PRECISION \
+                    ) AS profit \
+                    FROM public.operations ops \
+                    WHERE {PERIOD_FILTER} AND ops.create_date IS NOT NULL \
+                    GROUP BY ops.create_date \
+                    ORDER BY ops.create_date ASC"
+                ),
+                PERIOD_TYPES,
+            )
+            .await
+    }
+    async fn prepare_report_top(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                &format!(
+                    "SELECT ops.id AS id, art.name AS article_name, \
+                    ops.debit AS debit, ops.credit AS credit, ops.create_date AS create_date \
+                    FROM public.operations ops \
+                    LEFT JOIN public.articles art \
+                    ON art.id = ops.article_id \
+                    WHERE {PERIOD_FILTER} \
+                    ORDER BY GREATEST(COALESCE(ops.debit, 0), COALESCE(ops.credit, 0)) DESC \
+                    LIMIT $4"
+                ),
+                &[Type::INT4, Type::TIMESTAMP, Type::TIMESTAMP, Type::INT8],
+            )
+            .await
+    }
+    async fn prepare_previous_balance(client: &Client) -> Result<Statement, Error> {
+        client
+            .prepare_typed(
+                "SELECT prev.id AS id \
+                FROM public.balance prev, public.balance cur \
+                WHERE cur.id = $1 AND prev.create_date < cur.create_date \
+                ORDER BY prev.create_date DESC \
+                LIMIT 1",
+                &[Type::INT4],
+            )
+            .await
+    }
 }
 
+/// Отбор операций периода: по `balance_id` ($1), если он задан,
+/// иначе по датам [$2, $3).
+const PERIOD_FILTER: &str = "CASE WHEN $1::INT4 IS NULL \
+    THEN ops.create_date >= $2 AND ops.create_date < $3 \
+    ELSE ops.balance_id = $1 END";
+const PERIOD_TYPES: &[Type] = &[Type::INT4, Type::TIMESTAMP, Type::TIMESTAMP];
+
+/// Схема создаётся вне программы, здесь только то, что добавлено позже.
+/// Номер шага — версия схемы в `public.schema_version`: выполняются только
+/// шаги новее записанной версии. Базы, обновлённые до появления версии,
+/// проходят все шаги заново, поэтому каждый должен выполняться повторно без ошибок.
+/// Новые шаги только дописываются в конец.
+const MIGRATIONS: &[&str] = &[
+    // Журнал правок операций из сформированных балансов
+    "\
+        CREATE TABLE IF NOT EXISTS public.balance_overrides ( \
+        	id SERIAL PRIMARY KEY, \
+        	operation_id INT4 NOT NULL, \
+        	balance_id INT4, \
+        	before JSONB, \
+        	after JSONB, \
+        	user_name TEXT NOT NULL DEFAULT CURRENT_USER, \
+        	create_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP \
+        );",
+    // Описания и метки операций
+    "\
+        ALTER TABLE public.operations ADD COLUMN IF NOT EXISTS description TEXT; \
+        ALTER TABLE public.operations \
+        	ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';",
+    // Шаблоны повторяющихся операций
+    "\
+        CREATE TABLE IF NOT EXISTS public.recurring ( \
+        	id SERIAL PRIMARY KEY, \
+        	article_id INT4 REFERENCES public.articles(id) ON DELETE SET NULL, \
+        	debit INT4, \
+        	credit INT4, \
+        	frequency TEXT NOT NULL \
+        		CHECK (frequency IN ('daily', 'weekly', 'monthly', 'yearly')), \
+        	every INT4 NOT NULL DEFAULT 1 CHECK (every > 0), \
+        	nth INT4 CHECK (nth BETWEEN -1 AND 4 AND nth <> 0), \
+        	weekday INT4 CHECK (weekday BETWEEN 0 AND 6), \
+        	start_date DATE NOT NULL, \
+        	end_date DATE, \
+        	next_due DATE, \
+        	confirm BOOLEAN NOT NULL DEFAULT FALSE \
+        );",
+    // Прикреплённые файлы
+    "\
+        CREATE TABLE IF NOT EXISTS public.attachments ( \
+        	id SERIAL PRIMARY KEY, \
+        	operation_id INT4 NOT NULL REFERENCES public.operations(id) ON DELETE CASCADE, \
+        	file_name TEXT NOT NULL, \
+        	mime TEXT NOT NULL, \
+        	content BYTEA NOT NULL, \
+        	create_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP \
+        );",
+    // Разбивка операций по статьям
+    "\
+        CREATE TABLE IF NOT EXISTS public.operation_splits ( \
+        	id SERIAL PRIMARY KEY, \
+        	operation_id INT4 NOT NULL REFERENCES public.operations(id) ON DELETE CASCADE, \
+        	article_id INT4 REFERENCES public.articles(id) ON DELETE SET NULL, \
+        	debit INT4 NOT NULL DEFAULT 0, \
+        	credit INT4 NOT NULL DEFAULT 0 \
+        ); \
+        CREATE OR REPLACE VIEW public.operation_lines AS \
+        	SELECT ops.id, ops.article_id, ops.debit, ops.credit, \
+        	ops.create_date, ops.balance_id, ops.tags \
+        	FROM public.operations ops \
+        	WHERE NOT EXISTS ( \
+        		SELECT 1 FROM public.operation_splits s WHERE s.operation_id = ops.id \
+        	) \
+        	UNION ALL \
+        	SELECT ops.id, s.article_id, s.debit, s.credit, \
+        	ops.create_date, ops.balance_id, ops.tags \
+        	FROM public.operation_splits s \
+        	JOIN public.operations ops ON ops.id = s.operation_id;",
+    // Иерархия статей
+    "\
+        ALTER TABLE public.articles ADD COLUMN IF NOT EXISTS \
+        	parent_id INT4 REFERENCES public.articles(id) ON DELETE SET NULL; \
+        CREATE OR REPLACE FUNCTION public.articles_check_parent() RETURNS trigger AS $$ \
+        BEGIN \
+        	IF EXISTS ( \
+        		WITH RECURSIVE up AS ( \
+        			SELECT NEW.parent_id AS id \
+        			UNION \
+        			SELECT art.parent_id FROM public.articles art JOIN up ON art.id = up.id \
+        		) \
+        		SELECT 1 FROM up WHERE id = NEW.id \
+        	) THEN \
+        		RAISE EXCEPTION 'Статья % не может быть вложена сама в себя', NEW.id; \
+        	END IF; \
+        	RETURN NEW; \
+        END $$ LANGUAGE plpgsql; \
+        CREATE OR REPLACE TRIGGER articles_check_parent \
+        	BEFORE INSERT OR UPDATE OF parent_id ON public.articles \
+        	FOR EACH ROW EXECUTE FUNCTION public.articles_check_parent();",
+    // Цвета и значки статей
+    "\
+        ALTER TABLE public.articles ADD COLUMN IF NOT EXISTS color TEXT; \
+        ALTER TABLE public.articles ADD COLUMN IF NOT EXISTS icon TEXT;",
+    // Бюджеты
+    "\
+        CREATE TABLE IF NOT EXISTS public.budgets ( \
+        	id SERIAL PRIMARY KEY, \
+        	article_id INT4 NOT NULL REFERENCES public.articles(id) ON DELETE CASCADE, \
+        	month DATE NOT NULL CHECK (EXTRACT(DAY FROM month) = 1), \
+        	amount INT4 NOT NULL CHECK (amount >= 0), \
+        	UNIQUE (article_id, month) \
+        );",
+    // Архив статей
+    "\
+        ALTER TABLE public.articles \
+        	ADD COLUMN IF NOT EXISTS archived BOOLEAN NOT NULL DEFAULT FALSE;",
+];
+
+const RECURRING_TYPES: &[Type] = &[
+    Type::INT4,
+    Type::INT4,
+    Type::INT4,
+    Type::TEXT,
+    Type::INT4,
+    Type::INT4,
+    Type::INT4,
+    Type::DATE,
+    Type::DATE,
+    Type::DATE,
+    Type::BOOL,
+];
+
+const RECALCULATE_BALANCES: &str = "\
+    UPDATE public.balance b \
+    SET debit = s.debit, credit = s.credit, amount = s.debit - s.credit \
+    FROM ( \
+    	SELECT bl.id, \
+    	COALESCE(SUM(ops.debit), 0) AS debit, \
+    	COALESCE(SUM(ops.credit), 0) AS credit \
+    	FROM public.balance bl \
+    	LEFT JOIN public.operations ops ON ops.balance_id = bl.id \
+    	WHERE bl.id = ANY($1) \
+    	GROUP BY bl.id \
+    ) s \
+    WHERE b.id = s.id";
+
 // Говорим системе типов замолчать, когда взрослые разговаривают
 const NO_PARAMS: Option<&(dyn ToSql + Sync)> = None;
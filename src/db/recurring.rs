@@ -0,0 +1,382 @@
+//! Повторяющиеся операции: правило, по которому шаблон превращается в операции.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike as _, Days, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
+
+use super::scheme::RecurringRow;
+
+/// Сколько пропущенных повторений создаётся за один раз,
+/// если приложение долго не открывали. Остальные создадутся при следующем входе.
+pub const MAX_CATCH_UP: usize = 366;
+
+/// Итог создания операций по шаблонам
+pub struct Posted {
+    pub templates: BTreeMap<i32, RecurringRow>,
+    pub created: usize,
+    /// Шаблоны, операции по которым не проходят правила проверки.
+    /// По ним ничего не создано, и их даты не сдвинуты.
+    pub rejected: Vec<i32>,
+}
+
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    #[strum(serialize = "Ежедневно")]
+    Daily,
+    #[strum(serialize = "Еженедельно")]
+    Weekly,
+    #[strum(serialize = "Ежемесячно")]
+    Monthly,
+    #[strum(serialize = "Ежегодно")]
+    Yearly,
+}
+pub const FREQUENCIES: [Frequency; 4] = [
+    Frequency::Daily,
+    Frequency::Weekly,
+    Frequency::Monthly,
+    Frequency::Yearly,
+];
+
+/// Например, вторая пятница или последний понедельник месяца
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct NthWeekday {
+    /// С 1 по 4, -1 — последний в месяце
+    pub n: i32,
+    pub weekday: Weekday,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Schedule {
+    pub frequency: Frequency,
+    /// Каждый второй месяц, каждую третью неделю и так далее
+    pub every: i32,
+    /// Только для ежемесячных и ежегодных: вместо числа — день недели месяца
+    pub nth_weekday: Option<NthWeekday>,
+    /// От неё отсчитываются повторения: число месяца, день недели, месяц года
+    pub start: NaiveDate,
+    pub end: Option<NaiveDate>,
+}
+
+impl Frequency {
+    /// Как хранится в базе
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+            Self::Yearly => "yearly",
+        }
+    }
+    pub fn from_code(code: &str) -> Option<Self> {
+        FREQUENCIES.into_iter().find(|f| f.code() == code)
+    }
+    fn unit(self) -> &'static str {
+        match self {
+            Self::Daily => "дн.",
+            Self::Weekly => "нед.",
+            Self::Monthly => "мес.",
+            Self::Yearly => "г.",
+        }
+    }
+}
+
+impl NthWeekday {
+    fn in_month(self, year: i32, month: u32) -> Option<NaiveDate> {
+        if self.n > 0 {
+            return NaiveDate::from_weekday_of_month_opt(
+                year,
+                month,
+                self.weekday,
+                u8::try_from(self.n).ok()?,
+            );
+        }
+        let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let last = first.checked_add_months(Months::new(1))?.pred_opt()?;
+        let back =
+            (last.weekday().num_days_from_monday() + 7 - self.weekday.num_days_from_monday()) % 7;
+        last.checked_sub_days(Days::new(back.into()))
+    }
+}
+
+impl Schedule {
+    pub fn new(start: NaiveDate) -> Self {
+        Self {
+            frequency: Frequency::Monthly,
+            every: 1,
+            nth_weekday: None,
+            start,
+            end: None,
+        }
+    }
+    /// Повторение номер `k`, нулевое приходится на месяц (неделю, день) начала
+    fn occurrence(&self, k: u32) -> Option<NaiveDate> {
+        let step = k.checked_mul(self.every.max(1).cast_unsigned())?;
+        match self.frequency {
+            Frequency::Daily => self.start.checked_add_days(Days::new(step.into())),
+            Frequency::Weekly => self.start.checked_add_days(Days::new(u64::from(step) * 7)),
+            Frequency::Monthly => self.in_month(step),
+            Frequency::Yearly => self.in_month(step.checked_mul(12)?),
+        }
+    }
+    /// Число месяца берётся из начала; если такого числа нет, берётся последнее
+    fn in_month(&self, months: u32) -> Option<NaiveDate> {
+        let date = self.start.checked_add_months(Months::new(months))?;
+        match self.nth_weekday {
+            Some(nth) => nth.in_month(date.year(), date.month()),
+            None => Some(date),
+        }
+    }
+    /// Первое повторение не раньше `date`, если расписание к тому времени не закончилось
+    pub fn first_from(&self, date: NaiveDate) -> Option<NaiveDate> {
+        let date = date.max(self.start);
+        (0..)
+            .map_while(|k| self.occurrence(k))
+            .find(|d| *d >= date)
+            .filter(|d| self.end.is_none_or(|end| *d <= end))
+    }
+    pub fn next_after(&self, date: NaiveDate) -> Option<NaiveDate> {
+        self.first_from(date.succ_opt()?)
+    }
+    /// Даты повторений с `next_due` по `today` включительно
+    pub fn due(&self, next_due: Option<NaiveDate>, today: NaiveDate) -> Vec<NaiveDate> {
+        std::iter::successors(next_due, |d| self.next_after(*d))
+            .take_while(|d| *d <= today && self.end.is_none_or(|end| *d <= end))
+            .take(MAX_CATCH_UP)
+            .collect()
+    }
+    pub fn describe(&self) -> String {
+        let mut text = if self.every > 1 {
+            format!("Раз в {} {}", self.every, self.frequency.unit())
+        } else {
+            <&str>::from(self.frequency).to_owned()
+        };
+        let day = match self.nth_weekday {
+            Some(NthWeekday { n, weekday }) if n > 0 => {
+                format!("{n}-я неделя, {}", weekday_name(weekday))
+            }
+            Some(NthWeekday { weekday, .. }) => {
+                format!("последняя неделя, {}", weekday_name(weekday))
+            }
+            None => format!("{}-е число", self.start.day()),
+        };
+        let details = match self.frequency {
+            Frequency::Daily => None,
+            Frequency::Weekly => Some(weekday_name(self.start.weekday()).to_owned()),
+            Frequency::Monthly => Some(day),
+            Frequency::Yearly => {
+                let month = MONTHS
+                    .get(self.start.month0() as usize)
+                    .copied()
+                    .unwrap_or_default();
+                Some(format!("{month}, {day}"))
+            }
+        };
+        if let Some(details) = details {
+            text.push_str(&format!(", {details}"));
+        }
+        if let Some(end) = self.end {
+            text.push_str(&format!(", до {}", end.format("%d.%m.%Y")));
+        }
+        text
+    }
+}
+
+pub const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+pub fn weekday_name(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "понедельник",
+        Weekday::Tue => "вторник",
+        Weekday::Wed => "среда",
+        Weekday::Thu => "четверг",
+        Weekday::Fri => "пятница",
+        Weekday::Sat => "суббота",
+        Weekday::Sun => "воскресенье",
+    }
+}
+const MONTHS: [&str; 12] = [
+    "январь",
+    "февраль",
+    "март",
+    "апрель",
+    "май",
+    "июнь",
+    "июль",
+    "август",
+    "сентябрь",
+    "октябрь",
+    "ноябрь",
+    "декабрь",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).expect("дата в тесте должна существовать")
+    }
+    fn schedule(frequency: Frequency, every: i32, start: NaiveDate) -> Schedule {
+        Schedule {
+            frequency,
+            every,
+            ..Schedule::new(start)
+        }
+    }
+
+    #[test]
+    fn month_end_start_falls_back_to_last_day() {
+        let start = date(2026, 1, 31);
+        let schedule = schedule(Frequency::Monthly, 1, start);
+        assert_eq!(
+            schedule.due(Some(start), date(2026, 5, 31)),
+            [
+                date(2026, 1, 31),
+                date(2026, 2, 28),
+                date(2026, 3, 31),
+                date(2026, 4, 30),
+                date(2026, 5, 31),
+            ],
+            "короткий месяц не должен сдвигать следующие повторения"
+        );
+    }
+
+    #[test]
+    fn leap_day_repeats_on_last_day_of_february() {
+        let schedule = schedule(Frequency::Yearly, 1, date(2024, 2, 29));
+        assert_eq!(
+            schedule.next_after(date(2024, 2, 29)),
+            Some(date(2025, 2, 28)),
+            "в невисокосный год — 28 февраля"
+        );
+        assert_eq!(
+            schedule.next_after(date(2027, 3, 1)),
+            Some(date(2028, 2, 29)),
+            "в високосный год снова 29 февраля"
+        );
+    }
+
+    #[test]
+    fn nth_weekday_in_month() {
+        let second_tuesday = NthWeekday {
+            n: 2,
+            weekday: Weekday::Tue,
+        };
+        assert_eq!(
+            second_tuesday.in_month(2026, 1),
+            Some(date(2026, 1, 13)),
+            "второй вторник января 2026"
+        );
+        let fifth = NthWeekday {
+            n: 5,
+            weekday: Weekday::Tue,
+        };
+        assert_eq!(fifth.in_month(2026, 1), None, "пятого номера не бывает");
+        let last_friday = NthWeekday {
+            n: -1,
+            weekday: Weekday::Fri,
+        };
+        for (month, day) in [(1, 30), (2, 27), (5, 29), (7, 31)] {
+            assert_eq!(
+                last_friday.in_month(2026, month),
+                Some(date(2026, month, day)),
+                "последняя пятница месяца {month}"
+            );
+        }
+    }
+
+    #[test]
+    fn last_weekday_schedule() {
+        let schedule = Schedule {
+            nth_weekday: Some(NthWeekday {
+                n: -1,
+                weekday: Weekday::Fri,
+            }),
+            ..Schedule::new(date(2026, 1, 1))
+        };
+        let first = schedule.first_from(date(2026, 1, 1));
+        assert_eq!(first, Some(date(2026, 1, 30)), "первое повторение");
+        assert_eq!(
+            schedule.due(first, date(2026, 3, 1)),
+            [date(2026, 1, 30), date(2026, 2, 27)],
+            "повторения до начала марта"
+        );
+    }
+
+    #[test]
+    fn every_skips_periods() {
+        let start = date(2026, 1, 5);
+        let weekly = schedule(Frequency::Weekly, 2, start);
+        assert_eq!(
+            weekly.due(Some(start), date(2026, 2, 2)),
+            [date(2026, 1, 5), date(2026, 1, 19), date(2026, 2, 2)],
+            "раз в две недели"
+        );
+        let quarterly = schedule(Frequency::Monthly, 3, date(2026, 1, 15));
+        assert_eq!(
+            quarterly.first_from(date(2026, 1, 16)),
+            Some(date(2026, 4, 15)),
+            "раз в три месяца"
+        );
+        assert_eq!(
+            quarterly.first_from(date(2026, 1, 15)),
+            Some(date(2026, 1, 15)),
+            "сама дата начала тоже повторение"
+        );
+    }
+
+    #[test]
+    fn end_is_inclusive() {
+        let schedule = Schedule {
+            end: Some(date(2026, 1, 3)),
+            ..schedule(Frequency::Daily, 1, date(2026, 1, 1))
+        };
+        assert_eq!(
+            schedule.due(Some(date(2026, 1, 1)), date(2026, 1, 10)),
+            [date(2026, 1, 1), date(2026, 1, 2), date(2026, 1, 3)],
+            "повторения по дату окончания включительно"
+        );
+        assert_eq!(
+            schedule.first_from(date(2026, 1, 3)),
+            Some(date(2026, 1, 3)),
+            "дата окончания ещё входит"
+        );
+        assert_eq!(
+            schedule.first_from(date(2026, 1, 4)),
+            None,
+            "после окончания повторений нет"
+        );
+        assert_eq!(
+            schedule.first_from(date(2025, 12, 1)),
+            Some(date(2026, 1, 1)),
+            "до начала — первое повторение"
+        );
+    }
+
+    #[test]
+    fn catch_up_is_limited() {
+        let start = date(2020, 1, 1);
+        let schedule = schedule(Frequency::Daily, 1, start);
+        let due = schedule.due(Some(start), date(2026, 1, 1));
+        assert_eq!(due.len(), MAX_CATCH_UP, "за раз не больше MAX_CATCH_UP");
+        assert_eq!(
+            due.last().copied(),
+            start.checked_add_days(Days::new(MAX_CATCH_UP as u64 - 1)),
+            "создаются самые ранние пропущенные"
+        );
+        assert!(
+            schedule.due(None, date(2026, 1, 1)).is_empty(),
+            "законченный шаблон ничего не создаёт"
+        );
+    }
+}
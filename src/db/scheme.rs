@@ -1,21 +1,63 @@
-use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
 use tokio_postgres::{Error, Row};
 
-#[derive(Clone, PartialEq, Default)]
+use super::recurring::{Frequency, NthWeekday, Schedule, WEEKDAYS};
+
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct OperationsRow {
     pub article_id: Option<i32>,
     pub balance_id: Option<i32>,
     pub debit: Option<i32>,
     pub credit: Option<i32>,
     pub create_date: Option<chrono::NaiveDateTime>,
+    pub description: Option<String>,
+    /// Копии, сделанные до появления меток, их не содержат
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-#[derive(Clone, PartialEq, Default)]
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct ArticlesRow {
     pub name: Option<String>,
+    /// Статья, в которую вложена эта. Копии, сделанные до появления
+    /// вложенных статей, его не содержат
+    #[serde(default)]
+    pub parent_id: Option<i32>,
+    /// Цвет на графиках в виде `#rrggbb`
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Значок перед названием, обычно эмодзи
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Статья больше не используется: её нет в списках для новых операций,
+    /// но старые операции и отчёты по ней остаются
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// Сколько операций ссылается на статью и на какую сумму
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct ArticleUsage {
+    pub operations: i64,
+    /// Из них в сформированных балансах
+    pub locked: i64,
+    pub debit: i64,
+    pub credit: i64,
 }
 
-#[derive(Clone, PartialEq, Default)]
+/// План и факт расходов статьи за месяц
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct BudgetLine {
+    /// `None` — бюджет на месяц не задан
+    pub planned: Option<i32>,
+    /// Расходы статьи вместе с вложенными
+    pub actual: i64,
+}
+
+#[derive(Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct BalanceRow {
     pub debit: Option<i32>,
     pub credit: Option<i32>,
@@ -23,8 +65,47 @@ pub struct BalanceRow {
     pub create_date: Option<chrono::NaiveDateTime>,
 }
 
-#[derive(Clone, PartialEq)]
+/// Строка разбивки операции по статьям. Строки одной операции в сумме дают её саму.
+#[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct SplitRow {
+    pub article_id: Option<i32>,
+    pub debit: i32,
+    pub credit: i32,
+}
+
+/// Строки разбивки по id операции
+pub type Splits = BTreeMap<i32, Vec<SplitRow>>;
+
+/// id добавленной строки и все строки таблицы вместе с ней
+pub type Inserted<T> = (i32, BTreeMap<i32, T>);
+
+/// Сведения о прикреплённом файле. Само содержимое загружается отдельно.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AttachmentRow {
+    pub operation_id: i32,
+    pub file_name: String,
+    pub mime: String,
+    pub size: i32,
+    pub create_date: NaiveDateTime,
+}
+
+/// Шаблон повторяющейся операции
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecurringRow {
+    pub article_id: Option<i32>,
+    pub debit: Option<i32>,
+    pub credit: Option<i32>,
+    pub schedule: Schedule,
+    /// `None` — повторения закончились
+    pub next_due: Option<NaiveDate>,
+    /// Спрашивать перед созданием операций
+    pub confirm: bool,
+}
+
+#[derive(Clone, PartialEq, Serialize)]
 pub struct PercentsBar {
+    /// `None` при группировке по меткам
+    pub article_id: Option<i32>,
     pub article_name: String,
     pub debit: f64,
     pub credit: f64,
@@ -35,10 +116,56 @@ pub struct ProfitPoint(pub egui_plot::PlotPoint);
 
 #[derive(Clone, PartialEq)]
 pub struct DynamicsPoint {
+    /// Линия, к которой относится точка: статья или метка, смотря по группировке
+    pub article_id: Option<i32>,
+    pub tag: Option<String>,
     pub debit: egui_plot::PlotPoint,
     pub credit: egui_plot::PlotPoint,
 }
 
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct Totals {
+    pub debit: i64,
+    pub credit: i64,
+    pub count: i64,
+    pub first: Option<NaiveDateTime>,
+    pub last: Option<NaiveDateTime>,
+}
+
+/// Строка выписки баланса: операция или её часть, приходящаяся на одну статью
+#[derive(Clone, PartialEq, Eq)]
+pub struct StatementLine {
+    pub operation_id: i32,
+    pub article_id: Option<i32>,
+    pub debit: Option<i32>,
+    pub credit: Option<i32>,
+    pub create_date: Option<NaiveDateTime>,
+    pub description: Option<String>,
+}
+
+/// Что войдёт в новый баланс и сколько свободных операций останется вне его
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct BalancePreview {
+    pub included: Totals,
+    pub left_out: i64,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct ArticleTotals {
+    pub article_name: Option<String>,
+    pub debit: i64,
+    pub credit: i64,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct TopOperation {
+    pub id: i32,
+    pub article_name: Option<String>,
+    pub debit: Option<i32>,
+    pub credit: Option<i32>,
+    pub create_date: Option<NaiveDateTime>,
+}
+
 impl OperationsRow {
     pub fn new(row: Row) -> Result<(i32, Self), Error> {
         Ok((
@@ -49,6 +176,8 @@ impl OperationsRow {
                 debit: row.try_get("debit")?,
                 credit: row.try_get("credit")?,
                 create_date: row.try_get("create_date")?,
+                description: row.try_get("description")?,
+                tags: row.try_get("tags")?,
             },
         ))
     }
@@ -59,11 +188,48 @@ impl ArticlesRow {
             row.try_get("id")?,
             Self {
                 name: row.try_get("name")?,
+                parent_id: row.try_get("parent_id")?,
+                color: row.try_get("color")?,
+                icon: row.try_get("icon")?,
+                archived: row.try_get("archived")?,
             },
         ))
     }
 }
 
+impl ArticleUsage {
+    pub fn new(row: Row) -> Result<(i32, Self), Error> {
+        Ok((
+            row.try_get("id")?,
+            Self {
+                operations: row.try_get("operations")?,
+                locked: row.try_get("locked")?,
+                debit: row.try_get("debit")?,
+                credit: row.try_get("credit")?,
+            },
+        ))
+    }
+    /// Оборот: доход и расход вместе
+    pub fn turnover(&self) -> i64 {
+        self.debit + self.credit
+    }
+}
+impl BudgetLine {
+    pub fn new(row: Row) -> Result<(i32, Self), Error> {
+        Ok((
+            row.try_get("article_id")?,
+            Self {
+                planned: row.try_get("planned")?,
+                actual: row.try_get("actual")?,
+            },
+        ))
+    }
+    /// Какая доля бюджета потрачена, в процентах
+    pub fn spent_percent(&self) -> Option<f64> {
+        let planned = self.planned.filter(|p| *p > 0)?;
+        Some(100.0 * self.actual as f64 / f64::from(planned))
+    }
+}
 impl BalanceRow {
     pub fn new(row: Row) -> Result<(i32, Self), Error> {
         Ok((
@@ -78,12 +244,86 @@ impl BalanceRow {
     }
 }
 
+impl SplitRow {
+    /// Вместе с id операции, к которой относится строка
+    pub fn new(row: &Row) -> Result<(i32, Self), Error> {
+        Ok((
+            row.try_get("operation_id")?,
+            Self {
+                article_id: row.try_get("article_id")?,
+                debit: row.try_get("debit")?,
+                credit: row.try_get("credit")?,
+            },
+        ))
+    }
+}
+
+impl AttachmentRow {
+    pub fn new(row: Row) -> Result<(i32, Self), Error> {
+        Ok((
+            row.try_get("id")?,
+            Self {
+                operation_id: row.try_get("operation_id")?,
+                file_name: row.try_get("file_name")?,
+                mime: row.try_get("mime")?,
+                size: row.try_get("size")?,
+                create_date: row.try_get("create_date")?,
+            },
+        ))
+    }
+}
+
+impl RecurringRow {
+    pub fn new(row: Row) -> Result<(i32, Self), Error> {
+        let frequency: String = row.try_get("frequency")?;
+        let nth: Option<i32> = row.try_get("nth")?;
+        let weekday: Option<i32> = row.try_get("weekday")?;
+        let nth_weekday = nth.zip(weekday).and_then(|(n, weekday)| {
+            Some(NthWeekday {
+                n,
+                weekday: *WEEKDAYS.get(usize::try_from(weekday).ok()?)?,
+            })
+        });
+        Ok((
+            row.try_get("id")?,
+            Self {
+                article_id: row.try_get("article_id")?,
+                debit: row.try_get("debit")?,
+                credit: row.try_get("credit")?,
+                schedule: Schedule {
+                    // Другие значения не пропустит ограничение в базе
+                    frequency: Frequency::from_code(&frequency).unwrap_or(Frequency::Monthly),
+                    every: row.try_get("every")?,
+                    nth_weekday,
+                    start: row.try_get("start_date")?,
+                    end: row.try_get("end_date")?,
+                },
+                next_due: row.try_get("next_due")?,
+                confirm: row.try_get("confirm")?,
+            },
+        ))
+    }
+    /// Операция, которую шаблон создаёт на эту дату
+    pub fn operation(&self, date: NaiveDate) -> OperationsRow {
+        OperationsRow {
+            article_id: self.article_id,
+            balance_id: None,
+            debit: self.debit,
+            credit: self.credit,
+            create_date: Some(date.into()),
+            ..Default::default()
+        }
+    }
+}
+
 impl PercentsBar {
     pub fn new(row: Row) -> Result<Self, Error> {
+        let article_id: Option<i32> = row.try_get("article_id")?;
         let article_name: String = row.try_get("article_name")?;
         let debit: Option<f64> = row.try_get("debit")?;
         let credit: Option<f64> = row.try_get("credit")?;
         Ok(Self {
+            article_id,
             article_name,
             debit: debit.unwrap_or_default(),
             credit: credit.unwrap_or_default(),
@@ -106,8 +346,66 @@ impl DynamicsPoint {
         let debit: i64 = row.try_get("debit")?;
         let credit: i64 = row.try_get("credit")?;
         Ok(Self {
+            article_id: row.try_get("article_id")?,
+            tag: row.try_get("tag")?,
             debit: (egui_plot::PlotPoint::new(date, debit as f64)),
             credit: (egui_plot::PlotPoint::new(date, credit as f64)),
         })
     }
 }
+
+impl Totals {
+    pub fn new(row: Row) -> Result<Self, Error> {
+        Ok(Self {
+            debit: row.try_get("debit")?,
+            credit: row.try_get("credit")?,
+            count: row.try_get("count")?,
+            first: row.try_get("first")?,
+            last: row.try_get("last")?,
+        })
+    }
+}
+
+impl BalancePreview {
+    pub fn new(row: Row) -> Result<Self, Error> {
+        Ok(Self {
+            left_out: row.try_get("left_out")?,
+            included: Totals::new(row)?,
+        })
+    }
+}
+
+impl StatementLine {
+    pub fn new(row: Row) -> Result<Self, Error> {
+        Ok(Self {
+            operation_id: row.try_get("id")?,
+            article_id: row.try_get("article_id")?,
+            debit: row.try_get("debit")?,
+            credit: row.try_get("credit")?,
+            create_date: row.try_get("create_date")?,
+            description: row.try_get("description")?,
+        })
+    }
+}
+
+impl ArticleTotals {
+    pub fn new(row: Row) -> Result<Self, Error> {
+        Ok(Self {
+            article_name: row.try_get("article_name")?,
+            debit: row.try_get("debit")?,
+            credit: row.try_get("credit")?,
+        })
+    }
+}
+
+impl TopOperation {
+    pub fn new(row: Row) -> Result<Self, Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            article_name: row.try_get("article_name")?,
+            debit: row.try_get("debit")?,
+            credit: row.try_get("credit")?,
+            create_date: row.try_get("create_date")?,
+        })
+    }
+}
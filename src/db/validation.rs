@@ -0,0 +1,144 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::scheme::OperationsRow;
+
+/// Правила проверки операций. Одни и те же правила проверяются
+/// в строке редактирования и перед записью в базу.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    pub require_article: bool,
+    pub require_date: bool,
+    pub allow_negative: bool,
+    /// Доход и расход одновременно в одной операции
+    pub allow_both: bool,
+    /// Операция без суммы
+    pub allow_zero: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Field {
+    Article,
+    Debit,
+    Credit,
+    CreateDate,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Violation {
+    pub field: Field,
+    pub message: &'static str,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Db(tokio_postgres::Error),
+    Invalid(Vec<Violation>),
+    /// Операция входит в сформированный баланс
+    Locked {
+        operation: i32,
+        balance: i32,
+    },
+    /// Сумма операции не совпадает с суммой строк её разбивки, `debit` и `credit` — по строкам
+    Split {
+        operation: i32,
+        debit: i64,
+        credit: i64,
+    },
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            require_article: true,
+            require_date: true,
+            allow_negative: false,
+            allow_both: false,
+            allow_zero: false,
+        }
+    }
+}
+
+impl Rules {
+    /// Ничего не проверяет. Для возврата строки к состоянию, которое уже было в базе.
+    pub const NONE: Self = Self {
+        require_article: false,
+        require_date: false,
+        allow_negative: true,
+        allow_both: true,
+        allow_zero: true,
+    };
+    pub fn violations(&self, row: &OperationsRow) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let mut violate = |field, message| violations.push(Violation { field, message });
+        if self.require_article && row.article_id.is_none() {
+            violate(Field::Article, "Не выбрана статья");
+        }
+        if self.require_date && row.create_date.is_none() {
+            violate(Field::CreateDate, "Не указана дата");
+        }
+        let (debit, credit) = (row.debit.unwrap_or(0), row.credit.unwrap_or(0));
+        if !self.allow_negative {
+            if debit < 0 {
+                violate(Field::Debit, "Отрицательный доход");
+            }
+            if credit < 0 {
+                violate(Field::Credit, "Отрицательный расход");
+            }
+        }
+        if !self.allow_both && debit != 0 && credit != 0 {
+            violate(Field::Debit, "Указаны и доход, и расход");
+            violate(Field::Credit, "Указаны и доход, и расход");
+        }
+        if !self.allow_zero && debit == 0 && credit == 0 {
+            violate(Field::Debit, "Не указана сумма");
+            violate(Field::Credit, "Не указана сумма");
+        }
+        violations
+    }
+    pub fn check(&self, row: &OperationsRow) -> Result<(), Error> {
+        let violations = self.violations(row);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Invalid(violations))
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Db(err) => write!(f, "Ошибка базы: {err}"),
+            Self::Invalid(violations) => {
+                f.write_str("Операция не прошла проверку:")?;
+                let mut messages: Vec<&str> = violations.iter().map(|v| v.message).collect();
+                messages.dedup();
+                write!(f, " {}", messages.join("; "))
+            }
+            Self::Locked { operation, balance } => write!(
+                f,
+                "Операция №{operation} входит в баланс №{balance}. \
+                Расформируйте баланс или измените операцию с пересчётом баланса"
+            ),
+            Self::Split {
+                operation,
+                debit,
+                credit,
+            } => write!(
+                f,
+                "Сумма операции №{operation} не совпадает с разбивкой по статьям \
+                (по строкам разбивки: доход {debit}, расход {credit}). Сначала измените разбивку"
+            ),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::Db(err)
+    }
+}
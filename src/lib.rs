@@ -1,6 +1,10 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod chart;
+pub mod cli;
 mod db;
 mod promise_lite;
+mod report;
+mod statement;
 pub use app::App;
@@ -1,16 +1,25 @@
 #![warn(clippy::all, rust_2018_idioms)]
+// Подсистема задаётся для всего бинарника, а не для режима запуска: в Windows
+// релизная сборка не получает консоли и в консольном режиме. Вывод команд там
+// нужно перенаправлять в файл, а код возврата ждать через `start /wait`.
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
-use std::sync::Arc;
+use std::{process::ExitCode, sync::Arc};
 
 use anyhow;
 use tokio;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<ExitCode> {
     // Отправляем логи в stderr
     // `RUST_LOG=debug`
     env_logger::init();
 
+    // С аргументами работаем без окна, в консоли
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return Ok(dbgui::cli::run(args));
+    }
+
     // Разворачиваем асинхронный рантайм, для работы с базой.
     // Нам хватит однопоточной версии.
     let rt = tokio::runtime::Builder::new_current_thread()
@@ -51,7 +60,7 @@ fn main() -> anyhow::Result<()> {
     ) {
         anyhow::bail!("Eframe failed: {}", err)
     }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
 struct ExitGuard(Arc<tokio::sync::Notify>);
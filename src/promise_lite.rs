@@ -1,3 +1,8 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
 use tokio::task::{JoinError, JoinHandle};
 
 pub struct PromiseLite<T>(JoinHandle<T>);
@@ -15,3 +20,10 @@ where
         tokio::runtime::Handle::current().block_on(self.0)
     }
 }
+// Позволяет дождаться результата из асинхронного кода, например в консольном режиме
+impl<T> Future for PromiseLite<T> {
+    type Output = Result<T, JoinError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
@@ -0,0 +1,304 @@
+use std::{fmt, path::Path};
+
+use chrono::{Datelike as _, Days, Local, Months, NaiveDate, NaiveDateTime};
+use egui::Color32;
+use egui_plot::PlotPoint;
+
+use crate::{
+    chart::{Axis, Chart, Kind, Series, escape},
+    db::scheme::{ArticleTotals, ProfitPoint, TopOperation, Totals},
+};
+
+/// Отчётный период: либо интервал дат, либо сформированный баланс.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Dates { start: NaiveDate, end: NaiveDate },
+    Balance(i32),
+}
+
+pub struct PeriodData {
+    pub totals: Totals,
+    pub articles: Vec<ArticleTotals>,
+    pub profit: Vec<ProfitPoint>,
+    pub top: Vec<TopOperation>,
+}
+
+pub struct Report {
+    pub period: Period,
+    pub current: PeriodData,
+    pub previous: Option<(Period, PeriodData)>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Db(tokio_postgres::Error),
+    Io(std::io::Error),
+}
+
+/// Сколько крупнейших операций попадает в отчёт
+pub const TOP_OPERATIONS: i64 = 10;
+
+impl Period {
+    /// Параметры фильтра операций: `balance_id`, начало и конец (не включительно)
+    pub fn params(&self) -> (Option<i32>, NaiveDateTime, NaiveDateTime) {
+        match *self {
+            Self::Dates { start, end } => (
+                None,
+                start.into(),
+                end.checked_add_days(Days::new(1)).unwrap_or(end).into(),
+            ),
+            Self::Balance(id) => (Some(id), NaiveDateTime::default(), NaiveDateTime::default()),
+        }
+    }
+    /// Такой же по длине интервал прямо перед текущим. Целые месяцы
+    /// сравниваются с предыдущими месяцами. Для баланса предыдущий ищется в базе.
+    pub fn previous_dates(&self) -> Option<Self> {
+        let Self::Dates { start, end } = *self else {
+            return None;
+        };
+        let next = end.checked_add_days(Days::new(1))?;
+        if start.day() == 1 && next.day() == 1 {
+            let months = Months::new(
+                (next.year() * 12 + next.month0().cast_signed()
+                    - start.year() * 12
+                    - start.month0().cast_signed())
+                .cast_unsigned(),
+            );
+            return Some(Self::Dates {
+                start: start.checked_sub_months(months)?,
+                end: start.checked_sub_days(Days::new(1))?,
+            });
+        }
+        let length = (end - start).num_days().max(0).cast_unsigned();
+        let end = start.checked_sub_days(Days::new(1))?;
+        let start = end.checked_sub_days(Days::new(length))?;
+        Some(Self::Dates { start, end })
+    }
+    fn title(&self, totals: &Totals) -> String {
+        match self {
+            Self::Dates { start, end } => {
+                format!("{} — {}", start.format("%d.%m.%Y"), end.format("%d.%m.%Y"))
+            }
+            Self::Balance(id) => {
+                let dates = match (totals.first, totals.last) {
+                    (Some(first), Some(last)) => format!(
+                        " ({} — {})",
+                        first.format("%d.%m.%Y"),
+                        last.format("%d.%m.%Y")
+                    ),
+                    _ => String::new(),
+                };
+                format!("баланс №{id}{dates}")
+            }
+        }
+    }
+}
+
+impl Report {
+    /// Печатный HTML документ. Для PDF достаточно «Печать → Сохранить как PDF» в браузере.
+    pub fn to_html(&self) -> String {
+        let title = format!("Отчёт: {}", self.period.title(&self.current.totals));
+        let mut html = String::new();
+        html.push_str(&format!(
+            "<!DOCTYPE html>\n<html lang=\"ru\">\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+            title = escape(&title),
+        ));
+        self.write_totals(&mut html);
+        self.write_articles(&mut html);
+        self.write_profit(&mut html);
+        self.write_top(&mut html);
+        html.push_str(&format!(
+            "<footer>Сформировано {}</footer>\n</body>\n</html>\n",
+            Local::now().format("%d.%m.%Y %H:%M")
+        ));
+        html
+    }
+    pub async fn save(&self, path: &Path) -> Result<(), Error> {
+        tokio::fs::write(path, self.to_html()).await?;
+        Ok(())
+    }
+    fn write_totals(&self, html: &mut String) {
+        let current = &self.current.totals;
+        html.push_str("<h2>Итоги</h2>\n<table>\n<tr><th></th><th>Текущий период</th>");
+        if let Some((period, data)) = &self.previous {
+            html.push_str(&format!(
+                "<th>Предыдущий период<br><small>{}</small></th><th>Изменение</th>",
+                escape(&period.title(&data.totals))
+            ));
+        }
+        html.push_str("</tr>\n");
+        let values = |t: &Totals| [t.debit, t.credit, t.debit - t.credit, t.count];
+        let previous = self.previous.as_ref().map(|(_, data)| values(&data.totals));
+        let labels = ["Доходы", "Расходы", "Итог", "Операций"];
+        for (n, (label, value)) in labels.into_iter().zip(values(current)).enumerate() {
+            html.push_str(&format!("<tr><td>{label}</td><td>{value}</td>"));
+            if let Some(previous) = previous.and_then(|p| p.get(n).copied()) {
+                html.push_str(&format!(
+                    "<td>{previous}</td><td>{}</td>",
+                    change(value, previous)
+                ));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+    fn write_articles(&self, html: &mut String) {
+        let totals = &self.current.totals;
+        html.push_str(
+            "<h2>По статьям</h2>\n<table>\n<tr><th>Статья</th><th>Доходы</th><th>% доходов</th>\
+            <th>Расходы</th><th>% расходов</th>",
+        );
+        if self.previous.is_some() {
+            html.push_str("<th>Расходы ранее</th><th>Изменение</th>");
+        }
+        html.push_str("</tr>\n");
+        for article in &self.current.articles {
+            let name = article.article_name.as_deref().unwrap_or("[без статьи]");
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+                escape(name),
+                article.debit,
+                percent(article.debit, totals.debit),
+                article.credit,
+                percent(article.credit, totals.credit),
+            ));
+            if let Some((_, data)) = &self.previous {
+                let previous = data
+                    .articles
+                    .iter()
+                    .find(|a| a.article_name == article.article_name)
+                    .map(|a| a.credit)
+                    .unwrap_or_default();
+                html.push_str(&format!(
+                    "<td>{previous}</td><td>{}</td>",
+                    change(article.credit, previous)
+                ));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+        let names = self
+            .current
+            .articles
+            .iter()
+            .map(|a| a.article_name.clone().unwrap_or_default())
+            .collect();
+        let bars = |value: fn(&ArticleTotals) -> i64, total: i64| {
+            self.current
+                .articles
+                .iter()
+                .enumerate()
+                .map(|(i, a)| {
+                    let share = if total == 0 {
+                        0.0
+                    } else {
+                        100.0 * value(a) as f64 / total as f64
+                    };
+                    PlotPoint::new(i as f64, share)
+                })
+                .collect()
+        };
+        let chart = Chart::new("Проценты по статьям", Axis::Categories(names))
+            .x_label("Статьи")
+            .y_label("Проценты")
+            .y_suffix("%")
+            .series(Series {
+                name: "Доходы".into(),
+                color: Color32::DARK_GREEN,
+                kind: Kind::Bars,
+                points: bars(|a| a.debit, totals.debit),
+            })
+            .series(Series {
+                name: "Расходы".into(),
+                color: Color32::DARK_RED,
+                kind: Kind::Bars,
+                points: bars(|a| a.credit, totals.credit),
+            });
+        html.push_str(&chart.to_svg(900, 360));
+    }
+    fn write_profit(&self, html: &mut String) {
+        html.push_str("<h2>Прибыль</h2>\n");
+        if self.current.profit.is_empty() {
+            html.push_str("<p>Нет операций за период.</p>\n");
+            return;
+        }
+        let chart = Chart::new("Прибыль от времени", Axis::Time)
+            .x_label("Время")
+            .y_label("Деньги")
+            .series(Series {
+                name: "Прибыль".into(),
+                color: Color32::RED,
+                kind: Kind::LineWithPoints,
+                points: self.current.profit.iter().map(|p| p.0).collect(),
+            });
+        html.push_str(&chart.to_svg(900, 360));
+    }
+    fn write_top(&self, html: &mut String) {
+        html.push_str(
+            "<h2>Крупнейшие операции</h2>\n<table>\n<tr><th>id</th><th>Дата</th><th>Статья</th>\
+            <th>Доход</th><th>Расход</th></tr>\n",
+        );
+        for operation in &self.current.top {
+            html.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                operation.id,
+                operation
+                    .create_date
+                    .map(|d| d.format("%d.%m.%Y").to_string())
+                    .unwrap_or_default(),
+                escape(operation.article_name.as_deref().unwrap_or_default()),
+                operation.debit.unwrap_or_default(),
+                operation.credit.unwrap_or_default(),
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Db(err) => write!(f, "Ошибка базы: {err}"),
+            Self::Io(err) => write!(f, "Ошибка файла: {err}"),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Self::Db(err)
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+fn percent(part: i64, total: i64) -> String {
+    if total == 0 {
+        return "—".into();
+    }
+    format!("{:.1}%", 100.0 * part as f64 / total as f64)
+}
+
+fn change(current: i64, previous: i64) -> String {
+    let diff = current - previous;
+    if previous == 0 {
+        return format!("{diff:+}");
+    }
+    format!(
+        "{diff:+} ({:+.1}%)",
+        100.0 * diff as f64 / previous.abs() as f64
+    )
+}
+
+pub const STYLE: &str = "body { font-family: sans-serif; margin: 2em; } \
+    table { border-collapse: collapse; margin-bottom: 1em; } \
+    th, td { border: 1px solid #999; padding: 4px 8px; text-align: right; } \
+    th:first-child, td:first-child { text-align: left; } \
+    footer { color: #666; margin-top: 2em; } \
+    @page { size: A4; margin: 15mm; } \
+    @media print { h2 { break-after: avoid; } svg { max-width: 100%; height: auto; } }";
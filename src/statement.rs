@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{Local, NaiveDateTime};
+
+use crate::{
+    chart::escape,
+    db::{
+        hierarchy,
+        scheme::{ArticlesRow, BalanceRow, StatementLine},
+    },
+    report::STYLE,
+};
+
+/// Выписка баланса: его операции, сгруппированные по статьям, с промежуточными итогами.
+#[derive(Clone)]
+pub struct Statement {
+    pub balance_id: i32,
+    pub balance: BalanceRow,
+    pub groups: Vec<Group>,
+}
+
+/// Операции одной статьи. У операций без статьи `article_id` пустой.
+#[derive(Clone)]
+pub struct Group {
+    pub article_id: Option<i32>,
+    pub name: String,
+    pub lines: Vec<StatementLine>,
+    pub debit: i64,
+    pub credit: i64,
+}
+
+impl Statement {
+    /// Статьи идут по полному пути, операции без статьи — в конце
+    pub fn new(
+        balance_id: i32,
+        balance: BalanceRow,
+        lines: Vec<StatementLine>,
+        articles: &BTreeMap<i32, ArticlesRow>,
+    ) -> Self {
+        let mut by_article: BTreeMap<Option<i32>, Vec<StatementLine>> = BTreeMap::new();
+        for line in lines {
+            by_article.entry(line.article_id).or_default().push(line);
+        }
+        let mut groups: Vec<_> = by_article
+            .into_iter()
+            .map(|(article_id, lines)| Group {
+                article_id,
+                name: article_id
+                    .map_or_else(|| "[без статьи]".into(), |id| hierarchy::path(id, articles)),
+                debit: lines
+                    .iter()
+                    .map(|l| i64::from(l.debit.unwrap_or_default()))
+                    .sum(),
+                credit: lines
+                    .iter()
+                    .map(|l| i64::from(l.credit.unwrap_or_default()))
+                    .sum(),
+                lines,
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            (a.article_id.is_none(), &a.name).cmp(&(b.article_id.is_none(), &b.name))
+        });
+        Self {
+            balance_id,
+            balance,
+            groups,
+        }
+    }
+    pub fn debit(&self) -> i64 {
+        self.groups.iter().map(|g| g.debit).sum()
+    }
+    pub fn credit(&self) -> i64 {
+        self.groups.iter().map(|g| g.credit).sum()
+    }
+    /// Число операций. Разбитая операция считается один раз.
+    pub fn count(&self) -> usize {
+        let mut ids: Vec<_> = self
+            .groups
+            .iter()
+            .flat_map(|g| g.lines.iter().map(|l| l.operation_id))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.len()
+    }
+    /// Печатный HTML документ, как и отчёт
+    pub fn to_html(&self) -> String {
+        let title = format!(
+            "Выписка по балансу №{} от {}",
+            self.balance_id,
+            date(self.balance.create_date)
+        );
+        let mut html = String::new();
+        html.push_str(&format!(
+            "<!DOCTYPE html>\n<html lang=\"ru\">\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+            title = escape(&title),
+        ));
+        html.push_str(
+            "<table>\n<tr><th>id</th><th>Дата</th><th>Описание</th>\
+            <th>Доход</th><th>Расход</th></tr>\n",
+        );
+        for group in &self.groups {
+            html.push_str(&format!(
+                "<tr><th colspan=\"5\">{}</th></tr>\n",
+                escape(&group.name)
+            ));
+            for line in &group.lines {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    line.operation_id,
+                    date(line.create_date),
+                    escape(line.description.as_deref().unwrap_or_default()),
+                    line.debit.unwrap_or_default(),
+                    line.credit.unwrap_or_default(),
+                ));
+            }
+            html.push_str(&format!(
+                "<tr><td colspan=\"3\"><b>Итого по статье</b></td><td><b>{}</b></td><td><b>{}</b></td></tr>\n",
+                group.debit, group.credit
+            ));
+        }
+        html.push_str(&format!(
+            "<tr><td colspan=\"3\"><b>Всего, операций: {}</b></td><td><b>{}</b></td><td><b>{}</b></td></tr>\n\
+            </table>\n<p>Итог: {}</p>\n",
+            self.count(),
+            self.debit(),
+            self.credit(),
+            self.debit() - self.credit()
+        ));
+        html.push_str(&format!(
+            "<footer>Сформировано {}</footer>\n</body>\n</html>\n",
+            Local::now().format("%d.%m.%Y %H:%M")
+        ));
+        html
+    }
+    pub async fn save(&self, path: &Path) -> Result<(), std::io::Error> {
+        tokio::fs::write(path, self.to_html()).await
+    }
+}
+
+fn date(date: Option<NaiveDateTime>) -> String {
+    date.map(|d| d.format("%d.%m.%Y").to_string())
+        .unwrap_or_default()
+}